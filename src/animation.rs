@@ -0,0 +1,86 @@
+/// Frames of an animation to render, both ends included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl FrameRange {
+    pub fn new(first: usize, last: usize) -> Self {
+        FrameRange {
+            first,
+            last,
+        }
+    }
+
+    /// Reads a single frame ("12") or an inclusive range ("10-20").
+    pub fn from_text(value: &str) -> Option<FrameRange> {
+        let mut parts = value.splitn(2, '-');
+        let first = parts.next()?.trim().parse::<usize>().ok()?;
+        let last = match parts.next() {
+            Some(x) => x.trim().parse::<usize>().ok()?,
+            None => first,
+        };
+
+        if last < first {
+            return None;
+        }
+
+        Some(FrameRange::new(first, last))
+    }
+}
+
+pub fn frame_count(duration: f64, frames_per_second: i32) -> usize {
+    (duration * frames_per_second as f64) as usize
+}
+
+/// Seconds into the animation at which `frame` starts, the shutter opens then.
+pub fn frame_time(frame: usize, frames_per_second: i32) -> f64 {
+    frame as f64 / frames_per_second as f64
+}
+
+/// File name for one frame of an animation. A run of '#' in the path is replaced by the zero padded frame number,
+/// without one the number goes in front of the extension: "render.png" becomes "render.0012.png".
+pub fn frame_path(output_path: &str, frame: usize) -> String {
+    if let Some(start) = output_path.find('#') {
+        let width = output_path[start..].chars().take_while(|x| *x == '#').count();
+        return format!("{}{:0width$}{}", &output_path[..start], frame, &output_path[start + width..], width = width);
+    }
+
+    let path = std::path::Path::new(output_path);
+    let stem = path.file_stem().and_then(|x| x.to_str()).unwrap_or("frame");
+    let file_name = match path.extension().and_then(|x| x.to_str()) {
+        Some(extension) => format!("{}.{:04}.{}", stem, frame, extension),
+        None => format!("{}.{:04}", stem, frame),
+    };
+
+    path.with_file_name(file_name).to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_text_should_read_single_frames_and_ranges() {
+        assert_eq!(FrameRange::from_text("12"), Some(FrameRange::new(12, 12)));
+        assert_eq!(FrameRange::from_text("10-20"), Some(FrameRange::new(10, 20)));
+        assert_eq!(FrameRange::from_text("20-10"), None);
+        assert_eq!(FrameRange::from_text("10-"), None);
+        assert_eq!(FrameRange::from_text("first"), None);
+    }
+
+    #[test]
+    fn frame_path_should_number_frames() {
+        assert_eq!(frame_path("out/render.png", 12), "out/render.0012.png");
+        assert_eq!(frame_path("render", 3), "render.0003");
+        assert_eq!(frame_path("out/shot_###.exr", 7), "out/shot_007.exr");
+        assert_eq!(frame_path("shot_#.png", 1234), "shot_1234.png");
+    }
+
+    #[test]
+    fn frame_count_should_cover_the_duration() {
+        assert_eq!(frame_count(5.0, 30), 150);
+        assert_eq!(frame_time(15, 30), 0.5);
+    }
+}
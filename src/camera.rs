@@ -29,7 +29,7 @@ impl ImagePlane {
         const DISTANCE: f32 = 10.0;
 
         let width = 2.0 * DISTANCE as f64 * (field_of_view / 2.0).tan();
-        let height = width * aspect_ratio as f64;
+        let height = width / aspect_ratio as f64;
 
         let n = glm::normalize(direction * -1.0f32);
 
@@ -47,8 +47,8 @@ impl ImagePlane {
         self.pixel_height = (height / resolution.y as f64) as f32;
     }
 
-    pub fn point_on_plane(&self, x: usize, y: usize) -> glm::Vec3 {
-        self.origin - (self.u * self.pixel_width * (x as f32)) + (self.v * self.pixel_height * (y as f32))
+    pub fn point_on_plane(&self, x: f32, y: f32) -> glm::Vec3 {
+        self.origin - (self.u * self.pixel_width * x) + (self.v * self.pixel_height * y)
     }
 }
 
@@ -101,6 +101,10 @@ impl Camera {
         self.rebuild_image_plane = true;
     }
 
+    pub fn resolution(&self) -> glm::Vector2<u32> {
+        self.resolution
+    }
+
     pub fn update(&mut self) {
         if self.rebuild_image_plane {
             self.rebuild_image_plane = false;
@@ -108,14 +112,13 @@ impl Camera {
         }
     }
 
-    pub fn cast_ray(&self, x: usize, y: usize) -> Ray {
+    /// Casts a ray through a continuous position on the image plane,
+    /// pixel (x, y) covers [x, x + 1) x [y, y + 1) so its center is at (x + 0.5, y + 0.5).
+    pub fn cast_ray_at(&self, x: f32, y: f32) -> Ray {
         if self.rebuild_image_plane {
-            panic!("cast_ray called without calling update!");
+            panic!("cast_ray_at called without calling update!");
         }
 
-        Ray {
-            origin: self.position,
-            direction: glm::normalize(self.image_plane.point_on_plane(x, y) - self.position)
-        }
+        Ray::new(self.position, glm::normalize(self.image_plane.point_on_plane(x, y) - self.position))
     }
 }
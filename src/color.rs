@@ -1,3 +1,5 @@
+pub mod spectrum;
+
 #[derive(Clone)]
 pub struct Color {
     pub r: u8,
@@ -20,4 +22,8 @@ impl Color {
             b: (vec.z * 255.0) as u8
         }
     }
-}
\ No newline at end of file
+}
+/// Relative luminance of a linear Rec. 709 color.
+pub fn luminance(color: &glm::Vec3) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
@@ -0,0 +1,224 @@
+/// Shortest and longest wavelength (in nm) a spectral path can carry.
+pub const MIN_WAVELENGTH: f32 = 360.0;
+pub const MAX_WAVELENGTH: f32 = 830.0;
+
+/// Wavelengths traced together by one path, one per component of a `glm::Vec3`.
+const WAVELENGTH_COUNT: usize = 3;
+
+// Integrals of the matching functions below over [MIN_WAVELENGTH, MAX_WAVELENGTH]
+const CIE_X_INTEGRAL: f32 = 106.765_82;
+const CIE_Y_INTEGRAL: f32 = 106.922_07;
+const CIE_Z_INTEGRAL: f32 = 106.875_01;
+
+/// The wavelengths a spectral path carries and the probability densities they were sampled with.
+/// The first one is the hero wavelength, it is the one that survives when the others can't follow the path.
+/// A spectral path's "color" is a `glm::Vec3` holding its radiance at each of the three wavelengths,
+/// so the integrators can keep multiplying colors component by component.
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    wavelengths: [f32; WAVELENGTH_COUNT],
+    pdfs: [f32; WAVELENGTH_COUNT],
+}
+
+impl SampledWavelengths {
+    /// Picks the hero wavelength with `u` in [0, 1) and spaces the others evenly in sample space.
+    /// Wavelengths the eye is most sensitive to are picked more often.
+    pub fn sample_visible(u: f32) -> Self {
+        let mut wavelengths = [0.0; WAVELENGTH_COUNT];
+        let mut pdfs = [0.0; WAVELENGTH_COUNT];
+        for i in 0..WAVELENGTH_COUNT {
+            let shifted = (u + i as f32 / WAVELENGTH_COUNT as f32).fract();
+            wavelengths[i] = sample_visible_wavelength(shifted);
+            pdfs[i] = visible_wavelength_pdf(wavelengths[i]);
+        }
+
+        SampledWavelengths { wavelengths, pdfs }
+    }
+
+    pub fn hero(&self) -> f32 {
+        self.wavelengths[0]
+    }
+
+    /// Drops every wavelength but the hero, for when they would need to go different ways, like through a prism.
+    pub fn terminate_secondary(&mut self) {
+        if self.is_secondary_terminated() {
+            return;
+        }
+
+        for pdf in self.pdfs.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        // The hero now stands in for all of them
+        self.pdfs[0] /= WAVELENGTH_COUNT as f32;
+    }
+
+    pub fn is_secondary_terminated(&self) -> bool {
+        self.pdfs.iter().skip(1).all(|pdf| *pdf == 0.0)
+    }
+
+    /// An RGB color, reflectance or coefficient as its spectrum evaluated at the sampled wavelengths.
+    pub fn spectrum_from_rgb(&self, rgb: &glm::Vec3) -> glm::Vec3 {
+        glm::vec3(
+            rgb_to_spectrum(rgb, self.wavelengths[0]),
+            rgb_to_spectrum(rgb, self.wavelengths[1]),
+            rgb_to_spectrum(rgb, self.wavelengths[2]))
+    }
+
+    /// Converts the radiance carried at the sampled wavelengths back to a linear Rec. 709 color.
+    /// An equal energy spectrum maps to white.
+    pub fn to_rgb(self, radiance: &glm::Vec3) -> glm::Vec3 {
+        let values = [radiance.x, radiance.y, radiance.z];
+        let mut xyz = glm::vec3(0.0, 0.0, 0.0);
+        for ((wavelength, pdf), value) in self.wavelengths.iter().zip(self.pdfs.iter()).zip(values.iter()) {
+            if *pdf > 0.0 {
+                xyz = xyz + cie_xyz(*wavelength) * (value / pdf);
+            }
+        }
+        xyz = xyz / (WAVELENGTH_COUNT as f32 * CIE_Y_INTEGRAL);
+
+        let white = xyz_to_rgb(&glm::vec3(CIE_X_INTEGRAL / CIE_Y_INTEGRAL, 1.0, CIE_Z_INTEGRAL / CIE_Y_INTEGRAL));
+        xyz_to_rgb(&xyz) / white
+    }
+}
+
+fn sample_visible_wavelength(u: f32) -> f32 {
+    538.0 - 138.888_89 * (0.856_910_6 - 1.827_502 * u).atanh()
+}
+
+/// Density proportional to the eye's sensitivity, normalized over [MIN_WAVELENGTH, MAX_WAVELENGTH].
+fn visible_wavelength_pdf(wavelength: f32) -> f32 {
+    if !(MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(&wavelength) {
+        return 0.0;
+    }
+
+    let c = (0.0072 * (wavelength - 538.0)).cosh();
+    0.003_939_804 / (c * c)
+}
+
+/// The CIE 1931 standard observer, as the multi-lobe Gaussian fit by Wyman, Sloan and Shirley.
+pub fn cie_xyz(wavelength: f32) -> glm::Vec3 {
+    let lobe = |mean: f32, sigma_below: f32, sigma_above: f32| {
+        let sigma = if wavelength < mean { sigma_below } else { sigma_above };
+        let t = (wavelength - mean) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    glm::vec3(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7) - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8))
+}
+
+/// CIE XYZ to linear Rec. 709 (sRGB primaries, D65 white).
+pub fn xyz_to_rgb(xyz: &glm::Vec3) -> glm::Vec3 {
+    glm::vec3(
+        3.240_454 * xyz.x - 1.537_138 * xyz.y - 0.498_531 * xyz.z,
+        -0.969_266 * xyz.x + 1.876_011 * xyz.y + 0.041_556 * xyz.z,
+        0.055_643 * xyz.x - 0.204_026 * xyz.y + 1.057_225 * xyz.z)
+}
+
+// Smits' basis spectra in ten bins from 380 to 720 nm
+const SMITS_WHITE: [f32; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f32; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f32; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f32; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f32; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f32; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Smits' RGB to spectrum conversion evaluated at one wavelength: white plus the secondary and primary color
+/// that make up the rest, so smooth colors get smooth spectra. Linear in `rgb`, so it works for emission too.
+pub fn rgb_to_spectrum(rgb: &glm::Vec3, wavelength: f32) -> f32 {
+    let bin = (((wavelength - 380.0) / 34.0).max(0.0) as usize).min(9);
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+
+    if r <= g && r <= b {
+        let rest = if g <= b {
+            (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+        } else {
+            (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+        };
+        r * SMITS_WHITE[bin] + rest
+    } else if g <= r && g <= b {
+        let rest = if r <= b {
+            (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+        } else {
+            (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+        };
+        g * SMITS_WHITE[bin] + rest
+    } else {
+        let rest = if r <= g {
+            (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+        } else {
+            (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+        };
+        b * SMITS_WHITE[bin] + rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Averages the spectral estimate of a color over many stratified wavelength samples
+    fn round_trip(rgb: glm::Vec3) -> glm::Vec3 {
+        let samples = 4096;
+        let mut sum = glm::vec3(0.0, 0.0, 0.0);
+        for i in 0..samples {
+            let wavelengths = SampledWavelengths::sample_visible((i as f32 + 0.5) / samples as f32);
+            sum = sum + wavelengths.to_rgb(&wavelengths.spectrum_from_rgb(&rgb));
+        }
+
+        sum / samples as f32
+    }
+
+    #[test]
+    fn cie_integrals_should_match_the_matching_functions() {
+        let steps = 4700;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f32;
+        let mut integral = glm::vec3(0.0, 0.0, 0.0);
+        for i in 0..steps {
+            integral = integral + cie_xyz(MIN_WAVELENGTH + (i as f32 + 0.5) * step) * step;
+        }
+
+        assert!((integral.x - CIE_X_INTEGRAL).abs() < 0.01);
+        assert!((integral.y - CIE_Y_INTEGRAL).abs() < 0.01);
+        assert!((integral.z - CIE_Z_INTEGRAL).abs() < 0.01);
+    }
+
+    #[test]
+    fn visible_wavelengths_should_cover_the_range() {
+        assert!((sample_visible_wavelength(0.0) - MIN_WAVELENGTH).abs() < 1.0);
+        assert!((sample_visible_wavelength(0.999_999) - MAX_WAVELENGTH).abs() < 1.0);
+        assert_eq!(visible_wavelength_pdf(300.0), 0.0);
+    }
+
+    #[test]
+    fn colors_should_survive_the_round_trip() {
+        for rgb in &[glm::vec3(1.0, 1.0, 1.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.8, 0.5, 0.2)] {
+            let result = round_trip(*rgb);
+            assert!((result.x - rgb.x).abs() < 0.02 && (result.y - rgb.y).abs() < 0.02 && (result.z - rgb.z).abs() < 0.02,
+                    "{:?} became {:?}", rgb, result);
+        }
+    }
+
+    #[test]
+    fn terminating_secondary_wavelengths_should_keep_the_estimate() {
+        let mut wavelengths = SampledWavelengths::sample_visible(0.3);
+        let flat = glm::vec3(1.0, 1.0, 1.0);
+        let hero_only = {
+            wavelengths.terminate_secondary();
+            wavelengths.to_rgb(&flat)
+        };
+
+        // The hero alone is now weighted as much as all three together were
+        let hero = wavelengths.hero();
+        let expected = cie_xyz(hero) / (visible_wavelength_pdf(hero) * CIE_Y_INTEGRAL);
+        let white = xyz_to_rgb(&glm::vec3(CIE_X_INTEGRAL / CIE_Y_INTEGRAL, 1.0, CIE_Z_INTEGRAL / CIE_Y_INTEGRAL));
+        let expected = xyz_to_rgb(&expected) / white;
+        assert!((hero_only.x - expected.x).abs() < 1e-4);
+        assert!((hero_only.y - expected.y).abs() < 1e-4);
+        assert!((hero_only.z - expected.z).abs() < 1e-4);
+        assert!(wavelengths.is_secondary_terminated());
+    }
+}
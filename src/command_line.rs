@@ -0,0 +1,429 @@
+use crate::integrator::IntegratorType;
+use crate::renderer::tiles::TileOrder;
+use crate::renderer::filter::FilterType;
+use crate::renderer::sampler::SamplerType;
+use crate::renderer::aov::{AovType, parse_aov_list};
+use crate::renderer::display::ToneMapping;
+use crate::animation::FrameRange;
+
+pub struct CommandLineOptions {
+    pub scene_path: String,
+    pub output_path: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub samples: Option<u32>,
+    pub threads: Option<u32>,
+    pub tile_size: Option<u32>,
+    pub tile_order: Option<TileOrder>,
+    pub filter: Option<FilterType>,
+    pub filter_radius: Option<f32>,
+    pub max_depth: Option<u32>,
+    pub adaptive_threshold: Option<f32>,
+    pub max_samples: Option<u32>,
+    pub heatmap_path: Option<String>,
+    pub aovs: Option<Vec<AovType>>,
+    pub denoise: bool,
+    pub exposure: Option<f32>,
+    pub tone_mapping: Option<ToneMapping>,
+    pub integrator: IntegratorType,
+    pub animate: bool,
+    pub frames: Option<FrameRange>,
+    pub skip_existing: bool,
+    pub checkpoint_path: Option<String>,
+    /// Leave the checkpoint on disk after the image is saved, so the render can be continued with more samples.
+    pub keep_checkpoint: bool,
+    pub seed: Option<u64>,
+    pub sampler: Option<SamplerType>,
+    pub spectral: bool,
+    /// Seconds between checkpoint saves.
+    pub checkpoint_interval: f32,
+}
+
+impl CommandLineOptions {
+    pub fn new() -> Self {
+        CommandLineOptions {
+            scene_path: "assets/scene.json".to_string(),
+            output_path: None,
+            width: 512,
+            height: 512,
+            samples: None,
+            threads: None,
+            tile_size: None,
+            tile_order: None,
+            filter: None,
+            filter_radius: None,
+            max_depth: None,
+            adaptive_threshold: None,
+            max_samples: None,
+            heatmap_path: None,
+            aovs: None,
+            denoise: false,
+            exposure: None,
+            tone_mapping: None,
+            integrator: IntegratorType::Whitted,
+            animate: false,
+            frames: None,
+            skip_existing: false,
+            checkpoint_path: None,
+            keep_checkpoint: false,
+            seed: None,
+            sampler: None,
+            spectral: false,
+            checkpoint_interval: 60.0,
+        }
+    }
+
+    // Without an output path we open the interactive viewer, otherwise we render straight to file.
+    pub fn is_headless(&self) -> bool {
+        self.output_path.is_some()
+    }
+
+    // A frame range implies an animation, without one the whole timeline is rendered.
+    pub fn is_animation(&self) -> bool {
+        self.animate || self.frames.is_some()
+    }
+}
+
+pub fn usage() -> &'static str {
+    r#"Usage: rust-rt [options]
+
+Without --output an interactive window is opened. With --output the scene is rendered
+without a window and written to the given path, the format is picked from the extension.
+
+Options:
+  --scene <path>          Scene description (default: assets/scene.json)
+  --output <path>         Render to this file instead of opening a window (.png, .jpg, .exr, .hdr, .pfm)
+  --width <pixels>        Width of the output image (default: 512)
+  --height <pixels>       Height of the output image (default: 512)
+  --integrator <name>     whitted, path, ao, normal or depth (default: whitted)
+  --animate               Render every frame of the keyframe timeline to numbered files
+  --frames <first-last>   Only render these frames of the animation, e.g. 0-29 or 12
+  --skip-existing         Don't render frames whose output file already exists
+  --checkpoint <path>     Save the progress to this file and resume from it after a crash
+  --checkpoint-interval <seconds>
+                          Time between checkpoint saves (default: 60)
+  --keep-checkpoint       Keep the checkpoint once the image is saved, to add samples later
+  --samples <count>       Samples per pixel
+  --seed <number>         Seed of the random streams, the same seed gives the same image (default: 0)
+  --sampler <name>        independent, stratified, halton or sobol (default: sobol)
+  --spectral              Trace wavelengths instead of RGB, dispersive materials split light into colors
+  --threads <count>       Worker threads (default: number of cores)
+  --tile-size <pixels>    Width and height of the tiles handed to the workers
+  --tile-order <order>    scanline, spiral or hilbert
+  --filter <name>         Pixel filter: box, tent, gaussian or mitchell (default: box)
+  --filter-radius <px>    Filter radius in pixels (default depends on the filter)
+  --max-depth <bounces>   Longest path the integrators follow (default: 8)
+  --adaptive <threshold>  Stop sampling pixels once their relative error drops below the threshold
+  --max-samples <count>   Sample budget per pixel when sampling adaptively (default: 256)
+  --heatmap <path>        Also write the number of samples each pixel received as a heatmap
+  --denoise               Denoise the image, in the viewer D toggles it
+  --exposure <stops>      Brighten (positive) or darken (negative) the image, 1 stop doubles it
+  --tone-mapping <name>   clamp, reinhard or aces (default: clamp)
+  --aovs <passes>         Comma separated extra passes, each written next to the output as
+                          <name>.<pass>.<ext>: depth, normal, albedo, entity_id, material_id,
+                          direct and indirect. An .exr output holds them as layers instead
+
+With --adaptive, --samples is the minimum every pixel gets.
+A checkpoint is removed once the image is saved unless --keep-checkpoint is given, it is only resumed with
+the same scene and settings. A kept checkpoint continues with more --samples, the samples it has are kept.
+Animation frames and their checkpoints are numbered in front of the extension (render.0012.png), or in place of a run of '#' (shot_###.png).
+.exr, .hdr and .pfm files store linear radiance, exposure and tone mapping only apply to 8 bit formats.
+In the viewer +/- change the exposure and T cycles through the tone mapping operators.
+Samples, threads, seed, sampler, spectral, depth, the tile, filter, adaptive, pass and display options override the 'render' section of the scene file."#
+}
+
+fn parse_integrator(value: Option<String>) -> Result<IntegratorType, String> {
+    let value = value.ok_or("Missing value for '--integrator'")?;
+
+    IntegratorType::from_name(&value)
+        .ok_or(format!("Unknown integrator '{}', expected one of whitted, path, ao, normal or depth", value))
+}
+
+fn parse_tile_order(value: Option<String>) -> Result<TileOrder, String> {
+    let value = value.ok_or("Missing value for '--tile-order'")?;
+
+    TileOrder::from_name(&value)
+        .ok_or(format!("Unknown tile order '{}', expected one of scanline, spiral or hilbert", value))
+}
+
+fn parse_filter(value: Option<String>) -> Result<FilterType, String> {
+    let value = value.ok_or("Missing value for '--filter'")?;
+
+    FilterType::from_name(&value)
+        .ok_or(format!("Unknown filter '{}', expected one of box, tent, gaussian or mitchell", value))
+}
+
+fn parse_sampler(value: Option<String>) -> Result<SamplerType, String> {
+    let value = value.ok_or("Missing value for '--sampler'")?;
+
+    SamplerType::from_name(&value)
+        .ok_or(format!("Unknown sampler '{}', expected one of independent, stratified, halton or sobol", value))
+}
+
+fn parse_positive_float(name: &str, value: Option<String>) -> Result<f32, String> {
+    let value = value.ok_or(format!("Missing value for '{}'", name))?;
+
+    match value.parse::<f32>() {
+        Ok(x) if x > 0.0 && x.is_finite() => Ok(x),
+        _ => Err(format!("'{}' must be a positive number, got '{}'", name, value)),
+    }
+}
+
+fn parse_aovs(value: Option<String>) -> Result<Vec<AovType>, String> {
+    let value = value.ok_or("Missing value for '--aovs'")?;
+
+    parse_aov_list(&value)
+        .ok_or(format!("Unknown pass in '{}', expected depth, normal, albedo, entity_id, material_id, direct or indirect", value))
+}
+
+fn parse_tone_mapping(value: Option<String>) -> Result<ToneMapping, String> {
+    let value = value.ok_or("Missing value for '--tone-mapping'")?;
+
+    ToneMapping::from_name(&value)
+        .ok_or(format!("Unknown tone mapping '{}', expected one of clamp, reinhard or aces", value))
+}
+
+fn parse_frames(value: Option<String>) -> Result<FrameRange, String> {
+    let value = value.ok_or("Missing value for '--frames'")?;
+
+    FrameRange::from_text(&value)
+        .ok_or(format!("'--frames' must be a frame number or a range like 10-20, got '{}'", value))
+}
+
+fn parse_float(name: &str, value: Option<String>) -> Result<f32, String> {
+    let value = value.ok_or(format!("Missing value for '{}'", name))?;
+
+    match value.parse::<f32>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(format!("'{}' must be a number, got '{}'", name, value)),
+    }
+}
+
+fn parse_seed(value: Option<String>) -> Result<u64, String> {
+    let value = value.ok_or("Missing value for '--seed'")?;
+
+    value.parse::<u64>()
+        .map_err(|_| format!("'--seed' must be a whole number, got '{}'", value))
+}
+
+fn parse_number(name: &str, value: Option<String>) -> Result<u32, String> {
+    let value = value.ok_or(format!("Missing value for '{}'", name))?;
+
+    match value.parse::<u32>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(format!("'{}' must be a positive whole number, got '{}'", name, value)),
+    }
+}
+
+pub fn parse_arguments<I>(args: I) -> Result<CommandLineOptions, String>
+    where
+        I: IntoIterator<Item=String>
+{
+    let mut options = CommandLineOptions::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => options.scene_path = args.next().ok_or("Missing value for '--scene'")?,
+            "--output" => options.output_path = Some(args.next().ok_or("Missing value for '--output'")?),
+            "--width" => options.width = parse_number(&arg, args.next())?,
+            "--height" => options.height = parse_number(&arg, args.next())?,
+            "--samples" => options.samples = Some(parse_number(&arg, args.next())?),
+            "--seed" => options.seed = Some(parse_seed(args.next())?),
+            "--sampler" => options.sampler = Some(parse_sampler(args.next())?),
+            "--spectral" => options.spectral = true,
+            "--threads" => options.threads = Some(parse_number(&arg, args.next())?),
+            "--tile-size" => options.tile_size = Some(parse_number(&arg, args.next())?),
+            "--tile-order" => options.tile_order = Some(parse_tile_order(args.next())?),
+            "--filter" => options.filter = Some(parse_filter(args.next())?),
+            "--filter-radius" => options.filter_radius = Some(parse_positive_float(&arg, args.next())?),
+            "--max-depth" => options.max_depth = Some(parse_number(&arg, args.next())?),
+            "--adaptive" => options.adaptive_threshold = Some(parse_positive_float(&arg, args.next())?),
+            "--max-samples" => options.max_samples = Some(parse_number(&arg, args.next())?),
+            "--heatmap" => options.heatmap_path = Some(args.next().ok_or("Missing value for '--heatmap'")?),
+            "--denoise" => options.denoise = true,
+            "--exposure" => options.exposure = Some(parse_float(&arg, args.next())?),
+            "--tone-mapping" => options.tone_mapping = Some(parse_tone_mapping(args.next())?),
+            "--aovs" => options.aovs = Some(parse_aovs(args.next())?),
+            "--integrator" => options.integrator = parse_integrator(args.next())?,
+            "--animate" => options.animate = true,
+            "--frames" => options.frames = Some(parse_frames(args.next())?),
+            "--skip-existing" => options.skip_existing = true,
+            "--checkpoint" => options.checkpoint_path = Some(args.next().ok_or("Missing value for '--checkpoint'")?),
+            "--checkpoint-interval" => options.checkpoint_interval = parse_positive_float(&arg, args.next())?,
+            "--keep-checkpoint" => options.keep_checkpoint = true,
+            _ => return Err(format!("Unknown argument '{}'", arg)),
+        }
+    }
+
+    // The viewer lets the path tracer be picked later, a file is rendered with the integrator given here
+    if options.spectral && options.is_headless() && options.integrator != IntegratorType::PathTracing {
+        return Err("'--spectral' needs '--integrator path', the other integrators render RGB".to_string());
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_arguments_should_use_defaults_without_arguments() {
+        let options = parse_arguments(Vec::new()).unwrap();
+
+        assert_eq!(options.scene_path, "assets/scene.json");
+        assert!(!options.is_headless());
+        assert!(options.samples.is_none());
+        assert!(options.threads.is_none());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_headless_options() {
+        let options = parse_arguments(to_args(&[
+            "--scene", "scene.json", "--output", "out.png", "--width", "640", "--height", "480", "--samples", "64"
+        ])).unwrap();
+
+        assert_eq!(options.scene_path, "scene.json");
+        assert_eq!(options.output_path, Some("out.png".to_string()));
+        assert_eq!(options.width, 640);
+        assert_eq!(options.height, 480);
+        assert_eq!(options.samples, Some(64));
+        assert!(options.is_headless());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_thread_count() {
+        let options = parse_arguments(to_args(&["--threads", "32"])).unwrap();
+
+        assert_eq!(options.threads, Some(32));
+    }
+
+    #[test]
+    fn parse_arguments_should_read_seed() {
+        assert_eq!(parse_arguments(to_args(&["--seed", "0"])).unwrap().seed, Some(0));
+        assert_eq!(parse_arguments(to_args(&["--seed", "18446744073709551615"])).unwrap().seed, Some(u64::MAX));
+        assert!(parse_arguments(to_args(&["--seed", "-1"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_sampling_options() {
+        assert_eq!(parse_arguments(to_args(&["--sampler", "halton"])).unwrap().sampler, Some(SamplerType::Halton));
+        assert!(parse_arguments(to_args(&["--sampler", "random"])).is_err());
+        assert!(parse_arguments(to_args(&["--spectral"])).unwrap().spectral);
+        assert!(parse_arguments(to_args(&["--spectral", "--output", "out.exr", "--integrator", "path"])).unwrap().spectral);
+        assert!(parse_arguments(to_args(&["--spectral", "--output", "out.exr"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_max_depth() {
+        let options = parse_arguments(to_args(&["--max-depth", "12"])).unwrap();
+
+        assert_eq!(options.max_depth, Some(12));
+        assert!(parse_arguments(to_args(&["--max-depth", "0"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_tile_options() {
+        let options = parse_arguments(to_args(&["--tile-size", "64", "--tile-order", "spiral"])).unwrap();
+
+        assert_eq!(options.tile_size, Some(64));
+        assert_eq!(options.tile_order, Some(TileOrder::Spiral));
+        assert!(parse_arguments(to_args(&["--tile-order", "random"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_filter_options() {
+        let options = parse_arguments(to_args(&["--filter", "mitchell", "--filter-radius", "1.5"])).unwrap();
+
+        assert_eq!(options.filter, Some(FilterType::Mitchell));
+        assert_eq!(options.filter_radius, Some(1.5));
+        assert!(parse_arguments(to_args(&["--filter", "lanczos"])).is_err());
+        assert!(parse_arguments(to_args(&["--filter-radius", "0"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_adaptive_options() {
+        let options = parse_arguments(to_args(&[
+            "--adaptive", "0.05", "--max-samples", "512", "--heatmap", "samples.png"
+        ])).unwrap();
+
+        assert_eq!(options.adaptive_threshold, Some(0.05));
+        assert_eq!(options.max_samples, Some(512));
+        assert_eq!(options.heatmap_path, Some("samples.png".to_string()));
+        assert!(parse_arguments(to_args(&["--adaptive", "-1"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_aovs() {
+        let options = parse_arguments(to_args(&["--aovs", "depth,albedo,indirect"])).unwrap();
+
+        assert_eq!(options.aovs, Some(vec![AovType::Depth, AovType::Albedo, AovType::Indirect]));
+        assert!(parse_arguments(to_args(&["--aovs", "depth,velocity"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_denoise_flag() {
+        assert!(!parse_arguments(Vec::new()).unwrap().denoise);
+        assert!(parse_arguments(to_args(&["--denoise"])).unwrap().denoise);
+    }
+
+    #[test]
+    fn parse_arguments_should_read_display_options() {
+        let options = parse_arguments(to_args(&["--exposure", "-1.5", "--tone-mapping", "aces"])).unwrap();
+
+        assert_eq!(options.exposure, Some(-1.5));
+        assert_eq!(options.tone_mapping, Some(ToneMapping::Aces));
+        assert!(parse_arguments(to_args(&["--tone-mapping", "filmic"])).is_err());
+        assert!(parse_arguments(to_args(&["--exposure", "bright"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_integrator() {
+        assert_eq!(parse_arguments(Vec::new()).unwrap().integrator, IntegratorType::Whitted);
+        assert_eq!(parse_arguments(to_args(&["--integrator", "path"])).unwrap().integrator, IntegratorType::PathTracing);
+        assert_eq!(parse_arguments(to_args(&["--integrator", "ao"])).unwrap().integrator, IntegratorType::AmbientOcclusion);
+        assert!(parse_arguments(to_args(&["--integrator", "photon"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_animation_options() {
+        assert!(!parse_arguments(Vec::new()).unwrap().is_animation());
+        assert!(parse_arguments(to_args(&["--animate"])).unwrap().is_animation());
+
+        let options = parse_arguments(to_args(&["--frames", "10-20", "--skip-existing"])).unwrap();
+        assert_eq!(options.frames, Some(FrameRange::new(10, 20)));
+        assert!(options.skip_existing);
+        assert!(options.is_animation());
+        assert!(parse_arguments(to_args(&["--frames", "20-10"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_read_checkpoint_options() {
+        assert_eq!(parse_arguments(Vec::new()).unwrap().checkpoint_interval, 60.0);
+
+        let options = parse_arguments(to_args(&["--checkpoint", "render.checkpoint", "--checkpoint-interval", "300"])).unwrap();
+        assert_eq!(options.checkpoint_path, Some("render.checkpoint".to_string()));
+        assert_eq!(options.checkpoint_interval, 300.0);
+        assert!(!options.keep_checkpoint);
+        assert!(parse_arguments(to_args(&["--keep-checkpoint"])).unwrap().keep_checkpoint);
+        assert!(parse_arguments(to_args(&["--checkpoint-interval", "0"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_reject_invalid_numbers() {
+        assert!(parse_arguments(to_args(&["--samples", "0"])).is_err());
+        assert!(parse_arguments(to_args(&["--width", "wide"])).is_err());
+        assert!(parse_arguments(to_args(&["--height"])).is_err());
+        assert!(parse_arguments(to_args(&["--threads", "-2"])).is_err());
+    }
+
+    #[test]
+    fn parse_arguments_should_reject_unknown_arguments() {
+        assert!(parse_arguments(to_args(&["--fullscreen"])).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::core::geom::AABB;
+use crate::core::Ray;
+
+/// Density values on a regular grid of voxels stretched over a box in world space, zero outside the box.
+/// Values are interpolated trilinearly between voxel centers.
+///
+/// Grid files are plain text: the voxel counts along x, y and z, followed by one number per voxel with x
+/// changing fastest and z slowest, all separated by whitespace. Lines starting with '#' are comments.
+pub struct DensityGrid {
+    width: usize,
+    height: usize,
+    depth: usize,
+    values: Vec<f32>,
+    max_value: f32,
+    bounds: AABB,
+}
+
+impl DensityGrid {
+    pub fn new(width: usize, height: usize, depth: usize, values: Vec<f32>, bounds: AABB) -> Result<Self, String> {
+        if width == 0 || height == 0 || depth == 0 {
+            return Err("A density grid needs at least one voxel along every axis".to_string());
+        }
+
+        if values.len() != width * height * depth {
+            return Err(format!("A {}x{}x{} density grid needs {} values, got {}", width, height, depth, width * height * depth, values.len()));
+        }
+
+        if values.iter().any(|x| !x.is_finite() || *x < 0.0) {
+            return Err("Densities must be finite and not negative".to_string());
+        }
+
+        let max_value = values.iter().cloned().fold(0.0, f32::max);
+
+        Ok(DensityGrid { width, height, depth, values, max_value, bounds })
+    }
+
+    pub fn from_text(text: &str, bounds: AABB) -> Result<Self, String> {
+        let mut numbers = text.lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split_whitespace());
+
+        let mut dimension = |name: &str| -> Result<usize, String> {
+            let value = numbers.next().ok_or(format!("Missing the {} of the density grid", name))?;
+            value.parse().map_err(|_| format!("The {} of the density grid must be a whole number, got '{}'", name, value))
+        };
+        let width = dimension("width")?;
+        let height = dimension("height")?;
+        let depth = dimension("depth")?;
+
+        let values = numbers
+            .map(|x| x.parse::<f32>().map_err(|_| format!("Invalid density '{}'", x)))
+            .collect::<Result<Vec<f32>, String>>()?;
+
+        DensityGrid::new(width, height, depth, values, bounds)
+    }
+
+    pub fn load(path: &str, bounds: AABB) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read density grid {}: {}", path, e))?;
+
+        DensityGrid::from_text(&text, bounds)
+    }
+
+    /// The highest density anywhere in the grid.
+    pub fn max_value(&self) -> f32 {
+        self.max_value
+    }
+
+    pub fn density(&self, point: &glm::Vec3) -> f32 {
+        if !self.bounds.contains(point) {
+            return 0.0;
+        }
+
+        // Position in voxel units, voxel centers sit at whole numbers
+        let size = self.bounds.size();
+        let local = glm::vec3(
+            (point.x - self.bounds.min.x) / size.x * self.width as f32 - 0.5,
+            (point.y - self.bounds.min.y) / size.y * self.height as f32 - 0.5,
+            (point.z - self.bounds.min.z) / size.z * self.depth as f32 - 0.5);
+
+        let axis = |position: f32, count: usize| {
+            let floor = position.floor();
+            let low = (floor.max(0.0) as usize).min(count - 1);
+            let high = ((floor + 1.0).max(0.0) as usize).min(count - 1);
+            (low, high, position - floor)
+        };
+        let (x0, x1, fx) = axis(local.x, self.width);
+        let (y0, y1, fy) = axis(local.y, self.height);
+        let (z0, z1, fz) = axis(local.z, self.depth);
+
+        let value = |x: usize, y: usize, z: usize| self.values[(z * self.height + y) * self.width + x];
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        lerp(
+            lerp(lerp(value(x0, y0, z0), value(x1, y0, z0), fx), lerp(value(x0, y1, z0), value(x1, y1, z0), fx), fy),
+            lerp(lerp(value(x0, y0, z1), value(x1, y0, z1), fx), lerp(value(x0, y1, z1), value(x1, y1, z1), fx), fy),
+            fz)
+    }
+
+    /// The part of the ray between its origin and `max_distance` that runs through the grid's box.
+    pub fn overlap(&self, ray: &Ray, max_distance: f32) -> Option<(f32, f32)> {
+        let mut near: f32 = 0.0;
+        let mut far = max_distance;
+
+        for dimension in 0..3 {
+            let inverse = 1.0 / ray.direction[dimension];
+            let mut t1 = (self.bounds.min[dimension] - ray.origin[dimension]) * inverse;
+            let mut t2 = (self.bounds.max[dimension] - ray.origin[dimension]) * inverse;
+            if t1 > t2 {
+                std::mem::swap(&mut t1, &mut t2);
+            }
+
+            // NaN from a zero direction inside the slab leaves the interval as it is
+            near = if t1 > near { t1 } else { near };
+            far = if t2 < far { t2 } else { far };
+            if near > far {
+                return None;
+            }
+        }
+
+        Some((near, far))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> AABB {
+        AABB { min: glm::vec3(0.0, 0.0, 0.0), max: glm::vec3(1.0, 1.0, 1.0) }
+    }
+
+    #[test]
+    fn from_text_should_read_dimensions_and_values() {
+        let grid = DensityGrid::from_text("# two voxels\n2 1 1\n0.0 4.0\n", unit_box()).unwrap();
+
+        assert_eq!(grid.max_value(), 4.0);
+        // Voxel centers at x = 0.25 and 0.75, halfway in between is the average
+        assert_eq!(grid.density(&glm::vec3(0.5, 0.5, 0.5)), 2.0);
+        assert_eq!(grid.density(&glm::vec3(0.1, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density(&glm::vec3(0.9, 0.5, 0.5)), 4.0);
+        assert_eq!(grid.density(&glm::vec3(1.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn from_text_should_reject_wrong_value_counts() {
+        assert!(DensityGrid::from_text("2 2 2\n1 2 3", unit_box()).is_err());
+        assert!(DensityGrid::from_text("1 1 1\n-1", unit_box()).is_err());
+        assert!(DensityGrid::from_text("1 1", unit_box()).is_err());
+    }
+
+    #[test]
+    fn overlap_should_clip_the_ray_to_the_box() {
+        let grid = DensityGrid::from_text("1 1 1 1", unit_box()).unwrap();
+        let ray = Ray::new(glm::vec3(-1.0, 0.5, 0.5), glm::vec3(1.0, 0.0, 0.0));
+
+        assert_eq!(grid.overlap(&ray, 10.0), Some((1.0, 2.0)));
+        assert_eq!(grid.overlap(&ray, 1.5), Some((1.0, 1.5)));
+        assert_eq!(grid.overlap(&ray, 0.5), None);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::color::luminance;
+use crate::core::sampling::{Distribution2D, to_world};
+use crate::output::exr;
+use image::codecs::hdr::HdrDecoder;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+
+/// Light arriving from infinitely far away in every direction, from an equirectangular (latitude-longitude)
+/// image. The top of the image is straight up (+y) and its center looks along -z before `rotation`, which
+/// turns the image around the y axis. Directions are picked in proportion to their brightness,
+/// so bright spots like the sun are found without waiting for a path to hit them.
+/// A sun too small for the image to hold can be added as a separate disk, see `with_sun`.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    // Top row first
+    pixels: Vec<glm::Vec3>,
+    rotation: f32,
+    intensity: f32,
+    distribution: Distribution2D,
+    sun: Option<Sun>,
+    // Chance that `sample` picks the sun instead of the image
+    sun_probability: f32,
+}
+
+/// A disk of constant radiance in the sky.
+#[derive(Clone, Copy, Debug)]
+pub struct Sun {
+    pub direction: glm::Vec3,
+    /// Half the angle the disk covers, in radians.
+    pub angular_radius: f32,
+    pub radiance: glm::Vec3,
+}
+
+impl Sun {
+    fn cos_angular_radius(&self) -> f32 {
+        self.angular_radius.cos()
+    }
+
+    fn solid_angle(&self) -> f32 {
+        2.0 * PI * (1.0 - self.cos_angular_radius())
+    }
+
+    fn contains(&self, direction: &glm::Vec3) -> bool {
+        glm::dot(glm::normalize(*direction), self.direction) >= self.cos_angular_radius()
+    }
+}
+
+/// A direction towards the environment, the light coming from it and the solid angle density it was picked with.
+pub struct EnvironmentSample {
+    pub direction: glm::Vec3,
+    pub radiance: glm::Vec3,
+    pub pdf: f32,
+}
+
+impl EnvironmentMap {
+    /// `rotation` is in degrees, `intensity` scales the radiance of every pixel.
+    pub fn new(width: usize, height: usize, pixels: Vec<glm::Vec3>, rotation: f32, intensity: f32) -> Result<Self, String> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err(format!("A {}x{} environment map needs {} pixels, got {}", width, height, width * height, pixels.len()));
+        }
+
+        // Rows near the poles cover less of the sphere than rows at the horizon
+        let mut weights = Vec::with_capacity(pixels.len());
+        for y in 0..height {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            for x in 0..width {
+                weights.push(luminance(&pixels[y * width + x]).max(0.0) * sin_theta);
+            }
+        }
+
+        Ok(EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation: rotation.to_radians(),
+            intensity,
+            distribution: Distribution2D::new(&weights, width),
+            sun: None,
+            sun_probability: 0.0,
+        })
+    }
+
+    /// Adds a sun disk on top of the image, the sun is sampled in proportion to the light it gives.
+    pub fn with_sun(mut self, sun: Sun) -> Self {
+        let sun = Sun { direction: glm::normalize(sun.direction), ..sun };
+        let sun_power = luminance(&sun.radiance).max(0.0) * sun.solid_angle();
+        // The weights of the distribution are luminance times the area of the sphere per unit of image area
+        let image_power = self.distribution.integral() * 2.0 * PI * PI * self.intensity;
+
+        self.sun_probability = if sun_power + image_power > 0.0 { sun_power / (sun_power + image_power) } else { 0.0 };
+        self.sun = Some(sun);
+        self
+    }
+
+    /// Loads a Radiance .hdr or an uncompressed or ZIP compressed OpenEXR image, picked by the file extension.
+    pub fn load(path: &str, rotation: f32, intensity: f32) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Could not open environment map {}: {}", path, e))?;
+        let mut reader = BufReader::new(file);
+        let is_exr = path.to_lowercase().ends_with(".exr");
+
+        let (width, height, pixels) = if is_exr {
+            exr::read(&mut reader)
+        } else {
+            read_hdr(reader)
+        }.map_err(|e| format!("Could not read environment map {}: {}", path, e))?;
+
+        EnvironmentMap::new(width, height, pixels, rotation, intensity)
+    }
+
+    /// The light arriving from `direction`, which points away from the scene.
+    pub fn radiance(&self, direction: &glm::Vec3) -> glm::Vec3 {
+        let point = self.to_image(direction);
+        let radiance = self.pixels[self.pixel_index(point)] * self.intensity;
+
+        match &self.sun {
+            Some(sun) if sun.contains(direction) => radiance + sun.radiance,
+            _ => radiance,
+        }
+    }
+
+    pub fn sample(&self, u: glm::Vec2) -> EnvironmentSample {
+        let direction = match &self.sun {
+            Some(sun) if u.x < self.sun_probability => {
+                // Uniformly within the cone of the disk
+                let u = glm::vec2(u.x / self.sun_probability, u.y);
+                let cos_theta = 1.0 - u.x * (1.0 - sun.cos_angular_radius());
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u.y;
+
+                glm::normalize(to_world(glm::vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta), sun.direction))
+            }
+            _ => {
+                let u = glm::vec2((u.x - self.sun_probability) / (1.0 - self.sun_probability), u.y);
+                let (point, _) = self.distribution.sample(glm::vec2(u.x.min(crate::renderer::sampler::ONE_MINUS_EPSILON), u.y));
+
+                self.to_direction(point)
+            }
+        };
+
+        EnvironmentSample {
+            direction,
+            radiance: self.radiance(&direction),
+            pdf: self.pdf(&direction),
+        }
+    }
+
+    /// The solid angle density `sample` picks `direction` with.
+    pub fn pdf(&self, direction: &glm::Vec3) -> f32 {
+        let point = self.to_image(direction);
+        let sin_theta = (PI * point.y).sin();
+        // Per unit of image area to per unit of solid angle
+        let image_pdf = if sin_theta > 0.0 { self.distribution.pdf(point) / (2.0 * PI * PI * sin_theta) } else { 0.0 };
+
+        match &self.sun {
+            Some(sun) => {
+                let sun_pdf = if sun.contains(direction) { 1.0 / sun.solid_angle() } else { 0.0 };
+                self.sun_probability * sun_pdf + (1.0 - self.sun_probability) * image_pdf
+            }
+            None => image_pdf,
+        }
+    }
+
+    fn pixel_index(&self, point: glm::Vec2) -> usize {
+        let x = ((point.x * self.width as f32) as usize).min(self.width - 1);
+        let y = ((point.y * self.height as f32) as usize).min(self.height - 1);
+
+        y * self.width + x
+    }
+
+    // Position in the image in [0, 1), y down from the top
+    fn to_image(&self, direction: &glm::Vec3) -> glm::Vec2 {
+        let direction = glm::normalize(*direction);
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = direction.x.atan2(-direction.z) - self.rotation;
+
+        glm::vec2((0.5 + phi / (2.0 * PI)).rem_euclid(1.0), theta / PI)
+    }
+
+    fn to_direction(&self, point: glm::Vec2) -> glm::Vec3 {
+        let theta = PI * point.y;
+        let phi = 2.0 * PI * (point.x - 0.5) + self.rotation;
+
+        glm::vec3(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+}
+
+fn read_hdr(reader: BufReader<File>) -> Result<(usize, usize, Vec<glm::Vec3>), String> {
+    let decoder = HdrDecoder::new(reader).map_err(|e| e.to_string())?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr().map_err(|e| e.to_string())?;
+
+    Ok((metadata.width as usize, metadata.height as usize, pixels.iter().map(|x| glm::vec3(x[0], x[1], x[2])).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A dim map with one bright pixel
+    fn spot_map(rotation: f32) -> EnvironmentMap {
+        let mut pixels = vec![glm::vec3(0.1, 0.1, 0.1); 8 * 4];
+        // Second row, sixth column
+        pixels[8 + 5] = glm::vec3(100.0, 50.0, 10.0);
+
+        EnvironmentMap::new(8, 4, pixels, rotation, 2.0).unwrap()
+    }
+
+    #[test]
+    fn directions_should_map_to_the_image_and_back() {
+        let map = spot_map(30.0);
+        for &(x, y) in &[(0.1, 0.2), (0.5, 0.5), (0.93, 0.8)] {
+            let point = glm::vec2(x, y);
+            let result = map.to_image(&map.to_direction(point));
+
+            assert!((result.x - x).abs() < 1e-5 && (result.y - y).abs() < 1e-5, "{:?}", result);
+        }
+
+        // The center of an unrotated map looks along -z, the top is up
+        let map = spot_map(0.0);
+        assert!(glm::length(map.to_direction(glm::vec2(0.5, 0.5)) - glm::vec3(0.0, 0.0, -1.0)) < 1e-5);
+        assert!(map.to_image(&glm::vec3(0.0, 1.0, 0.0)).y < 1e-5);
+    }
+
+    #[test]
+    fn samples_should_favor_bright_pixels_and_match_the_pdf() {
+        let map = spot_map(45.0);
+        let mut bright = 0;
+        for i in 0..16 {
+            for j in 0..16 {
+                let sample = map.sample(glm::vec2((i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0));
+
+                assert!((map.pdf(&sample.direction) - sample.pdf).abs() < 1e-3 * sample.pdf);
+                assert_eq!(sample.radiance, map.radiance(&sample.direction));
+                if sample.radiance.x > 1.0 {
+                    bright += 1;
+                }
+            }
+        }
+
+        assert!(bright > 200);
+    }
+
+    #[test]
+    fn samples_with_a_sun_should_match_the_pdf() {
+        let sun = Sun { direction: glm::vec3(1.0, 1.0, 0.0), angular_radius: 0.05, radiance: glm::vec3(100000.0, 90000.0, 80000.0) };
+        let map = spot_map(0.0).with_sun(sun);
+        let mut in_sun = 0;
+        for i in 0..16 {
+            for j in 0..16 {
+                let sample = map.sample(glm::vec2((i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0));
+
+                assert!((map.pdf(&sample.direction) - sample.pdf).abs() < 1e-3 * sample.pdf);
+                if sample.radiance.x > 1000.0 {
+                    in_sun += 1;
+                }
+            }
+        }
+
+        // The sun gives more light than the rest of the map together
+        assert!(map.sun_probability > 0.5);
+        let sun_columns = (0..16).filter(|i| (*i as f32 + 0.5) / 16.0 < map.sun_probability).count();
+        assert_eq!(in_sun, sun_columns * 16);
+    }
+
+    #[test]
+    fn pdf_should_integrate_to_one_over_the_sphere() {
+        let map = spot_map(0.0);
+        let steps = 200;
+        let mut total = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let point = glm::vec2((i as f32 + 0.5) / steps as f32, (j as f32 + 0.5) / steps as f32);
+                let solid_angle = 2.0 * PI * PI * (PI * point.y).sin() / (steps * steps) as f32;
+                total += map.pdf(&map.to_direction(point)) * solid_angle;
+            }
+        }
+
+        assert!((total - 1.0).abs() < 0.01, "{}", total);
+    }
+}
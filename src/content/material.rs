@@ -1,4 +1,7 @@
 use image::io::Reader as ImageReader;
+use crate::core::optics::Dispersion;
+use crate::content::medium::Medium;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct Texture {
@@ -22,35 +25,69 @@ impl Texture {
     }
 }
 
+#[derive(Clone)]
 pub struct Material {
+    id: u32,
     diffuse_map: Option<Texture>,
     diffuse: glm::Vec3,
     emission: glm::Vec3,
     reflectivity: f32,
     transparent: bool,
     refractive_index: f32,
+    // Beer-Lambert absorption coefficient per unit of distance travelled inside a transparent material
+    absorption: glm::Vec3,
+    // Wavelength dependent refractive index, only used when rendering spectrally
+    dispersion: Option<Dispersion>,
+    // Fills the inside of the entity, see `interior_medium`
+    medium: Option<Arc<Medium>>,
 }
 
 impl Material {
-    pub fn new(diffuse_map: Option<Texture>, diffuse: glm::Vec3, emission: glm::Vec3, reflectivity: f32, transparent: bool, refractive_index: f32) -> Material {
+    pub fn new(id: u32, diffuse_map: Option<Texture>, diffuse: glm::Vec3, emission: glm::Vec3, reflectivity: f32, transparent: bool, refractive_index: f32, absorption: glm::Vec3, dispersion: Option<Dispersion>, medium: Option<Arc<Medium>>) -> Material {
         Material {
+            id,
             diffuse_map,
             diffuse,
             emission,
             reflectivity,
             transparent,
-            refractive_index
+            refractive_index,
+            absorption,
+            dispersion,
+            medium,
         }
     }
 
+    /// Identifies the material in the material id pass, 0 when not set.
+    pub fn id(&self) -> u32 { self.id }
+
     pub fn transparent(&self) -> bool { self.transparent }
 
     pub fn refractive_index(&self) -> f32 { self.refractive_index }
 
+    pub fn dispersion(&self) -> Option<&Dispersion> { self.dispersion.as_ref() }
+
+    /// The refractive index for light of the given wavelength in nm, `refractive_index` when not dispersive.
+    pub fn refractive_index_at(&self, wavelength: f32) -> f32 {
+        match &self.dispersion {
+            Some(dispersion) => dispersion.refractive_index(wavelength),
+            None => self.refractive_index,
+        }
+    }
+
     pub fn reflectivity(&self) -> f32 { self.reflectivity }
 
+    /// The medium inside entities with this material. Unless the material is also transparent the surface itself
+    /// is invisible, rays pass straight through it and it only marks where the medium begins and ends.
+    pub fn interior_medium(&self) -> Option<&Arc<Medium>> { self.medium.as_ref() }
+
+    /// True for surfaces that are only the boundary of a medium, see `interior_medium`.
+    pub fn is_medium_boundary(&self) -> bool { self.medium.is_some() && !self.transparent }
+
     pub fn emission(&self) -> &glm::Vec3 { &self.emission }
 
+    pub fn absorption(&self) -> &glm::Vec3 { &self.absorption }
+
     pub fn sample_diffuse(&self, uv: &glm::Vec2) -> glm::Vector3<f32> {
         // let diffuse_map = self.diffuse_map.as_ref().expect("Cannot sample a Material without diffuse map");
         match &self.diffuse_map {
@@ -1,26 +1,42 @@
 use crate::content::material::{Texture, Material};
+use crate::core::optics::Dispersion;
+use crate::content::medium::Medium;
+use std::sync::Arc;
 
 pub struct MaterialBuilder {
+    id: u32,
     diffuse_map: Option<Texture>,
     diffuse_color: Option<glm::Vec3>,
     emissive_color: Option<glm::Vec3>,
     reflectivity: f32,
     transparency: bool,
     refractive_index : f32,
+    absorption: glm::Vec3,
+    dispersion: Option<Dispersion>,
+    medium: Option<Arc<Medium>>,
 }
 
 impl MaterialBuilder {
     pub fn new() -> Self {
         MaterialBuilder {
+            id: 0,
             diffuse_map: None,
             diffuse_color: None,
             emissive_color: None,
             reflectivity: 0.0,
             transparency: false,
-            refractive_index: 0.0
+            refractive_index: 0.0,
+            absorption: glm::vec3(0.0, 0.0, 0.0),
+            dispersion: None,
+            medium: None,
         }
     }
 
+    pub fn with_id(&mut self, id: u32) -> &mut MaterialBuilder {
+        self.id = id;
+        self
+    }
+
     pub fn with_diffuse_map(&mut self, texture: Texture) -> &mut MaterialBuilder {
         self.diffuse_map = Some(texture);
         self
@@ -47,14 +63,45 @@ impl MaterialBuilder {
         self
     }
 
+    /// Tints light passing through a transparent material, the further it travels inside the darker it gets.
+    /// The color is what remains of white light after travelling one unit of distance.
+    pub fn with_absorption_color(&mut self, color: glm::Vec3) -> &mut MaterialBuilder {
+        let coefficient = |x: f32| -x.max(1e-6).min(1.0).ln();
+        self.absorption = glm::vec3(coefficient(color.x), coefficient(color.y), coefficient(color.z));
+        self
+    }
+
+    /// Makes the material transparent with a refractive index that depends on the wavelength.
+    /// Without an explicit refractive index, renders in RGB use the one at `Dispersion::REFERENCE_WAVELENGTH`.
+    pub fn with_dispersion(&mut self, dispersion: Dispersion) -> &mut MaterialBuilder {
+        self.transparency = true;
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    /// Fills entities with this material with a medium, like smoke in a sphere.
+    pub fn with_medium(&mut self, medium: Medium) -> &mut MaterialBuilder {
+        self.medium = Some(Arc::new(medium));
+        self
+    }
+
     pub fn build(&self) -> Material {
+        let refractive_index = match &self.dispersion {
+            Some(dispersion) if self.refractive_index == 0.0 => dispersion.refractive_index(Dispersion::REFERENCE_WAVELENGTH),
+            _ => self.refractive_index,
+        };
+
         Material::new(
+            self.id,
             self.diffuse_map.clone(),
             self.diffuse_color.unwrap_or(glm::vec3(0.0, 0.0, 0.0)),
         self.emissive_color.unwrap_or(glm::vec3(0.0, 0.0, 0.0)),
         self.reflectivity,
         self.transparency,
-        self.refractive_index)
+        refractive_index,
+        self.absorption,
+        self.dispersion,
+        self.medium.clone())
     }
 }
 
@@ -0,0 +1,268 @@
+use crate::content::density_grid::DensityGrid;
+use crate::core::Ray;
+use crate::core::sampling::to_world;
+use crate::renderer::sampler::Sampler;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// The Henyey-Greenstein phase function, the distribution of directions light scatters into inside a medium.
+/// `asymmetry` is the mean cosine between the old and the new direction: 0 scatters evenly in all directions,
+/// positive values mostly forward (like fog and smoke) and negative values mostly back.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HenyeyGreenstein {
+    asymmetry: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(asymmetry: f32) -> Self {
+        HenyeyGreenstein {
+            asymmetry: asymmetry.clamp(-0.99, 0.99),
+        }
+    }
+
+    /// The density of scattering into a direction at `cos_theta` to the direction of travel, per unit of solid angle.
+    /// Also the pdf `sample` picks that direction with.
+    pub fn evaluate(&self, cos_theta: f32) -> f32 {
+        let g = self.asymmetry;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Picks the direction light travelling along `direction` scatters into, proportional to the phase function.
+    pub fn sample(&self, direction: glm::Vec3, u: glm::Vec2) -> glm::Vec3 {
+        let g = self.asymmetry;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u.x
+        } else {
+            let term = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.x);
+            (1.0 + g * g - term * term) / (2.0 * g)
+        }.clamp(-1.0, 1.0);
+
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y;
+        let local = glm::vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+
+        glm::normalize(to_world(local, direction))
+    }
+}
+
+/// What happened to a ray travelling through a medium, see `Medium::sample_interaction`.
+pub enum MediumEvent {
+    /// The ray scattered `distance` along the ray, the caller continues in a new direction.
+    Scattered { distance: f32, weight: glm::Vec3 },
+    /// The ray made it to `max_distance`.
+    Passed { weight: glm::Vec3 },
+    /// The light was absorbed, nothing comes back along this path.
+    Absorbed,
+}
+
+/// Fog, smoke and other participating media: space that absorbs and scatters light as it passes through.
+/// The coefficients are per unit of distance and get multiplied by the density, which is 1 everywhere
+/// for a homogeneous medium or comes from a density grid.
+#[derive(Clone)]
+pub struct Medium {
+    absorption: glm::Vec3,
+    scattering: glm::Vec3,
+    phase: HenyeyGreenstein,
+    density: Option<Arc<DensityGrid>>,
+}
+
+impl Medium {
+    pub fn new(absorption: glm::Vec3, scattering: glm::Vec3, phase: HenyeyGreenstein, density: Option<Arc<DensityGrid>>) -> Self {
+        Medium {
+            absorption,
+            scattering,
+            phase,
+            density,
+        }
+    }
+
+    pub fn absorption(&self) -> &glm::Vec3 { &self.absorption }
+
+    pub fn scattering(&self) -> &glm::Vec3 { &self.scattering }
+
+    pub fn phase(&self) -> &HenyeyGreenstein { &self.phase }
+
+    /// The same medium with different coefficients, used to trace it at other wavelengths.
+    pub fn with_coefficients(&self, absorption: glm::Vec3, scattering: glm::Vec3) -> Medium {
+        Medium {
+            absorption,
+            scattering,
+            ..self.clone()
+        }
+    }
+
+    fn density(&self, point: &glm::Vec3) -> f32 {
+        match &self.density {
+            Some(grid) => grid.density(point),
+            None => 1.0,
+        }
+    }
+
+    // The part of the ray that can interact, and an upper bound of the extinction along it in all channels
+    fn tracking_range(&self, ray: &Ray, max_distance: f32) -> Option<(f32, f32, f32)> {
+        let extinction = self.absorption + self.scattering;
+        let max_extinction = extinction.x.max(extinction.y).max(extinction.z);
+
+        let (near, far, max_density) = match &self.density {
+            Some(grid) => {
+                let (near, far) = grid.overlap(ray, max_distance)?;
+                (near, far, grid.max_value())
+            }
+            None => (0.0, max_distance, 1.0),
+        };
+
+        let majorant = max_extinction * max_density;
+        if majorant > 0.0 {
+            Some((near, far, majorant))
+        } else {
+            None
+        }
+    }
+
+    /// Delta tracking: walks the ray with tentative collisions spaced by the majorant. At every collision the
+    /// light is absorbed, scattered or the collision turns out to be a null collision, with probabilities that
+    /// follow the average of the channels. `weight` corrects for colored media where channels disagree.
+    pub fn sample_interaction(&self, ray: &Ray, max_distance: f32, sampler: &mut dyn Sampler) -> MediumEvent {
+        let mut weight = glm::vec3(1.0, 1.0, 1.0);
+        let (mut distance, far, majorant) = match self.tracking_range(ray, max_distance) {
+            Some(range) => range,
+            None => return MediumEvent::Passed { weight },
+        };
+        let average = |v: glm::Vec3| (v.x + v.y + v.z) / 3.0;
+
+        loop {
+            distance -= (1.0 - sampler.get_1d()).ln() / majorant;
+            if distance >= far {
+                return MediumEvent::Passed { weight };
+            }
+
+            let density = self.density(&(ray.origin + ray.direction * distance));
+            let absorption = self.absorption * density;
+            let scattering = self.scattering * density;
+            let null = glm::vec3(majorant, majorant, majorant) - absorption - scattering;
+
+            let absorb_probability = average(absorption) / majorant;
+            let scatter_probability = average(scattering) / majorant;
+            let u = sampler.get_1d();
+            if u < absorb_probability {
+                return MediumEvent::Absorbed;
+            }
+
+            if u < absorb_probability + scatter_probability {
+                weight = weight * scattering / (majorant * scatter_probability);
+                return MediumEvent::Scattered { distance, weight };
+            }
+
+            weight = weight * null / (majorant * (1.0 - absorb_probability - scatter_probability));
+        }
+    }
+
+    /// The fraction of light that makes it through the medium from the ray origin to `max_distance`.
+    /// Exact for homogeneous media, heterogeneous ones use ratio tracking which is unbiased but noisy.
+    pub fn transmittance(&self, ray: &Ray, max_distance: f32, sampler: &mut dyn Sampler) -> glm::Vec3 {
+        let extinction = self.absorption + self.scattering;
+        if self.density.is_none() {
+            return glm::vec3(
+                (-extinction.x * max_distance).exp(),
+                (-extinction.y * max_distance).exp(),
+                (-extinction.z * max_distance).exp());
+        }
+
+        let mut transmittance = glm::vec3(1.0, 1.0, 1.0);
+        let (mut distance, far, majorant) = match self.tracking_range(ray, max_distance) {
+            Some(range) => range,
+            None => return transmittance,
+        };
+
+        loop {
+            distance -= (1.0 - sampler.get_1d()).ln() / majorant;
+            if distance >= far {
+                return transmittance;
+            }
+
+            let local_extinction = extinction * self.density(&(ray.origin + ray.direction * distance));
+            transmittance = transmittance * (glm::vec3(1.0, 1.0, 1.0) - local_extinction / majorant);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::geom::AABB;
+    use crate::renderer::sampler::independent::IndependentSampler;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn henyey_greenstein_should_have_the_asymmetry_as_mean_cosine() {
+        let direction = glm::normalize(glm::vec3(0.3, 0.2, 1.0));
+        for &g in &[-0.6, 0.0, 0.8] {
+            let phase = HenyeyGreenstein::new(g);
+            let samples = 10000;
+            let mut sum = 0.0;
+            for i in 0..samples {
+                let u = glm::vec2((i as f32 + 0.5) / samples as f32, 0.37);
+                sum += glm::dot(phase.sample(direction, u), direction);
+            }
+
+            assert!((sum / samples as f32 - g).abs() < 0.01, "mean cosine for {}", g);
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_should_integrate_to_one_over_the_sphere() {
+        for &g in &[-0.6, 0.0, 0.8] {
+            let phase = HenyeyGreenstein::new(g);
+            let steps = 10000;
+            // Over bands of equal solid angle, 4 pi / steps each
+            let total: f32 = (0..steps)
+                .map(|i| phase.evaluate(1.0 - 2.0 * (i as f32 + 0.5) / steps as f32) * 4.0 * PI / steps as f32)
+                .sum();
+
+            assert!((total - 1.0).abs() < 1e-3, "{} for {}", total, g);
+        }
+    }
+
+    #[test]
+    fn delta_tracking_should_pass_with_the_transmittance_probability() {
+        let medium = Medium::new(glm::vec3(0.2, 0.2, 0.2), glm::vec3(0.3, 0.3, 0.3), HenyeyGreenstein::new(0.0), None);
+        let ray = Ray::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 1.0));
+        let mut sampler = IndependentSampler::new(StdRng::seed_from_u64(3));
+
+        let trials = 20000;
+        let mut passed = 0;
+        for _ in 0..trials {
+            if let MediumEvent::Passed { .. } = medium.sample_interaction(&ray, 2.0, &mut sampler) {
+                passed += 1;
+            }
+        }
+
+        let expected = (-0.5f32 * 2.0).exp();
+        assert!((passed as f32 / trials as f32 - expected).abs() < 0.01);
+        assert!((medium.transmittance(&ray, 2.0, &mut sampler).x - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ratio_tracking_should_match_the_transmittance_of_a_constant_grid() {
+        let bounds = AABB { min: glm::vec3(-1.0, -1.0, 0.0), max: glm::vec3(1.0, 1.0, 1.0) };
+        let grid = DensityGrid::from_text("2 2 2  0.5 0.5 0.5 0.5 0.5 0.5 0.5 0.5", bounds).unwrap();
+        let medium = Medium::new(glm::vec3(1.0, 0.5, 0.0), glm::vec3(0.0, 0.0, 0.0), HenyeyGreenstein::new(0.0), Some(Arc::new(grid)));
+        let ray = Ray::new(glm::vec3(0.0, 0.0, -1.0), glm::vec3(0.0, 0.0, 1.0));
+        let mut sampler = IndependentSampler::new(StdRng::seed_from_u64(5));
+
+        let trials = 20000;
+        let mut sum = glm::vec3(0.0, 0.0, 0.0);
+        for _ in 0..trials {
+            sum = sum + medium.transmittance(&ray, 10.0, &mut sampler);
+        }
+        let average = sum / trials as f32;
+
+        // Only the unit of the ray inside the box has density 0.5
+        assert!((average.x - (-0.5f32).exp()).abs() < 0.01);
+        assert!((average.y - (-0.25f32).exp()).abs() < 0.01);
+        assert_eq!(average.z, 1.0);
+    }
+}
@@ -9,6 +9,10 @@ pub mod octree_mesh;
 pub mod material;
 pub mod store;
 pub mod material_builder;
+pub mod medium;
+pub mod density_grid;
+pub mod environment_map;
+pub mod sky;
 
 pub trait ModelLoader {
     fn load(&self, path: &str) -> Result<Model, &str>;
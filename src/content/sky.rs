@@ -0,0 +1,217 @@
+use crate::color::spectrum::xyz_to_rgb;
+use crate::content::environment_map::{EnvironmentMap, Sun};
+use std::f32::consts::PI;
+
+/// Angular radius of the sun seen from the earth.
+pub const SUN_ANGULAR_RADIUS: f32 = 0.004_65;
+
+// Luminance of the sun outside of the atmosphere in kcd/m2
+const SUN_LUMINANCE: f32 = 1.98e6;
+
+// Resolution the sky is baked at, the sky is smooth so it doesn't need much
+const SKY_WIDTH: usize = 512;
+const SKY_HEIGHT: usize = 256;
+
+/// The clear daytime sky of Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight", with the sun.
+/// `turbidity` is the haziness of the air: 2 is a very clear sky and 10 a hazy one.
+/// Radiance is in kcd/m2 like in the paper, a midday scene needs about -5 stops of exposure.
+/// Directions below the horizon are black, a scene puts a ground plane there.
+#[derive(Clone, Copy, Debug)]
+pub struct Sky {
+    turbidity: f32,
+    sun_direction: glm::Vec3,
+}
+
+impl Sky {
+    pub fn new(turbidity: f32, sun_direction: glm::Vec3) -> Self {
+        Sky {
+            turbidity,
+            sun_direction: glm::normalize(sun_direction),
+        }
+    }
+
+    /// The light from the sky in `direction`, without the sun.
+    pub fn radiance(&self, direction: &glm::Vec3) -> glm::Vec3 {
+        let direction = glm::normalize(*direction);
+        if direction.y <= 0.0 {
+            return glm::vec3(0.0, 0.0, 0.0);
+        }
+
+        // Below the horizon the model falls apart, a set sun gives the sky of one that just set
+        let sun_zenith = self.sun_direction.y.clamp(0.01, 1.0).acos();
+        let theta = direction.y.min(1.0).acos();
+        let gamma = glm::dot(direction, self.sun_direction).clamp(-1.0, 1.0).acos();
+        let t = self.turbidity;
+
+        let zenith = zenith_values(t, sun_zenith);
+        let relative = |coefficients: [f32; 5], value: f32| {
+            value * perez(&coefficients, theta, gamma) / perez(&coefficients, 0.0, sun_zenith)
+        };
+
+        let luminance = relative(luminance_coefficients(t), zenith.x);
+        let x = relative(x_coefficients(t), zenith.y);
+        let y = relative(y_coefficients(t), zenith.z);
+        if y <= 0.0 {
+            return glm::vec3(0.0, 0.0, 0.0);
+        }
+
+        let xyz = glm::vec3(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_to_rgb(&xyz);
+
+        glm::vec3(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+
+    /// The radiance of the sun disk after its light made it through the atmosphere, zero once it has set.
+    pub fn sun_radiance(&self) -> glm::Vec3 {
+        let zenith = self.sun_direction.y.clamp(-1.0, 1.0).acos().to_degrees();
+        if zenith >= 90.0 {
+            return glm::vec3(0.0, 0.0, 0.0);
+        }
+
+        // Relative optical mass, how much more air the light crosses than when the sun is straight up
+        let mass = 1.0 / (zenith.to_radians().cos() + 0.15 * (93.885 - zenith).powf(-1.253));
+        let beta = 0.046_08 * self.turbidity - 0.045_86;
+
+        // Rayleigh scattering by the air and scattering by aerosols (Angstrom), at red, green and blue in um
+        let transmittance = |wavelength: f32| {
+            let rayleigh = (-mass * 0.008_735 * wavelength.powf(-4.08)).exp();
+            let aerosol = (-mass * beta * wavelength.powf(-1.3)).exp();
+            rayleigh * aerosol
+        };
+
+        glm::vec3(transmittance(0.65), transmittance(0.57), transmittance(0.475)) * SUN_LUMINANCE
+    }
+
+    /// The sky and the sun as an environment map, scaled by `intensity`.
+    pub fn to_environment_map(self, intensity: f32) -> EnvironmentMap {
+        let mut pixels = Vec::with_capacity(SKY_WIDTH * SKY_HEIGHT);
+        for y in 0..SKY_HEIGHT {
+            for x in 0..SKY_WIDTH {
+                // Same layout as `EnvironmentMap`: the center looks along -z, the top is up
+                let theta = PI * (y as f32 + 0.5) / SKY_HEIGHT as f32;
+                let phi = 2.0 * PI * ((x as f32 + 0.5) / SKY_WIDTH as f32 - 0.5);
+                let direction = glm::vec3(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+
+                pixels.push(self.radiance(&direction));
+            }
+        }
+
+        let sun = Sun {
+            direction: self.sun_direction,
+            angular_radius: SUN_ANGULAR_RADIUS,
+            radiance: self.sun_radiance() * intensity,
+        };
+
+        EnvironmentMap::new(SKY_WIDTH, SKY_HEIGHT, pixels, 0.0, intensity)
+            .expect("The sky has a pixel for every direction")
+            .with_sun(sun)
+    }
+}
+
+/// Where the sun is at `time` hours of local solar time (12 is noon) on day `day` of the year,
+/// seen from `latitude` degrees north. North is -z, east is +x and up is +y.
+pub fn sun_direction(latitude: f32, day: f32, time: f32) -> glm::Vec3 {
+    let latitude = latitude.to_radians();
+    let declination = 0.4093 * (2.0 * PI * (day - 81.0) / 368.0).sin();
+    let hour_angle = PI * time / 12.0;
+
+    let zenith = PI / 2.0 - (latitude.sin() * declination.sin() - latitude.cos() * declination.cos() * hour_angle.cos()).asin();
+    // Measured from the south towards the west
+    let azimuth = (-declination.cos() * hour_angle.sin())
+        .atan2(latitude.cos() * declination.sin() - latitude.sin() * declination.cos() * hour_angle.cos());
+
+    glm::vec3(-zenith.sin() * azimuth.sin(), zenith.cos(), zenith.sin() * azimuth.cos())
+}
+
+// Luminance (kcd/m2) and the x and y chromaticity straight up
+fn zenith_values(t: f32, sun_zenith: f32) -> glm::Vec3 {
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_zenith);
+    let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+    let s = sun_zenith;
+    let (s2, s3) = (s * s, s * s * s);
+    let x = t * t * (0.001_66 * s3 - 0.003_75 * s2 + 0.002_09 * s)
+        + t * (-0.029_03 * s3 + 0.063_77 * s2 - 0.032_02 * s + 0.003_94)
+        + (0.116_93 * s3 - 0.211_96 * s2 + 0.060_52 * s + 0.258_86);
+    let y = t * t * (0.002_75 * s3 - 0.006_10 * s2 + 0.003_17 * s)
+        + t * (-0.042_14 * s3 + 0.089_70 * s2 - 0.041_53 * s + 0.005_16)
+        + (0.153_46 * s3 - 0.267_56 * s2 + 0.066_70 * s + 0.266_88);
+
+    glm::vec3(luminance, x, y)
+}
+
+// The Perez sky model, relative brightness at zenith angle `theta` and angle `gamma` from the sun
+fn perez(coefficients: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    let cos_gamma = gamma.cos();
+
+    (1.0 + a * (b / theta.cos().max(0.01)).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+fn luminance_coefficients(t: f32) -> [f32; 5] {
+    [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703]
+}
+
+fn x_coefficients(t: f32) -> [f32; 5] {
+    [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452]
+}
+
+fn y_coefficients(t: f32) -> [f32; 5] {
+    [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_direction_should_follow_the_day() {
+        // At the equator on the equinox the sun rises in the east and passes straight overhead
+        let noon = sun_direction(0.0, 81.0, 12.0);
+        assert!(glm::length(noon - glm::vec3(0.0, 1.0, 0.0)) < 1e-3, "{:?}", noon);
+
+        let morning = sun_direction(0.0, 81.0, 6.0);
+        assert!(glm::length(morning - glm::vec3(1.0, 0.0, 0.0)) < 1e-3, "{:?}", morning);
+
+        // Far north at midsummer noon it stands in the south
+        let north = sun_direction(60.0, 172.0, 12.0);
+        assert!(north.z > 0.0 && north.x.abs() < 1e-3);
+        assert!((north.y.asin().to_degrees() - 53.4).abs() < 0.5);
+    }
+
+    #[test]
+    fn clear_sky_should_be_blue_and_brightest_near_the_sun() {
+        let sky = Sky::new(2.5, glm::vec3(0.0, 1.0, 1.0));
+        let zenith = sky.radiance(&glm::vec3(0.0, 1.0, 0.0));
+        let near_sun = sky.radiance(&glm::vec3(0.0, 1.0, 1.1));
+        let away_from_sun = sky.radiance(&glm::vec3(0.0, 1.0, -1.0));
+
+        assert!(zenith.z > zenith.x);
+        assert!(near_sun.y > zenith.y && zenith.y > away_from_sun.y);
+        assert_eq!(sky.radiance(&glm::vec3(0.0, -1.0, 0.0)), glm::vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn low_sun_should_be_dimmer_and_redder() {
+        let high = Sky::new(3.0, glm::vec3(0.0, 1.0, 0.2)).sun_radiance();
+        let low = Sky::new(3.0, glm::vec3(0.0, 0.05, 1.0)).sun_radiance();
+
+        assert!(low.y < high.y);
+        assert!(low.z / low.x < high.z / high.x);
+        assert_eq!(Sky::new(3.0, glm::vec3(0.0, -0.1, 1.0)).sun_radiance(), glm::vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn environment_map_should_show_sky_and_sun() {
+        let sky = Sky::new(3.0, glm::vec3(1.0, 1.0, 0.0));
+        let map = sky.to_environment_map(1.0);
+
+        let sun = map.radiance(&glm::vec3(1.0, 1.0, 0.0));
+        assert!(sun.y > 1e5);
+
+        let direction = glm::vec3(-0.3, 0.8, 0.5);
+        let expected = sky.radiance(&direction);
+        let result = map.radiance(&direction);
+        assert!(glm::length(result - expected) < 0.05 * glm::length(expected), "{:?} vs {:?}", result, expected);
+    }
+}
@@ -150,8 +150,8 @@ impl AABB {
 pub fn ray_aabb_intersect(ray: &Ray, aabb: &AABB) -> bool {
     const EPSILON: f32 = 9.99999997475243E-07;
 
-    let mut near = std::f32::MIN_POSITIVE;
-    let mut far = std::f32::MAX;
+    let mut near = ray.t_min.max(std::f32::MIN_POSITIVE);
+    let mut far = ray.t_max.min(std::f32::MAX);
 
     for dimension in 0..3 {
         if ray.direction[dimension].abs() < EPSILON {
@@ -230,7 +230,7 @@ pub fn ray_triangle_intersect(ray: &Ray, triangle: (glm::Vector3<f32>, glm::Vect
     }
 
     let t = glm::dot(e2, q) * inv_determinant;
-    if t > EPSILON {
+    if t > EPSILON && t >= ray.t_min && t <= ray.t_max {
         *distance = t;
         *result_u = u;
         *result_v = v;
@@ -351,10 +351,10 @@ mod tests {
 
     #[test]
     fn triangle_intersect_should_handle_simple_intersection() {
-        let ray = Ray {
-            origin: glm::Vector3::new(0.0, 0.0, 0.0),
-            direction: glm::Vector3::new(0.0, 0.0, -1.0),
-        };
+        let ray = Ray::new(
+            glm::Vector3::new(0.0, 0.0, 0.0),
+            glm::Vector3::new(0.0, 0.0, -1.0),
+        );
 
         let triangle = (
             glm::Vector3::new(0.0, 0.5, -1.0),
@@ -372,10 +372,10 @@ mod tests {
 
     #[test]
     fn triangle_intersect_should_handle_backface_intersection() {
-        let ray = Ray {
-            origin: glm::Vector3::new(0.0, 0.0, 0.0),
-            direction: glm::Vector3::new(0.0, 0.0, -1.0),
-        };
+        let ray = Ray::new(
+            glm::Vector3::new(0.0, 0.0, 0.0),
+            glm::Vector3::new(0.0, 0.0, -1.0),
+        );
 
         let triangle = (
             glm::Vector3::new(0.0, 0.5, -1.0),
@@ -393,10 +393,10 @@ mod tests {
 
     #[test]
     fn triangle_intersect_should_handle_simple_miss() {
-        let ray = Ray {
-            origin: glm::Vector3::new(0.5, 0.5, 0.0),
-            direction: glm::Vector3::new(0.0, 0.0, -1.0),
-        };
+        let ray = Ray::new(
+            glm::Vector3::new(0.5, 0.5, 0.0),
+            glm::Vector3::new(0.0, 0.0, -1.0),
+        );
 
         let triangle = (
             glm::Vector3::new(0.0, 0.5, -1.0),
@@ -414,10 +414,10 @@ mod tests {
 
     #[test]
     fn triangle_intersect_should_handle_skewed_triangle() {
-        let ray = Ray {
-            origin: glm::Vector3::new(0.0, 0.0, 0.0),
-            direction: glm::Vector3::new(0.0, 1.0, 0.0),
-        };
+        let ray = Ray::new(
+            glm::Vector3::new(0.0, 0.0, 0.0),
+            glm::Vector3::new(0.0, 1.0, 0.0),
+        );
 
         let triangle = (
             glm::Vector3::new(0.0, 10.0, 0.5),
@@ -435,10 +435,10 @@ mod tests {
 
     #[test]
     fn triangle_intersect_should_handle_parallel_triangle_miss() {
-        let ray = Ray {
-            origin: glm::Vector3::new(0.0, 0.0, 0.0),
-            direction: glm::Vector3::new(1.0, 0.0, 0.0),
-        };
+        let ray = Ray::new(
+            glm::Vector3::new(0.0, 0.0, 0.0),
+            glm::Vector3::new(1.0, 0.0, 0.0),
+        );
 
         let triangle = (
             glm::Vector3::new(1.0, 0.0, 0.0),
@@ -456,10 +456,10 @@ mod tests {
 
     #[test]
     fn triangle_intersect_should_return_correct_distance() {
-        let ray = Ray {
-            origin: glm::Vector3::new(0.0, 0.0, 0.0),
-            direction: glm::Vector3::new(0.0, 0.0, -1.0),
-        };
+        let ray = Ray::new(
+            glm::Vector3::new(0.0, 0.0, 0.0),
+            glm::Vector3::new(0.0, 0.0, -1.0),
+        );
 
         let triangle = (
             glm::Vector3::new(0.0, 0.5, -5.0),
@@ -474,4 +474,25 @@ mod tests {
         let _ = ray_triangle_intersect(&ray, triangle, &mut distance, &mut u, &mut v);
         assert_eq!(distance, 5.0);
     }
+
+    #[test]
+    fn triangle_intersect_should_respect_ray_range() {
+        let triangle = (
+            glm::Vector3::new(0.0, 0.5, -5.0),
+            glm::Vector3::new(0.5, -0.5, -5.0),
+            glm::Vector3::new(-0.5, -0.5, -5.0)
+        );
+
+        let mut distance = 0.0f32;
+        let mut u = 0.0f32;
+        let mut v = 0.0f32;
+
+        let ray = Ray::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, -1.0)).with_max_distance(4.0);
+        assert_eq!(ray_triangle_intersect(&ray, triangle, &mut distance, &mut u, &mut v), false);
+
+        let mut ray = Ray::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, -1.0));
+        ray.t_min = 6.0;
+        assert_eq!(ray_triangle_intersect(&ray, triangle, &mut distance, &mut u, &mut v), false);
+        assert_eq!(ray_aabb_intersect(&ray, &AABB::from_vector3(&vec![triangle.0, triangle.1, triangle.2])), false);
+    }
 }
\ No newline at end of file
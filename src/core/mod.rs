@@ -4,6 +4,8 @@ use crate::content::material::Material;
 pub mod geom;
 pub mod math;
 pub mod plane;
+pub mod sampling;
+pub mod optics;
 
 pub trait Intersection {
     fn coordinate(&self) -> glm::Vec3;
@@ -25,19 +27,133 @@ pub trait Intersection {
     pub material_index: usize,
 }*/
 
+/// A ray only reports hits at distances within [t_min, t_max].
+/// `time` is the moment within the shutter interval the ray is traced at, 0 when the shutter opens and 1 when it closes.
 pub struct Ray {
     pub origin: glm::Vector3<f32>,
     pub direction: glm::Vector3<f32>,
+    pub t_min: f32,
+    pub t_max: f32,
+    pub time: f32,
 }
 
 
 impl Ray {
+    pub fn new(origin: glm::Vec3, direction: glm::Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            t_min: 0.0,
+            t_max: std::f32::INFINITY,
+            time: 0.0,
+        }
+    }
+
+    /// Starts a secondary ray at a surface point, nudged off the surface to the side `direction` leaves on
+    /// so it doesn't hit the surface it starts on.
+    pub fn spawn(point: glm::Vec3, normal: glm::Vec3, direction: glm::Vec3) -> Ray {
+        let normal = glm::normalize(normal);
+        let side = if glm::dot(direction, normal) < 0.0 { normal * -1.0 } else { normal };
+
+        Ray::new(offset_ray_origin(point, side), direction)
+    }
+
+    pub fn with_max_distance(mut self, t_max: f32) -> Ray {
+        self.t_max = t_max;
+        self
+    }
+
+    /// Secondary rays have to be traced at the time of the ray they continue.
+    pub fn with_time(mut self, time: f32) -> Ray {
+        self.time = time;
+        self
+    }
+
     pub fn transform(&self, matrix: &glm::Mat4) -> Ray {
         let new_origin = *matrix * glm::Vector4::new(self.origin.x, self.origin.y, self.origin.z, 1.0);
-        let new_direction = glm::normalize(*matrix * glm::Vector4::new(self.direction.x, self.direction.y, self.direction.z, 0.0));
+        let transformed_direction = *matrix * glm::Vector4::new(self.direction.x, self.direction.y, self.direction.z, 0.0);
+        let new_direction = glm::normalize(transformed_direction);
+
+        // The direction gets renormalized, so distances along the ray scale with the length it had after the transform
+        let scale = glm::length(transformed_direction) / glm::length(self.direction);
         Ray {
             origin: glm::Vector3::new(new_origin.x, new_origin.y, new_origin.z),
             direction: glm::Vector3::new(new_direction.x, new_direction.y, new_direction.z),
+            t_min: self.t_min * scale,
+            t_max: self.t_max * scale,
+            time: self.time,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Moves `point` off a surface in the direction of the unit `normal`.
+/// Far from the origin the offset is a fixed number of ULPs, so it grows with the rounding error of the
+/// coordinates instead of being a fixed distance that is too large for small objects and too small for large scenes.
+/// From "A Fast and Robust Method for Avoiding Self-Intersection", Ray Tracing Gems, chapter 6.
+pub fn offset_ray_origin(point: glm::Vec3, normal: glm::Vec3) -> glm::Vec3 {
+    const ORIGIN: f32 = 1.0 / 32.0;
+    const FLOAT_SCALE: f32 = 1.0 / 65536.0;
+    const INT_SCALE: f32 = 256.0;
+
+    let offset_component = |p: f32, n: f32| {
+        if p.abs() < ORIGIN {
+            // Close to zero ULPs are tiny, use a small fixed offset instead
+            return p + FLOAT_SCALE * n;
+        }
+
+        let ulps = (INT_SCALE * n) as i32;
+        let bits = p.to_bits() as i32;
+
+        // Moving away from zero increases the bit pattern for both signs
+        f32::from_bits((if p < 0.0 { bits - ulps } else { bits + ulps }) as u32)
+    };
+
+    glm::vec3(
+        offset_component(point.x, normal.x),
+        offset_component(point.y, normal.y),
+        offset_component(point.z, normal.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::One;
+
+    #[test]
+    fn offset_ray_origin_should_move_along_normal() {
+        let result = offset_ray_origin(glm::vec3(10.0, -20.0, 0.0), glm::vec3(0.0, 1.0, 0.0));
+
+        assert_eq!(result.x, 10.0);
+        assert!(result.y > -20.0);
+        assert!(result.y < -19.99);
+        assert_eq!(result.z, 0.0);
+    }
+
+    #[test]
+    fn offset_ray_origin_should_scale_with_magnitude() {
+        let near = offset_ray_origin(glm::vec3(1.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0)).x - 1.0;
+        let far = offset_ray_origin(glm::vec3(1000.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0)).x - 1000.0;
+
+        assert!(near > 0.0);
+        assert!(far > near * 100.0);
+    }
+
+    #[test]
+    fn spawn_should_offset_to_the_side_the_ray_leaves_on() {
+        let normal = glm::vec3(0.0, 2.0, 0.0);
+
+        assert!(Ray::spawn(glm::vec3(0.0, 5.0, 0.0), normal, glm::vec3(0.0, 1.0, 0.0)).origin.y > 5.0);
+        assert!(Ray::spawn(glm::vec3(0.0, 5.0, 0.0), normal, glm::vec3(0.0, -1.0, 0.0)).origin.y < 5.0);
+    }
+
+    #[test]
+    fn transform_should_scale_distance_range() {
+        let matrix = glm::ext::scale(&glm::Mat4::one(), glm::vec3(2.0, 2.0, 2.0));
+        let ray = Ray::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0)).with_max_distance(3.0);
+
+        let result = ray.transform(&matrix);
+
+        assert_eq!(result.t_max, 6.0);
+        assert_eq!(result.direction, glm::vec3(1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::color::spectrum::{MIN_WAVELENGTH, MAX_WAVELENGTH};
+
+/// Refracts `incident` through a surface with unit `normal` facing against it, `eta` is n1 / n2.
+/// Returns None on total internal reflection.
+pub fn refract(incident: glm::Vec3, normal: glm::Vec3, eta: f32) -> Option<glm::Vec3> {
+    let cos_i = -glm::dot(incident, normal);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    Some(glm::normalize(incident * eta + normal * (eta * cos_i - cos_t)))
+}
+
+/// Fraction of light reflected by a smooth dielectric boundary, going from index `n1` into `n2`.
+/// `cos_i` is the cosine between the incoming direction and the normal. Unpolarized, so the
+/// average of the s and p polarized Fresnel equations. Total internal reflection gives 1.
+pub fn fresnel_dielectric(cos_i: f32, n1: f32, n2: f32) -> f32 {
+    let cos_i = cos_i.abs().min(1.0);
+    let sin_t = n1 / n2 * (1.0 - cos_i * cos_i).max(0.0).sqrt();
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin_t * sin_t).max(0.0).sqrt();
+    let r_s = (n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t);
+    let r_p = (n2 * cos_i - n1 * cos_t) / (n2 * cos_i + n1 * cos_t);
+
+    (r_s * r_s + r_p * r_p) / 2.0
+}
+
+/// Beer-Lambert law, the fraction of light left after travelling `distance` through a medium.
+pub fn transmittance(absorption: &glm::Vec3, distance: f32) -> glm::Vec3 {
+    glm::vec3(
+        (-absorption.x * distance).exp(),
+        (-absorption.y * distance).exp(),
+        (-absorption.z * distance).exp())
+}
+
+/// How the refractive index of a transparent material changes with the wavelength of light,
+/// which is what splits white light into colors. Wavelengths are in nm.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dispersion {
+    /// n = a + b / λ², with λ in μm. Good enough for most glasses in the visible range.
+    Cauchy { a: f32, b: f32 },
+    /// n² = 1 + Σ b λ² / (λ² - c), with λ in μm and c in μm². The form glass catalogues use.
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Dispersion {
+    /// Wavelength of the helium d line, where catalogues quote a glass's refractive index.
+    pub const REFERENCE_WAVELENGTH: f32 = 587.6;
+
+    pub fn refractive_index(&self, wavelength: f32) -> f32 {
+        let micrometers = wavelength / 1000.0;
+        let squared = micrometers * micrometers;
+
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / squared,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f32 = b.iter().zip(c.iter())
+                    .map(|(b, c)| b * squared / (squared - c))
+                    .sum();
+                (1.0 + sum).max(0.0).sqrt()
+            }
+        }
+    }
+
+    /// True when the index is a positive number at every wavelength a spectral render can trace.
+    /// A Sellmeier term goes to infinity where λ² = c, so no c may fall within that range.
+    pub fn is_valid(&self) -> bool {
+        if let Dispersion::Sellmeier { c, .. } = self {
+            let (min, max) = (MIN_WAVELENGTH / 1000.0, MAX_WAVELENGTH / 1000.0);
+            if c.iter().any(|c| !c.is_finite() || (min * min..=max * max).contains(c)) {
+                return false;
+            }
+        }
+
+        (MIN_WAVELENGTH as u32..=MAX_WAVELENGTH as u32).all(|wavelength| {
+            let index = self.refractive_index(wavelength as f32);
+            index.is_finite() && index > 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sellmeier_should_match_bk7() {
+        let bk7 = Dispersion::Sellmeier {
+            b: [1.039_612_1, 0.231_792_34, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_65],
+        };
+
+        assert!((bk7.refractive_index(Dispersion::REFERENCE_WAVELENGTH) - 1.5168).abs() < 1e-4);
+        // Blue bends more than red
+        assert!(bk7.refractive_index(450.0) > bk7.refractive_index(650.0));
+    }
+
+    #[test]
+    fn cauchy_should_decrease_with_wavelength() {
+        let glass = Dispersion::Cauchy { a: 1.5046, b: 0.004_2 };
+
+        assert!((glass.refractive_index(1000.0) - 1.5088).abs() < 1e-5);
+        assert!(glass.refractive_index(400.0) > glass.refractive_index(700.0));
+    }
+
+    #[test]
+    fn dispersion_should_be_valid_only_with_a_positive_index_everywhere() {
+        let bk7 = Dispersion::Sellmeier {
+            b: [1.039_612_1, 0.231_792_34, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_65],
+        };
+        assert!(bk7.is_valid());
+        assert!(Dispersion::Cauchy { a: 1.5046, b: 0.004_2 }.is_valid());
+
+        // A pole at 500 nm
+        assert!(!Dispersion::Sellmeier { b: [1.0, 0.0, 0.0], c: [0.25, 0.0, 0.0] }.is_valid());
+        // n² = 1 - 2 everywhere far from the pole
+        assert!(!Dispersion::Sellmeier { b: [-2.0, 0.0, 0.0], c: [0.0, 0.0, 0.0] }.is_valid());
+        assert!(!Dispersion::Cauchy { a: 1.0, b: -0.5 }.is_valid());
+    }
+
+    #[test]
+    fn fresnel_should_match_normal_incidence() {
+        // ((n1 - n2) / (n1 + n2))^2 = 0.04 for glass
+        assert!((fresnel_dielectric(1.0, 1.0, 1.5) - 0.04).abs() < 1e-5);
+        assert!((fresnel_dielectric(1.0, 1.5, 1.0) - 0.04).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fresnel_should_reflect_everything_at_grazing_angles() {
+        assert!(fresnel_dielectric(0.001, 1.0, 1.5) > 0.99);
+    }
+
+    #[test]
+    fn fresnel_should_detect_total_internal_reflection() {
+        // The critical angle for glass to air is about 41.8 degrees
+        assert_eq!(fresnel_dielectric(43.0f32.to_radians().cos(), 1.5, 1.0), 1.0);
+        assert!(fresnel_dielectric(40.0f32.to_radians().cos(), 1.5, 1.0) < 1.0);
+    }
+
+    #[test]
+    fn refract_should_follow_snells_law() {
+        let angle = 30.0f32.to_radians();
+        let incident = glm::vec3(angle.sin(), -angle.cos(), 0.0);
+        let refracted = refract(incident, glm::vec3(0.0, 1.0, 0.0), 1.0 / 1.5).unwrap();
+
+        assert!((refracted.x - angle.sin() / 1.5).abs() < 1e-5);
+        assert!(refracted.y < 0.0);
+        assert!((glm::length(refracted) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn refract_should_return_none_on_total_internal_reflection() {
+        let angle = 60.0f32.to_radians();
+        let incident = glm::vec3(angle.sin(), -angle.cos(), 0.0);
+
+        assert!(refract(incident, glm::vec3(0.0, 1.0, 0.0), 1.5).is_none());
+    }
+
+    #[test]
+    fn transmittance_should_fall_off_exponentially() {
+        let result = transmittance(&glm::vec3(0.0, 1.0, 2.0), 0.5);
+
+        assert_eq!(result.x, 1.0);
+        assert!((result.y - (-0.5f32).exp()).abs() < 1e-6);
+        assert!((result.z - (-1.0f32).exp()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,204 @@
+use std::f32::consts::PI;
+
+/// Builds two tangent vectors that together with `normal` form an orthonormal basis.
+pub fn orthonormal_basis(normal: glm::Vec3) -> (glm::Vec3, glm::Vec3) {
+    let helper = if normal.x.abs() > 0.9 {
+        glm::vec3(0.0, 1.0, 0.0)
+    } else {
+        glm::vec3(1.0, 0.0, 0.0)
+    };
+
+    let tangent = glm::normalize(glm::cross(helper, normal));
+    let bitangent = glm::cross(normal, tangent);
+
+    (tangent, bitangent)
+}
+
+/// Maps two uniform numbers in [0, 1) to a direction on the hemisphere around +Z,
+/// distributed proportionally to the cosine of the angle to +Z.
+pub fn cosine_sample_hemisphere(u1: f32, u2: f32) -> glm::Vec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+
+    glm::vec3(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+/// Transforms a direction given relative to +Z into the hemisphere around `normal`.
+pub fn to_world(local: glm::Vec3, normal: glm::Vec3) -> glm::Vec3 {
+    let (tangent, bitangent) = orthonormal_basis(normal);
+
+    tangent * local.x + bitangent * local.y + normal * local.z
+}
+
+/// Multiple importance sampling weight of a sample picked with density `pdf` by one strategy,
+/// when another strategy would have picked it with density `other_pdf`.
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+/// A piecewise constant distribution over [0, 1), for picking values in proportion to a tabulated function.
+pub struct Distribution1D {
+    function: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    /// Falls back to a uniform distribution when the function is zero everywhere.
+    pub fn new(function: Vec<f32>) -> Self {
+        let count = function.len();
+        let mut cdf = vec![0.0; count + 1];
+        for i in 0..count {
+            cdf[i + 1] = cdf[i] + function[i].max(0.0) / count as f32;
+        }
+
+        let integral = cdf[count];
+        for (i, value) in cdf.iter_mut().enumerate().skip(1) {
+            *value = if integral > 0.0 { *value / integral } else { i as f32 / count as f32 };
+        }
+
+        Distribution1D { function, cdf, integral }
+    }
+
+    pub fn count(&self) -> usize {
+        self.function.len()
+    }
+
+    /// The integral of the function over [0, 1).
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Maps `u` to a value in [0, 1), returns it with its density and the piece it fell in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        // The last entry of the cdf at or below u, which skips pieces of zero width
+        let index = (self.cdf.partition_point(|x| *x <= u).max(1) - 1).min(self.count() - 1);
+
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 { (u - self.cdf[index]) / width } else { 0.0 };
+        let value = ((index as f32 + offset) / self.count() as f32).min(crate::renderer::sampler::ONE_MINUS_EPSILON);
+
+        (value, self.pdf(index), index)
+    }
+
+    /// The density of values in piece `index`.
+    pub fn pdf(&self, index: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.function[index].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A piecewise constant distribution over the unit square, built from rows of function values.
+/// A row is picked first, then a column within that row.
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `function` holds `width` values per row, rows along y.
+    pub fn new(function: &[f32], width: usize) -> Self {
+        let rows: Vec<Distribution1D> = function.chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|x| x.integral()).collect());
+
+        Distribution2D { rows, marginal }
+    }
+
+    /// The integral of the function over the unit square.
+    pub fn integral(&self) -> f32 {
+        self.marginal.integral()
+    }
+
+    /// A point in the unit square and its density.
+    pub fn sample(&self, u: glm::Vec2) -> (glm::Vec2, f32) {
+        let (y, row_pdf, row) = self.marginal.sample(u.y);
+        let (x, column_pdf, _) = self.rows[row].sample(u.x);
+
+        (glm::vec2(x, y), row_pdf * column_pdf)
+    }
+
+    pub fn pdf(&self, point: glm::Vec2) -> f32 {
+        let row = ((point.y * self.rows.len() as f32) as usize).min(self.rows.len() - 1);
+        let columns = &self.rows[row];
+        let column = ((point.x * columns.count() as f32) as usize).min(columns.count() - 1);
+
+        self.marginal.pdf(row) * columns.pdf(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthonormal_basis_should_be_orthogonal() {
+        let normal = glm::normalize(glm::vec3(0.3, -0.8, 0.2));
+        let (tangent, bitangent) = orthonormal_basis(normal);
+
+        assert!(glm::dot(normal, tangent).abs() < 1e-6);
+        assert!(glm::dot(normal, bitangent).abs() < 1e-6);
+        assert!(glm::dot(tangent, bitangent).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cosine_sample_hemisphere_should_stay_above_horizon() {
+        for i in 0..10 {
+            for j in 0..10 {
+                let direction = cosine_sample_hemisphere(i as f32 / 10.0, j as f32 / 10.0);
+
+                assert!(direction.z >= 0.0);
+                assert!((glm::length(direction) - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn to_world_should_map_z_axis_to_normal() {
+        let normal = glm::normalize(glm::vec3(1.0, 1.0, 0.0));
+        let result = to_world(glm::vec3(0.0, 0.0, 1.0), normal);
+
+        assert!(glm::length(result - normal) < 1e-6);
+    }
+
+    #[test]
+    fn distribution_1d_should_pick_pieces_in_proportion_to_the_function() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0]);
+        let samples = 1000;
+        let mut counts = [0; 3];
+        for i in 0..samples {
+            let (value, pdf, index) = distribution.sample((i as f32 + 0.5) / samples as f32);
+            assert_eq!((value * 3.0) as usize, index);
+            assert_eq!(pdf, distribution.pdf(index));
+            counts[index] += 1;
+        }
+
+        assert_eq!(counts, [250, 0, 750]);
+        assert!((distribution.pdf(2) - 2.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn distribution_2d_should_report_the_density_of_its_samples() {
+        let distribution = Distribution2D::new(&[1.0, 2.0, 0.0, 5.0, 0.0, 0.0], 3);
+
+        for i in 0..10 {
+            for j in 0..10 {
+                let (point, pdf) = distribution.sample(glm::vec2(i as f32 / 10.0, j as f32 / 10.0));
+                assert!(pdf > 0.0);
+                assert!((distribution.pdf(point) - pdf).abs() < 1e-5);
+            }
+        }
+
+        // Densities integrate to one over the square
+        let cells = [(0.1, 0.1), (0.5, 0.1), (0.9, 0.1), (0.1, 0.9), (0.5, 0.9), (0.9, 0.9)];
+        let total: f32 = cells.iter().map(|(x, y)| distribution.pdf(glm::vec2(*x, *y)) / 6.0).sum();
+        assert!((total - 1.0).abs() < 1e-5);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::render_configuration::{Frame, PropertyValue, PropertyChanges};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, BTreeMap};
 use num_traits::Bounded;
 use std::ops::RangeBounds;
 
@@ -27,54 +27,56 @@ struct PropProp<T> {
 }
 
 impl FrameInterpolator<'_> {
+    /// The state of every entity at `timestamp`. Each property is interpolated between the last keyframe
+    /// that set it at or before `timestamp` and the next keyframe that sets it again.
+    /// Entities show up once their first keyframe is reached, times before the first keyframe show the first one.
     pub fn frame_at(&self, timestamp: f64) -> Frame {
-        let frame_index = self.binary_search_for_frame_at_timestamp(timestamp, 0, self.key_frames.len() - 1);
-
-        if frame_index == self.key_frames.len() - 1 {
-            self.key_frames[frame_index].clone();
+        if self.key_frames.is_empty() {
+            return Frame::new(timestamp, Vec::new());
         }
 
-        let mut from_values: HashMap<String, HashMap<String, (f64, PropertyValue)>> = HashMap::new();
-        for i in (0..=frame_index).rev() {
-            self.collect_property_values(&mut from_values, i)
+        let frame_index = self.last_frame_at_or_before(timestamp).unwrap_or(0);
+
+        let mut from_values: BTreeMap<String, BTreeMap<String, (f64, PropertyValue)>> = BTreeMap::new();
+        for i in 0..=frame_index {
+            self.collect_property_values(&mut from_values, i, true);
         }
 
-        let mut to_values: HashMap<String, HashMap<String, (f64, PropertyValue)>> = HashMap::new();
+        let mut to_values: BTreeMap<String, BTreeMap<String, (f64, PropertyValue)>> = BTreeMap::new();
         for i in frame_index + 1..self.key_frames.len() {
-            self.collect_property_values(&mut to_values, i);
+            self.collect_property_values(&mut to_values, i, false);
         }
 
-        let mut all_entity_ids: Vec<&String> = from_values.keys().chain(to_values.keys()).collect();
-        all_entity_ids.sort();
-        all_entity_ids.dedup();
-
         let mut all_changes = Vec::new();
-        for id in all_entity_ids {
-            let entity_from_values = &from_values[id];
-            let entity_to_values = &to_values[id];
+        for (id, entity_from_values) in from_values {
+            let entity_to_values = to_values.get(&id);
 
             let mut new_values = HashMap::new();
-            for (property_name, property_value) in entity_from_values {
-                let frame_value = match entity_to_values.get(property_name) {
-                    Some(x) => (*x).1.lerp(&property_value.1, 1.0),
-                    None => property_value.clone().1
+            for (property_name, (from_timestamp, from_value)) in entity_from_values {
+                let frame_value = match entity_to_values.and_then(|x| x.get(&property_name)) {
+                    Some((to_timestamp, to_value)) if timestamp > from_timestamp => {
+                        let factor = (timestamp - from_timestamp) / (to_timestamp - from_timestamp);
+                        from_value.lerp(to_value, factor as f32)
+                    }
+                    _ => from_value
                 };
 
-                new_values.insert(property_name.clone(), frame_value);
+                new_values.insert(property_name, frame_value);
             }
 
-            all_changes.push(PropertyChanges::new(id.clone(), new_values))
+            all_changes.push(PropertyChanges::new(id, new_values))
         }
 
         Frame::new(timestamp, all_changes)
     }
 
-    fn collect_property_values(&self, from_values: &mut HashMap<String, HashMap<String, (f64, PropertyValue)>>, i: usize) {
+    // Later keyframes replace the values of earlier ones when `overwrite` is set, otherwise the first value is kept
+    fn collect_property_values(&self, values: &mut BTreeMap<String, BTreeMap<String, (f64, PropertyValue)>>, i: usize, overwrite: bool) {
         let frame = &self.key_frames[i];
         for entity in frame.updates() {
-            let mut entity_properties = from_values.entry(entity.id().clone()).or_insert(HashMap::new());
+            let entity_properties = values.entry(entity.id().clone()).or_insert(BTreeMap::new());
             for (property_name, value) in entity.values() {
-                if entity_properties.contains_key(property_name) {
+                if !overwrite && entity_properties.contains_key(property_name) {
                     continue;
                 }
 
@@ -83,27 +85,96 @@ impl FrameInterpolator<'_> {
         }
     }
 
-
-    fn binary_search_for_frame_at_timestamp(&self, timestamp: f64, start_index: usize, end_index: usize) -> usize {
-        if start_index == end_index {
-            return start_index;
+    /// Index of the last keyframe with a timestamp at or before `timestamp`, the keyframes are sorted by time.
+    fn last_frame_at_or_before(&self, timestamp: f64) -> Option<usize> {
+        // Binary search for the first keyframe that is AHEAD of the timestamp
+        let mut start_index = 0;
+        let mut end_index = self.key_frames.len();
+        while start_index < end_index {
+            let middle_index = start_index + (end_index - start_index) / 2;
+            if self.key_frames[middle_index].timestamp() > timestamp {
+                end_index = middle_index;
+            } else {
+                start_index = middle_index + 1;
+            }
         }
 
-        let middle_index = (end_index - start_index) / 2;
+        start_index.checked_sub(1)
+    }
+}
 
-        // We have landed on a frame that is AHEAD of the current timestamp
-        if self.key_frames[middle_index].timestamp() > timestamp {
-            return self.binary_search_for_frame_at_timestamp(timestamp, start_index, middle_index - 1);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // We have landed on a frame that is ON or BEFORE the current timestamp
+    fn key_frame(timestamp: f64, id: &str, values: Vec<(&str, PropertyValue)>) -> Frame {
+        let values = values.into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+        Frame::new(timestamp, vec![PropertyChanges::new(id.to_string(), values)])
+    }
 
-        let foo = self.binary_search_for_frame_at_timestamp(timestamp, middle_index + 1, end_index);
-        if self.key_frames[foo].timestamp() - timestamp <
-            self.key_frames[middle_index].timestamp() - timestamp {
-            return foo;
+    fn float_value(frame: &Frame, id: &str, property: &str) -> Option<f32> {
+        let entity = frame.updates().iter().find(|x| x.id() == id)?;
+        match entity.values().get(property) {
+            Some(PropertyValue::Float(x)) => Some(*x),
+            _ => None,
         }
+    }
+
+    fn key_frames() -> Vec<Frame> {
+        vec![
+            key_frame(0.0, "ball", vec![("radius", PropertyValue::Float(1.0)), ("height", PropertyValue::Float(0.0))]),
+            key_frame(2.0, "ball", vec![("radius", PropertyValue::Float(3.0))]),
+            key_frame(4.0, "ball", vec![("height", PropertyValue::Float(8.0))]),
+            key_frame(5.0, "box", vec![("height", PropertyValue::Float(1.0))]),
+        ]
+    }
+
+    #[test]
+    fn frame_at_should_interpolate_between_key_frames() {
+        let key_frames = key_frames();
+        let interpolator = FrameInterpolator::new(&key_frames);
 
-        middle_index
+        let result = interpolator.frame_at(1.0);
+
+        assert_eq!(float_value(&result, "ball", "radius"), Some(2.0));
+        // Height is set again at 4 seconds, not at 2
+        assert_eq!(float_value(&result, "ball", "height"), Some(2.0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn frame_at_should_hold_values_after_their_last_key_frame() {
+        let key_frames = key_frames();
+        let interpolator = FrameInterpolator::new(&key_frames);
+
+        let result = interpolator.frame_at(3.0);
+
+        assert_eq!(float_value(&result, "ball", "radius"), Some(3.0));
+        assert_eq!(float_value(&result, "ball", "height"), Some(6.0));
+        assert_eq!(float_value(&result, "box", "height"), None);
+    }
+
+    #[test]
+    fn frame_at_should_clamp_to_first_and_last_key_frame() {
+        let key_frames = key_frames();
+        let interpolator = FrameInterpolator::new(&key_frames);
+
+        let before = interpolator.frame_at(-1.0);
+        let after = interpolator.frame_at(10.0);
+
+        assert_eq!(float_value(&before, "ball", "radius"), Some(1.0));
+        assert_eq!(float_value(&after, "ball", "height"), Some(8.0));
+        assert_eq!(float_value(&after, "box", "height"), Some(1.0));
+    }
+
+    #[test]
+    fn last_frame_at_or_before_should_find_exact_and_in_between_timestamps() {
+        let key_frames = key_frames();
+        let interpolator = FrameInterpolator::new(&key_frames);
+
+        assert_eq!(interpolator.last_frame_at_or_before(-0.5), None);
+        assert_eq!(interpolator.last_frame_at_or_before(0.0), Some(0));
+        assert_eq!(interpolator.last_frame_at_or_before(2.0), Some(1));
+        assert_eq!(interpolator.last_frame_at_or_before(4.5), Some(2));
+        assert_eq!(interpolator.last_frame_at_or_before(7.0), Some(3));
+    }
+}
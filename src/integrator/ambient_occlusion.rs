@@ -0,0 +1,44 @@
+use crate::integrator::Integrator;
+use crate::scene::Scene;
+use crate::core::Ray;
+use crate::core::sampling::{cosine_sample_hemisphere, to_world};
+use crate::renderer::sampler::Sampler;
+use std::sync::Arc;
+
+/// White where the hemisphere above the first hit is open, darker the more of it is blocked
+/// by geometry closer than `distance`.
+pub struct AmbientOcclusionIntegrator {
+    distance: f32,
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new(distance: f32) -> Self {
+        AmbientOcclusionIntegrator {
+            distance,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(&self, scene: &Arc<dyn Scene + Sync + Send>, ray: Ray, sampler: &mut dyn Sampler) -> glm::Vec3 {
+        let intersection = match scene.find_intersection(&ray) {
+            None => return glm::vec3(0.0, 0.0, 0.0),
+            Some(intersection) => intersection,
+        };
+
+        let norm = glm::normalize(intersection.world_space_normal());
+        let facing_norm = if glm::dot(ray.direction, norm) > 0.0 { norm * -1.0 } else { norm };
+
+        let u = sampler.get_2d();
+        let direction = glm::normalize(to_world(cosine_sample_hemisphere(u.x, u.y), facing_norm));
+        let occlusion_ray = Ray::spawn(intersection.coordinate(), facing_norm, direction)
+            .with_time(ray.time)
+            .with_max_distance(self.distance);
+
+        // Only geometry within the occlusion distance can be hit
+        match scene.find_intersection(&occlusion_ray) {
+            Some(_) => glm::vec3(0.0, 0.0, 0.0),
+            None => glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
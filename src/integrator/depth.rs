@@ -0,0 +1,31 @@
+use crate::integrator::Integrator;
+use crate::scene::Scene;
+use crate::core::Ray;
+use crate::renderer::sampler::Sampler;
+use std::sync::Arc;
+
+/// Shows the distance to the first hit, white up close fading to black at `max_distance`.
+pub struct DepthIntegrator {
+    max_distance: f32,
+}
+
+impl DepthIntegrator {
+    pub fn new(max_distance: f32) -> Self {
+        DepthIntegrator {
+            max_distance,
+        }
+    }
+}
+
+impl Integrator for DepthIntegrator {
+    fn radiance(&self, scene: &Arc<dyn Scene + Sync + Send>, ray: Ray, _sampler: &mut dyn Sampler) -> glm::Vec3 {
+        match scene.find_intersection(&ray) {
+            None => glm::vec3(0.0, 0.0, 0.0),
+            Some(intersection) => {
+                let value = 1.0 - (intersection.distance() / self.max_distance).min(1.0);
+
+                glm::vec3(value, value, value)
+            }
+        }
+    }
+}
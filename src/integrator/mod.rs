@@ -0,0 +1,122 @@
+use crate::scene::Scene;
+use crate::core::{Intersection, Ray};
+use crate::renderer::sampler::Sampler;
+use crate::color::spectrum::SampledWavelengths;
+use crate::content::medium::Medium;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+pub mod whitted;
+pub mod path_tracer;
+pub mod ambient_occlusion;
+pub mod normal;
+pub mod depth;
+pub mod termination;
+
+use crate::renderer::settings::RenderSettings;
+
+/// Computes the color seen along a camera ray.
+/// The renderer calls `radiance` once per pixel sample and averages the results.
+pub trait Integrator {
+    fn radiance(&self, scene: &Arc<dyn Scene + Sync + Send>, ray: Ray, sampler: &mut dyn Sampler) -> glm::Vec3;
+
+    /// Same as `radiance`, but split into direct and indirect light for the lighting passes.
+    /// Integrators that can't tell the two apart report everything as direct.
+    fn radiance_components(&self, scene: &Arc<dyn Scene + Sync + Send>, ray: Ray, sampler: &mut dyn Sampler) -> RadianceComponents {
+        RadianceComponents {
+            direct: self.radiance(scene, ray, sampler),
+            indirect: glm::vec3(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+pub struct RadianceComponents {
+    /// Light from emitters seen directly or reaching the first hit straight from an emitter.
+    pub direct: glm::Vec3,
+    /// Light that bounced off at least one more surface on its way to the first hit.
+    pub indirect: glm::Vec3,
+}
+
+impl RadianceComponents {
+    pub fn total(&self) -> glm::Vec3 {
+        self.direct + self.indirect
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IntegratorType {
+    Whitted,
+    PathTracing,
+    AmbientOcclusion,
+    Normal,
+    Depth,
+}
+
+impl IntegratorType {
+    pub fn from_name(name: &str) -> Option<IntegratorType> {
+        match name {
+            "whitted" => Some(IntegratorType::Whitted),
+            "path" => Some(IntegratorType::PathTracing),
+            "ao" => Some(IntegratorType::AmbientOcclusion),
+            "normal" => Some(IntegratorType::Normal),
+            "depth" => Some(IntegratorType::Depth),
+            _ => None,
+        }
+    }
+}
+
+pub fn create_integrator(integrator_type: IntegratorType, settings: &RenderSettings) -> Arc<dyn Integrator + Sync + Send> {
+    let termination = settings.path_termination;
+    match integrator_type {
+        IntegratorType::Whitted => Arc::new(whitted::WhittedIntegrator::new(termination.max_depth)),
+        IntegratorType::PathTracing => Arc::new(path_tracer::PathTracingIntegrator::new(termination).with_spectral(settings.spectral)),
+        IntegratorType::AmbientOcclusion => Arc::new(ambient_occlusion::AmbientOcclusionIntegrator::new(2.0)),
+        IntegratorType::Normal => Arc::new(normal::NormalIntegrator {}),
+        IntegratorType::Depth => Arc::new(depth::DepthIntegrator::new(30.0)),
+    }
+}
+
+/// Follows a shadow ray up to `distance`. The boundaries of media don't block the light, they only change
+/// the medium it travels through. Returns the transmittance of the media crossed on the way
+/// and the first other surface, if there is one before `distance`.
+pub fn trace_shadow_ray<'a>(scene: &'a Arc<dyn Scene + Sync + Send>, mut ray: Ray, mut distance: f32, mut medium: Option<Arc<Medium>>, sampler: &mut dyn Sampler, wavelengths: &Option<SampledWavelengths>) -> (glm::Vec3, Option<Box<dyn Intersection + 'a>>) {
+    let mut transmittance = glm::vec3(1.0, 1.0, 1.0);
+    loop {
+        let intersection = scene.find_intersection(&ray).filter(|x| x.distance() < distance);
+        if let Some(current_medium) = &medium {
+            let max_distance = intersection.as_ref().map_or(distance, |x| x.distance());
+            transmittance = transmittance * to_path_medium(current_medium, wavelengths).transmittance(&ray, max_distance, sampler);
+        }
+
+        match intersection {
+            Some(intersection) if intersection.material().is_medium_boundary() => {
+                let norm = glm::normalize(intersection.world_space_normal());
+                medium = if glm::dot(ray.direction, norm) < 0.0 {
+                    intersection.material().interior_medium().cloned()
+                } else {
+                    scene.medium().cloned()
+                };
+                distance -= intersection.distance();
+                ray = Ray::spawn(intersection.coordinate(), norm, ray.direction).with_time(ray.time);
+            }
+            intersection => return (transmittance, intersection),
+        }
+    }
+}
+
+/// The fraction of the light of a light `distance` along `ray` that makes it back to the ray origin,
+/// zero when something is in the way.
+pub fn light_transmittance(scene: &Arc<dyn Scene + Sync + Send>, ray: Ray, distance: f32, medium: Option<Arc<Medium>>, sampler: &mut dyn Sampler, wavelengths: &Option<SampledWavelengths>) -> glm::Vec3 {
+    match trace_shadow_ray(scene, ray, distance, medium, sampler, wavelengths) {
+        (transmittance, None) => transmittance,
+        (_, Some(_)) => glm::vec3(0.0, 0.0, 0.0),
+    }
+}
+
+/// The medium with its coefficients at the sampled wavelengths of a spectral path.
+pub fn to_path_medium<'a>(medium: &'a Medium, wavelengths: &Option<SampledWavelengths>) -> Cow<'a, Medium> {
+    match wavelengths {
+        Some(wavelengths) => Cow::Owned(medium.with_coefficients(wavelengths.spectrum_from_rgb(medium.absorption()), wavelengths.spectrum_from_rgb(medium.scattering()))),
+        None => Cow::Borrowed(medium),
+    }
+}
@@ -0,0 +1,21 @@
+use crate::integrator::Integrator;
+use crate::scene::Scene;
+use crate::core::Ray;
+use crate::renderer::sampler::Sampler;
+use std::sync::Arc;
+
+/// Shows the world space normal of the first hit, mapped from [-1, 1] to [0, 1] per axis.
+pub struct NormalIntegrator {}
+
+impl Integrator for NormalIntegrator {
+    fn radiance(&self, scene: &Arc<dyn Scene + Sync + Send>, ray: Ray, _sampler: &mut dyn Sampler) -> glm::Vec3 {
+        match scene.find_intersection(&ray) {
+            None => glm::vec3(0.0, 0.0, 0.0),
+            Some(intersection) => {
+                let norm = glm::normalize(intersection.world_space_normal());
+
+                (norm + glm::vec3(1.0, 1.0, 1.0)) * 0.5
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::integrator::{Integrator, RadianceComponents, light_transmittance, to_path_medium};
+use crate::scene::Scene;
+use crate::core::Ray;
+use crate::core::sampling::{cosine_sample_hemisphere, to_world, power_heuristic};
+use crate::integrator::termination::PathTermination;
+use crate::core::optics::{fresnel_dielectric, refract, transmittance};
+use crate::renderer::sampler::Sampler;
+use crate::color::spectrum::SampledWavelengths;
+use crate::content::medium::{Medium, MediumEvent};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Monte Carlo path tracing with cosine-weighted diffuse bounces (global illumination).
+/// In spectral mode every path carries a few wavelengths instead of RGB, see `SampledWavelengths`.
+/// Media are sampled with delta tracking, scattering inside a medium counts as a bounce.
+/// Diffuse surfaces and scattering in media also sample the environment directly, combined with the bounces by multiple
+/// importance sampling, and gather the light of every light without geometry, which bounces can never hit.
+pub struct PathTracingIntegrator {
+    termination: PathTermination,
+    spectral: bool,
+}
+
+impl PathTracingIntegrator {
+    pub fn new(termination: PathTermination) -> Self {
+        PathTracingIntegrator {
+            termination,
+            spectral: false,
+        }
+    }
+
+    pub fn with_spectral(mut self, spectral: bool) -> Self {
+        self.spectral = spectral;
+        self
+    }
+
+    // Follows a path, colors are RGB or the values at the sampled wavelengths when `wavelengths` is set
+    fn trace(&self, scene: &Arc<dyn Scene + Sync + Send>, camera_ray: Ray, sampler: &mut dyn Sampler, wavelengths: &mut Option<SampledWavelengths>) -> RadianceComponents {
+        let mut components = RadianceComponents {
+            direct: glm::vec3(0.0, 0.0, 0.0),
+            indirect: glm::vec3(0.0, 0.0, 0.0),
+        };
+        let mut throughput = glm::vec3(1.0, 1.0, 1.0);
+        let mut ray = camera_ray;
+        // The camera is assumed to be outside of all entities
+        let mut medium = scene.medium().cloned();
+        let mut depth = 0;
+        // Density of the direction picked by the last diffuse bounce or scattering, None when the environment couldn't have picked it
+        let mut bounce_pdf = None;
+
+        loop {
+            throughput = match self.termination.next_throughput(depth, throughput, sampler) {
+                None => break,
+                Some(throughput) => throughput,
+            };
+
+            let intersection = scene.find_intersection(&ray);
+
+            if let Some(current_medium) = &medium {
+                let max_distance = intersection.as_ref().map_or(f32::MAX, |x| x.distance());
+                match to_path_medium(current_medium, wavelengths).sample_interaction(&ray, max_distance, sampler) {
+                    MediumEvent::Absorbed => break,
+                    MediumEvent::Passed { weight } => throughput = throughput * weight,
+                    MediumEvent::Scattered { distance, weight } => {
+                        throughput = throughput * weight;
+                        let scattered_at = Ray::new(ray.origin + ray.direction * distance, glm::normalize(ray.direction)).with_time(ray.time);
+                        add_light(&mut components, depth + 1, throughput * medium_direct_light(scene, &scattered_at, current_medium, sampler, wavelengths));
+
+                        let phase = current_medium.phase();
+                        let direction = phase.sample(scattered_at.direction, sampler.get_2d());
+                        bounce_pdf = Some(phase.evaluate(glm::dot(scattered_at.direction, direction)));
+                        ray = Ray::new(scattered_at.origin, direction).with_time(ray.time);
+                        depth += 1;
+                        continue;
+                    }
+                }
+            }
+
+            let intersection = match intersection {
+                None => {
+                    if let Some(environment) = scene.environment() {
+                        let weight = match bounce_pdf {
+                            Some(pdf) => power_heuristic(pdf, environment.pdf(&ray.direction)),
+                            None => 1.0,
+                        };
+                        add_light(&mut components, depth, throughput * to_path_color(&environment.radiance(&ray.direction), wavelengths) * weight);
+                    }
+                    break;
+                }
+                Some(intersection) => intersection,
+            };
+
+            let coordinate = intersection.coordinate();
+            let norm = glm::normalize(intersection.world_space_normal());
+            let material = intersection.material();
+
+            add_light(&mut components, depth, throughput * to_path_color(material.emission(), wavelengths));
+
+            let is_entering = glm::dot(ray.direction, norm) < 0.0;
+            // The medium on the other side of the surface, for rays that cross it
+            let medium_behind = if is_entering { material.interior_medium().cloned() } else { scene.medium().cloned() };
+
+            if material.is_medium_boundary() {
+                // Not a bounce, the ray carries on unchanged in the medium behind the boundary
+                ray = Ray::spawn(coordinate, norm, ray.direction).with_time(ray.time);
+                medium = medium_behind;
+                continue;
+            }
+
+            if material.transparent() {
+                let mut refractive_index1 = 1.0;
+                let mut refractive_index2 = match wavelengths {
+                    Some(wavelengths) if material.dispersion().is_some() => {
+                        // Each wavelength would bend its own way, only the hero can follow this path
+                        wavelengths.terminate_secondary();
+                        material.refractive_index_at(wavelengths.hero())
+                    }
+                    _ => material.refractive_index(),
+                };
+                if !is_entering {
+                    std::mem::swap(&mut refractive_index1, &mut refractive_index2);
+
+                    // The ray travelled through the material to get here
+                    throughput = throughput * transmittance(&to_path_color(material.absorption(), wavelengths), intersection.distance());
+                }
+
+                // Pick reflection or refraction with the Fresnel reflectance as probability,
+                // total internal reflection makes the reflectance one so refraction is never picked.
+                let facing_norm = if is_entering { norm } else { norm * -1.0 };
+                let reflectance = fresnel_dielectric(glm::dot(ray.direction, facing_norm), refractive_index1, refractive_index2);
+                let refracted = refract(ray.direction, facing_norm, refractive_index1 / refractive_index2);
+
+                ray = match refracted {
+                    Some(direction) if sampler.get_1d() >= reflectance => {
+                        medium = medium_behind;
+                        Ray::spawn(coordinate, norm, direction).with_time(ray.time)
+                    }
+                    _ => Ray::spawn(coordinate, norm, glm::reflect(ray.direction, facing_norm)).with_time(ray.time),
+                };
+                depth += 1;
+                bounce_pdf = None;
+                continue;
+            }
+
+            // Reflection and diffuse scattering are mixed by picking one of them at random,
+            // the probability equals the weight so the throughput stays untouched.
+            if material.reflectivity() > 0.0 && sampler.get_1d() < material.reflectivity() {
+                ray = Ray::spawn(coordinate, norm, glm::reflect(ray.direction, norm)).with_time(ray.time);
+                depth += 1;
+                bounce_pdf = None;
+                continue;
+            }
+
+            // Always scatter on the side of the surface the ray arrived from.
+            let facing_norm = if glm::dot(ray.direction, norm) > 0.0 { norm * -1.0 } else { norm };
+
+            // With cosine-weighted sampling the cosine term and the pdf cancel out,
+            // leaving only the albedo of the surface.
+            throughput = throughput * to_path_color(&material.sample_diffuse(&intersection.texture_coordinates()), wavelengths);
+            if throughput.x <= 0.0 && throughput.y <= 0.0 && throughput.z <= 0.0 {
+                break;
+            }
+
+            for light in scene.lights() {
+                let sample = match light.sample(&coordinate) {
+                    Some(sample) => sample,
+                    None => continue,
+                };
+
+                let cos_theta = glm::dot(sample.direction, facing_norm);
+                if cos_theta > 0.0 {
+                    let shadow_ray = Ray::spawn(coordinate, facing_norm, sample.direction).with_time(ray.time);
+                    let visibility = light_transmittance(scene, shadow_ray, sample.distance, medium.clone(), sampler, wavelengths);
+
+                    let incoming = to_path_color(&sample.irradiance, wavelengths) * visibility * (cos_theta / PI);
+                    add_light(&mut components, depth + 1, throughput * incoming);
+                }
+            }
+
+            if let Some(environment) = scene.environment() {
+                let sample = environment.sample(sampler.get_2d());
+                let cos_theta = glm::dot(sample.direction, facing_norm);
+                if sample.pdf > 0.0 && cos_theta > 0.0 {
+                    let shadow_ray = Ray::spawn(coordinate, facing_norm, sample.direction).with_time(ray.time);
+                    let visibility = light_transmittance(scene, shadow_ray, f32::MAX, medium.clone(), sampler, wavelengths);
+                    let bsdf_pdf = cos_theta / PI;
+                    let weight = power_heuristic(sample.pdf, bsdf_pdf);
+
+                    // The albedo is already part of the throughput, what is left of the BSDF is 1 / pi
+                    let light = to_path_color(&sample.radiance, wavelengths) * visibility * (bsdf_pdf * weight / sample.pdf);
+                    add_light(&mut components, depth + 1, throughput * light);
+                }
+            }
+
+            let u = sampler.get_2d();
+            let direction = glm::normalize(to_world(cosine_sample_hemisphere(u.x, u.y), facing_norm));
+            ray = Ray::spawn(coordinate, facing_norm, direction).with_time(ray.time);
+            depth += 1;
+            bounce_pdf = Some(glm::dot(direction, facing_norm).max(0.0) / PI);
+        }
+
+        components
+    }
+}
+
+impl Integrator for PathTracingIntegrator {
+    fn radiance(&self, scene: &Arc<dyn Scene + Sync + Send>, camera_ray: Ray, sampler: &mut dyn Sampler) -> glm::Vec3 {
+        self.radiance_components(scene, camera_ray, sampler).total()
+    }
+
+    fn radiance_components(&self, scene: &Arc<dyn Scene + Sync + Send>, camera_ray: Ray, sampler: &mut dyn Sampler) -> RadianceComponents {
+        if !self.spectral {
+            return self.trace(scene, camera_ray, sampler, &mut None);
+        }
+
+        let mut wavelengths = Some(SampledWavelengths::sample_visible(sampler.get_1d()));
+        let components = self.trace(scene, camera_ray, sampler, &mut wavelengths);
+        let wavelengths = wavelengths.unwrap();
+
+        RadianceComponents {
+            direct: wavelengths.to_rgb(&components.direct),
+            indirect: wavelengths.to_rgb(&components.indirect),
+        }
+    }
+}
+
+// Light from every light and one direction towards the environment scattered along `ray` at its origin,
+// a point inside `medium`. The phase function takes the place of the BSDF, the throughput already holds the albedo.
+fn medium_direct_light(scene: &Arc<dyn Scene + Sync + Send>, ray: &Ray, medium: &Arc<Medium>, sampler: &mut dyn Sampler, wavelengths: &Option<SampledWavelengths>) -> glm::Vec3 {
+    let phase = medium.phase();
+    let mut result = glm::vec3(0.0, 0.0, 0.0);
+
+    for light in scene.lights() {
+        if let Some(sample) = light.sample(&ray.origin) {
+            let shadow_ray = Ray::new(ray.origin, sample.direction).with_time(ray.time);
+            let visibility = light_transmittance(scene, shadow_ray, sample.distance, Some(medium.clone()), sampler, wavelengths);
+
+            result = result + to_path_color(&sample.irradiance, wavelengths) * visibility * phase.evaluate(glm::dot(ray.direction, sample.direction));
+        }
+    }
+
+    if let Some(environment) = scene.environment() {
+        let sample = environment.sample(sampler.get_2d());
+        if sample.pdf > 0.0 {
+            let shadow_ray = Ray::new(ray.origin, sample.direction).with_time(ray.time);
+            let visibility = light_transmittance(scene, shadow_ray, f32::MAX, Some(medium.clone()), sampler, wavelengths);
+            let phase_pdf = phase.evaluate(glm::dot(ray.direction, sample.direction));
+            let weight = power_heuristic(sample.pdf, phase_pdf);
+
+            result = result + to_path_color(&sample.radiance, wavelengths) * visibility * (phase_pdf * weight / sample.pdf);
+        }
+    }
+
+    result
+}
+
+// Light arriving at a path vertex `depth` bounces deep, emission seen by the camera or found by the first bounce
+// is direct light and the rest bounced more often
+fn add_light(components: &mut RadianceComponents, depth: u32, light: glm::Vec3) {
+    if depth <= 1 {
+        components.direct = components.direct + light;
+    } else {
+        components.indirect = components.indirect + light;
+    }
+}
+
+fn to_path_color(color: &glm::Vec3, wavelengths: &Option<SampledWavelengths>) -> glm::Vec3 {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.spectrum_from_rgb(color),
+        None => *color,
+    }
+}
@@ -0,0 +1,92 @@
+use crate::renderer::sampler::Sampler;
+
+/// Decides when a path stops bouncing.
+/// Paths never go deeper than `max_depth`. After `min_depth` bounces Russian roulette may end a path early,
+/// with a survival probability that follows the throughput so dim paths are the ones that get cut.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PathTermination {
+    pub max_depth: u32,
+    pub min_depth: u32,
+    pub russian_roulette: bool,
+}
+
+impl PathTermination {
+    pub fn new() -> Self {
+        PathTermination {
+            max_depth: 8,
+            min_depth: 3,
+            russian_roulette: true,
+        }
+    }
+
+    /// Probability that a path with the given throughput survives bounce number `depth`.
+    pub fn survival_probability(&self, depth: u32, throughput: &glm::Vec3) -> f32 {
+        if depth >= self.max_depth {
+            return 0.0;
+        }
+
+        if !self.russian_roulette || depth < self.min_depth {
+            return 1.0;
+        }
+
+        // Never fully certain, otherwise bright paths between mirrors would bounce until max_depth
+        throughput.x.max(throughput.y).max(throughput.z).min(0.95)
+    }
+
+    /// Plays Russian roulette for bounce `depth`. Returns the throughput of the surviving path,
+    /// scaled up to make up for the paths that were terminated, or None if the path ends here.
+    pub fn next_throughput(&self, depth: u32, throughput: glm::Vec3, sampler: &mut dyn Sampler) -> Option<glm::Vec3> {
+        let probability = self.survival_probability(depth, &throughput);
+        if probability >= 1.0 {
+            return Some(throughput);
+        }
+
+        if probability <= 0.0 || sampler.get_1d() >= probability {
+            return None;
+        }
+
+        Some(throughput / probability)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sampler::independent::IndependentSampler;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn paths_should_always_survive_before_min_depth() {
+        let termination = PathTermination::new();
+
+        assert_eq!(termination.survival_probability(0, &glm::vec3(0.01, 0.01, 0.01)), 1.0);
+        assert_eq!(termination.survival_probability(2, &glm::vec3(0.01, 0.01, 0.01)), 1.0);
+        assert_eq!(termination.survival_probability(3, &glm::vec3(0.01, 0.2, 0.01)), 0.2);
+    }
+
+    #[test]
+    fn paths_should_never_exceed_max_depth() {
+        let termination = PathTermination { max_depth: 4, min_depth: 0, russian_roulette: false };
+        let mut sampler = IndependentSampler::new(StdRng::seed_from_u64(1));
+
+        assert!(termination.next_throughput(3, glm::vec3(1.0, 1.0, 1.0), &mut sampler).is_some());
+        assert!(termination.next_throughput(4, glm::vec3(1.0, 1.0, 1.0), &mut sampler).is_none());
+    }
+
+    #[test]
+    fn russian_roulette_should_preserve_expected_throughput() {
+        let termination = PathTermination { max_depth: 100, min_depth: 0, russian_roulette: true };
+        let mut sampler = IndependentSampler::new(StdRng::seed_from_u64(1));
+
+        let trials = 100000;
+        let mut sum = 0.0;
+        for _ in 0..trials {
+            if let Some(throughput) = termination.next_throughput(5, glm::vec3(0.25, 0.1, 0.1), &mut sampler) {
+                sum += throughput.x;
+            }
+        }
+
+        assert!((sum / trials as f32 - 0.25).abs() < 0.01);
+    }
+}
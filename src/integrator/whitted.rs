@@ -0,0 +1,186 @@
+use crate::integrator::{Integrator, light_transmittance, trace_shadow_ray};
+use crate::scene::{Scene, SurfaceDescription};
+use crate::core::Ray;
+use crate::core::math::lerp;
+use crate::core::optics::{fresnel_dielectric, refract, transmittance};
+use crate::renderer::sampler::Sampler;
+use crate::content::medium::Medium;
+use crate::scene::light::{Light, DirectionalLight};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Direct light from every light, a single randomly chosen emissive surface and one direction towards
+/// the environment, plus perfect reflection and refraction.
+/// Reflected and refracted rays are followed up to `depth_limit` surfaces deep.
+/// Media only absorb and scatter light away, they never scatter light towards the camera.
+/// Scenes without any light are lit from straight above, so they don't render black.
+pub struct WhittedIntegrator {
+    depth_limit: u32,
+    fallback_light: DirectionalLight,
+}
+
+impl WhittedIntegrator {
+    pub fn new(depth_limit: u32) -> Self {
+        WhittedIntegrator {
+            depth_limit,
+            // A white surface facing up comes out in its full diffuse color
+            fallback_light: DirectionalLight::new(glm::vec3(0.0, -1.0, 0.0), glm::vec3(PI, PI, PI)),
+        }
+    }
+
+    // None when the scene has no emissive entities
+    fn pick_light(&self, scene: &Arc<dyn Scene + Sync + Send>, time: f32, sampler: &mut dyn Sampler) -> Option<SurfaceDescription> {
+        let emissive_entities = scene.get_emissive_entities();
+        if emissive_entities.is_empty() {
+            None
+        }
+        else {
+            let random_emissive_entity =
+                emissive_entities[((sampler.get_1d() * emissive_entities.len() as f32) as usize).min(emissive_entities.len() - 1)];
+
+            Some(random_emissive_entity.get_random_emissive_surface(time, sampler))
+        }
+    }
+
+    // `medium` is the medium the ray travels through, light reaching the ray origin is attenuated by it.
+    // `light` is the point picked on an emissive surface, `lights` are all lights without geometry.
+    fn shade(&self, scene: &Arc<dyn Scene + Sync + Send>, ray: &Ray, medium: Option<&Arc<Medium>>, light: &Option<SurfaceDescription>, lights: &[&dyn Light], depth_limit: u32, sampler: &mut dyn Sampler) -> glm::Vec3 {
+        if depth_limit == 0 {
+            return glm::vec3(0.0, 0.0, 0.0);
+        }
+
+        let intersection = scene.find_intersection(ray);
+        let attenuation = match medium {
+            Some(medium) => medium.transmittance(ray, intersection.as_ref().map_or(f32::MAX, |x| x.distance()), sampler),
+            None => glm::vec3(1.0, 1.0, 1.0),
+        };
+
+        attenuation * match intersection {
+            None => {
+                match scene.environment() {
+                    Some(environment) => environment.radiance(&ray.direction),
+                    None => glm::vec3(0.0, 0.0, 0.0),
+                }
+            }
+            Some(intersection) => {
+                let coordinate = intersection.coordinate();
+                let norm = intersection.world_space_normal();
+
+                let is_entering = glm::dot(ray.direction, norm) < 0.0;
+                let medium_behind = if is_entering { intersection.material().interior_medium() } else { scene.medium() };
+
+                if intersection.material().is_medium_boundary() {
+                    let continued_ray = Ray::spawn(coordinate, norm, ray.direction).with_time(ray.time);
+
+                    return attenuation * self.shade(scene, &continued_ray, medium_behind, light, lights, depth_limit, sampler);
+                }
+
+                if intersection.material().transparent() {
+                    let material = intersection.material();
+                    let norm = glm::normalize(norm);
+                    let mut refractive_index1 = 1.0;
+                    let mut refractive_index2 = material.refractive_index();
+                    if !is_entering {
+                        std::mem::swap(&mut refractive_index1, &mut refractive_index2);
+                    }
+
+                    // Blend reflection and refraction by the Fresnel reflectance.
+                    // With total internal reflection there is no refracted ray and everything is reflected.
+                    let facing_norm = if is_entering { norm } else { norm * -1.0 };
+                    let reflectance = fresnel_dielectric(glm::dot(ray.direction, facing_norm), refractive_index1, refractive_index2);
+
+                    let reflected_ray = Ray::spawn(coordinate, norm, glm::reflect(ray.direction, facing_norm)).with_time(ray.time);
+                    let reflected = self.shade(scene, &reflected_ray, medium, light, lights, depth_limit - 1, sampler);
+
+                    let refracted = match refract(ray.direction, facing_norm, refractive_index1 / refractive_index2) {
+                        Some(direction) => {
+                            let refracted_ray = Ray::spawn(coordinate, norm, direction).with_time(ray.time);
+
+                            self.shade(scene, &refracted_ray, medium_behind, light, lights, depth_limit - 1, sampler)
+                        }
+                        None => glm::vec3(0.0, 0.0, 0.0),
+                    };
+
+                    let color = lerp(refracted, reflected, reflectance);
+
+                    // Light reaching us from inside the material was absorbed on its way here
+                    return attenuation * if is_entering {
+                        color
+                    } else {
+                        color * transmittance(material.absorption(), intersection.distance())
+                    };
+                }
+
+                let mut reflected = glm::vec3(0.0, 0.0, 0.0);
+                if intersection.material().reflectivity() > 0.0 {
+                    let reflected_dir =
+                        glm::reflect(ray.direction, glm::normalize(norm));
+
+                    let reflected_ray = Ray::spawn(coordinate, norm, reflected_dir).with_time(ray.time);
+
+                    reflected = self.shade(scene, &reflected_ray, medium, light, lights, depth_limit - 1, sampler);
+                }
+
+                let diffuse = intersection.material().sample_diffuse(&intersection.texture_coordinates());
+                let mut direct_light = glm::vec3(0.0, 0.0, 0.0);
+
+                if let Some(light) = light {
+                    let shadow_ray = Ray::spawn(coordinate, norm, glm::normalize(light.coordinate - coordinate)).with_time(ray.time);
+
+                    if intersection.entity_id() != light.entity_id {
+                        if let (shadow_attenuation, Some(light_intersection)) = trace_shadow_ray(scene, shadow_ray, f32::MAX, medium.cloned(), sampler, &None) {
+                            if light_intersection.entity_id() == light.entity_id {
+                                // TODO: Should check that collision is close to light.coordinate too!!
+                                direct_light = direct_light + *light_intersection.material().emission() * shadow_attenuation;
+                            }
+                        }
+                    }
+                }
+
+                let facing_norm = glm::normalize(if glm::dot(ray.direction, norm) > 0.0 { norm * -1.0 } else { norm });
+                for light in lights {
+                    let sample = match light.sample(&coordinate) {
+                        Some(sample) => sample,
+                        None => continue,
+                    };
+
+                    let cos_theta = glm::dot(sample.direction, facing_norm);
+                    if cos_theta > 0.0 {
+                        let shadow_ray = Ray::spawn(coordinate, facing_norm, sample.direction).with_time(ray.time);
+                        let shadow_attenuation = light_transmittance(scene, shadow_ray, sample.distance, medium.cloned(), sampler, &None);
+                        direct_light = direct_light + sample.irradiance * shadow_attenuation * (cos_theta / PI);
+                    }
+                }
+
+                if let Some(environment) = scene.environment() {
+                    let sample = environment.sample(sampler.get_2d());
+                    let cos_theta = glm::dot(sample.direction, facing_norm);
+                    if sample.pdf > 0.0 && cos_theta > 0.0 {
+                        let shadow_ray = Ray::spawn(coordinate, facing_norm, sample.direction).with_time(ray.time);
+                        let shadow_attenuation = light_transmittance(scene, shadow_ray, f32::MAX, medium.cloned(), sampler, &None);
+                        // Lambertian reflection of light from one direction, the diffuse color is applied below
+                        direct_light = direct_light + sample.radiance * shadow_attenuation * (cos_theta / (PI * sample.pdf));
+                    }
+                }
+
+                lerp(
+                    *intersection.material().emission() + (diffuse * direct_light),
+                    reflected,
+                    intersection.material().reflectivity())
+            }
+        }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(&self, scene: &Arc<dyn Scene + Sync + Send>, ray: Ray, sampler: &mut dyn Sampler) -> glm::Vec3 {
+        let light = self.pick_light(scene, ray.time, sampler);
+
+        let mut lights: Vec<&dyn Light> = scene.lights().iter().map(|x| x.as_ref() as &dyn Light).collect();
+        if lights.is_empty() && light.is_none() && scene.environment().is_none() {
+            lights.push(&self.fallback_light);
+        }
+
+        self.shade(scene, &ray, scene.medium(), &light, &lights, self.depth_limit, sampler)
+    }
+}
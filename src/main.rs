@@ -19,52 +19,167 @@ use crate::content::wavefront_model_loader::{WaveFrontObjectLoader};
 use std::sync::Arc;
 use crate::content::store::ModelStore;
 use crate::scene::sphere_entity::SphereEntity;
-use std::fs::File;
-use crate::render_configuration::parser::parse;
-use crate::frame_interpolator::FrameInterpolator;
+use crate::render_configuration::parser::{parse, referenced_files};
+use crate::render_configuration::RenderConfiguration;
+use crate::render_configuration::scene_builder::build_scene;
 use crate::camera::Camera;
-use crate::renderer::{render};
-use rand::SeedableRng;
+use crate::renderer::{render, accumulate, ImageBuffer};
+use crate::renderer::settings::RenderSettings;
+use crate::renderer::filter::Filter;
+use crate::renderer::adaptive::{AdaptiveSampling, sample_count_heatmap};
+use crate::renderer::aov::{aov_path, aov_display_transform, displayable_aov, AovType};
+use crate::renderer::display::DisplayTransform;
+use crate::output::ImageFormat;
+use crate::renderer::denoise::{Denoiser, GUIDE_AOVS};
+use crate::renderer::checkpoint::{render_with_checkpoints, SettingsHash};
+use std::time::Duration;
+use crate::integrator::{create_integrator, IntegratorType};
 use crate::content::material_builder::MaterialBuilder;
 use crate::scene::transform_builder::TransformBuilder;
 use crate::scene::plane_entity::PlaneEntity;
 use crate::core::plane::Plane;
+use crate::command_line::{parse_arguments, usage, CommandLineOptions};
+use crate::animation::{frame_count, frame_path, frame_time, FrameRange};
 
 mod content;
 mod renderer;
+mod integrator;
 mod scene;
 mod core;
 mod render_configuration;
 mod frame_processor;
 mod camera;
 mod color;
+mod command_line;
+mod output;
+mod animation;
+
+const INITIAL_ANGLE: f32 = 3.1415 + 0.8;
 
 fn next_id(id: &mut u32) -> u32 {
     *id = *id + 1;
     *id
 }
 
+fn create_scene(angle: f32, bob: f32) -> Arc<dyn Scene + Sync + Send> {
+    let mut id = 0;
+    let entities: Vec<Box<dyn SceneEntity + Sync + Send>> = vec![
+        // Floor
+        Box::new(PlaneEntity::new(
+            next_id(&mut id),
+            Plane::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
+            10.0,
+            MaterialBuilder::new()
+                .with_id(1)
+                .with_diffuse_color(glm::vec3(1.0, 1.0, 1.0))
+                .build(),
+            TransformBuilder::new()
+                .build(),
+        )),
+
+        // Diffuse ball
+        Box::new(SphereEntity::new(
+            next_id(&mut id),
+            1.0,
+            MaterialBuilder::new()
+                .with_id(2)
+                .with_diffuse_color(glm::vec3(0.5, 0.5, 1.0))
+                // .with_transparency(1.69)
+                // .with_reflectivity(1.0)
+                .build(),
+            TransformBuilder::new()
+                .with_translation(glm::vec3(glm::sin(angle) * 3.0, 3.0 + glm::sin(bob), glm::cos(angle) * 3.0))
+                .build(),
+        )),
+
+        // Diffuse ball
+        Box::new(SphereEntity::new(
+            next_id(&mut id),
+            1.0,
+            MaterialBuilder::new()
+                .with_id(3)
+                .with_diffuse_color(glm::vec3(0.5, 0.5, 1.0))
+                .with_emissive_color(glm::vec3(1.0, 1.0, 1.0))
+                .build(),
+            TransformBuilder::new()
+                .with_translation(glm::vec3(0.0, 3.0, 0.0))
+                .build(),
+        )),
+    ];
+
+    Arc::new(scene::octree_scene::Octree::create(entities, 4))
+}
+
+/// The scene `time` seconds into the animation, with the shutter open from then until `shutter_speed` seconds later.
+/// Scene files that don't declare any entities get the built-in demo scene.
+fn create_frame_scene(config: &RenderConfiguration, time: f64) -> Arc<dyn Scene + Sync + Send> {
+    if config.entities.is_empty() {
+        return create_scene(INITIAL_ANGLE, (time * 3.0) as f32);
+    }
+
+    match build_scene(config, time, time + config.shutter_speed) {
+        Ok(scene) => Arc::new(scene),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn create_camera(resolution: &glm::Vector2<u32>) -> Camera {
+    let mut camera = Camera::new();
+    let camera_position = glm::vec3(0.0, 2.5, -15.0) * 1.0;
+    let camera_target = glm::vec3(0.0, 0.0, 0.0);
+    camera.set_position(camera_position);
+    camera.set_direction(glm::normalize(camera_target - camera_position));
+    camera.set_resolution(*resolution);
+    camera.update();
+
+    camera
+}
+
 fn main() {
-    let mut rng = rand::rngs::StdRng::seed_from_u64(123);
-    let sdl = sdl2::init().unwrap();
-    let window = window::Window::create(&sdl).unwrap();
+    let options = match parse_arguments(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, usage());
+            std::process::exit(1);
+        }
+    };
 
     let loader = WaveFrontObjectLoader {};
     let mut _store = ModelStore::new(Box::new(loader));
-    // let args = std::env::args();
-    // Input path should be taken from args
     //let mut config = ConfigurationParser{}.parse("/Users/emil/code/rust-rt/src/test.json");
     // let keyframe = &b.keyframes()[0];
     // let updates = &keyframe.updates()[0];
 
-    let f = File::open("/Users/emil/code/rust-rt/assets/scene.json").unwrap();
+    let scene_text = match std::fs::read(&options.scene_path) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Could not open scene '{}': {}", options.scene_path, e);
+            std::process::exit(1);
+        }
+    };
 
-    let config = parse(f).unwrap();
-    let interpolator = FrameInterpolator::new(&config.keyframes);
+    let config = match parse(scene_text.as_slice()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Could not read scene '{}': {}", options.scene_path, e);
+            std::process::exit(1);
+        }
+    };
 
-    let number_of_frames = (config.duration * config.frames_per_second as f64) as usize;
-    let seconds_per_frame = 1.0 / config.frames_per_second as f64;
-    let samples_per_frame = glm::floor(seconds_per_frame / config.shutter_speed) as usize;
+    // Checkpoints are tied to the exact scene file they were rendered from and the files it loads
+    let mut scene_hash = SettingsHash::new().add(&scene_text);
+    for path in referenced_files(&scene_text) {
+        match std::fs::read(&path) {
+            Ok(bytes) => scene_hash = scene_hash.add(path.as_bytes()).add(&bytes),
+            Err(e) => {
+                eprintln!("Could not read '{}': {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
 
     /*let mut apricot1 = ModelEntity::new(
         store.load("apricot", "/Users/emil/code/rust-rt/assets/models/apricot/Apricot_02_hi_poly.obj")
@@ -87,9 +202,254 @@ fn main() {
     light.set_position(glm::vec3(0.0, 5.0, 0.0));
     light.set_scale(glm::vec3(0.01, 0.01, 0.01));*/
 
+    let mut render_settings = config.render_settings.clone();
+    if let Some(threads) = options.threads {
+        render_settings.thread_count = threads as usize;
+    }
+    if let Some(tile_size) = options.tile_size {
+        render_settings.tile_size = tile_size as usize;
+    }
+    if let Some(tile_order) = options.tile_order {
+        render_settings.tile_order = tile_order;
+    }
+    if let Some(filter_type) = options.filter {
+        render_settings.filter = Filter::new(filter_type, filter_type.default_radius());
+    }
+    if let Some(filter_radius) = options.filter_radius {
+        render_settings.filter.radius = filter_radius;
+    }
+    if let Some(max_depth) = options.max_depth {
+        render_settings.path_termination.max_depth = max_depth;
+        render_settings.path_termination.min_depth = render_settings.path_termination.min_depth.min(max_depth);
+    }
+    if let Some(aovs) = &options.aovs {
+        render_settings.aovs = aovs.clone();
+    }
+    if let Some(exposure) = options.exposure {
+        render_settings.display.exposure = exposure;
+    }
+    if let Some(seed) = options.seed {
+        render_settings.seed = seed;
+    }
+    if let Some(sampler) = options.sampler {
+        render_settings.sampler = sampler;
+    }
+    if options.spectral {
+        render_settings.spectral = true;
+    }
+    if let Some(tone_mapping) = options.tone_mapping {
+        render_settings.display.tone_mapping = tone_mapping;
+    }
+    if options.denoise && render_settings.denoiser.is_none() {
+        render_settings.denoiser = Some(Denoiser::new());
+    }
+    if let Some(threshold) = options.adaptive_threshold {
+        let mut adaptive = render_settings.adaptive.unwrap_or(AdaptiveSampling::new(threshold));
+        adaptive.threshold = threshold;
+        render_settings.adaptive = Some(adaptive);
+    }
+    if let (Some(max_samples), Some(adaptive)) = (options.max_samples, render_settings.adaptive.as_mut()) {
+        adaptive.max_samples = max_samples;
+        adaptive.min_samples = adaptive.min_samples.min(max_samples);
+    }
+
+    match &options.output_path {
+        Some(output_path) => {
+            if render_settings.spectral && options.integrator != IntegratorType::PathTracing {
+                eprintln!("The scene asks for spectral rendering, but only the path tracer renders spectrally: rendering RGB");
+            }
+            if let Some(samples) = options.samples {
+                render_settings.samples = samples;
+                if let Some(adaptive) = render_settings.adaptive.as_mut() {
+                    adaptive.min_samples = samples.min(adaptive.max_samples);
+                }
+            }
+
+            run_headless(&options, &config, scene_hash, &render_settings, output_path)
+        }
+        None => {
+            // Keep the viewer responsive unless asked for more samples explicitly
+            render_settings.samples = options.samples.unwrap_or(2);
+            // The viewer only shows the beauty image
+            render_settings.aovs.clear();
+
+            run_viewer(&config, &render_settings)
+        }
+    }
+}
+
+fn run_headless(options: &CommandLineOptions, config: &RenderConfiguration, scene_hash: SettingsHash, render_settings: &RenderSettings, output_path: &str) {
+    if !options.is_animation() {
+        render_frame(options, config, scene_hash, render_settings, 0, output_path, options.heatmap_path.as_deref(), options.checkpoint_path.as_deref());
+        return;
+    }
+
+    let number_of_frames = frame_count(config.duration, config.frames_per_second);
+    if number_of_frames == 0 {
+        eprintln!("The animation is shorter than a single frame");
+        std::process::exit(1);
+    }
+
+    let range = options.frames.unwrap_or(FrameRange::new(0, number_of_frames - 1));
+    if range.last >= number_of_frames {
+        eprintln!("Frame {} is outside the animation, it has {} frames (0-{})", range.last, number_of_frames, number_of_frames - 1);
+        std::process::exit(1);
+    }
+
+    for frame in range.first..=range.last {
+        let path = frame_path(output_path, frame);
+        let heatmap_path = options.heatmap_path.as_ref().map(|x| frame_path(x, frame));
+        if options.skip_existing && frame_outputs(&path, &render_settings.aovs, heatmap_path.as_deref()).iter().all(|x| std::path::Path::new(x).exists()) {
+            println!("Skipping frame {}, {} already exists", frame, path);
+            continue;
+        }
+
+        println!("Rendering frame {} ({} of {})", frame, frame - range.first + 1, range.last - range.first + 1);
+        let checkpoint_path = options.checkpoint_path.as_ref().map(|x| frame_path(x, frame));
+        render_frame(options, config, scene_hash, render_settings, frame, &path, heatmap_path.as_deref(), checkpoint_path.as_deref());
+    }
+}
+
+// Every file `render_frame` writes for a frame, a frame is only done once all of them are there
+fn frame_outputs(output_path: &str, aovs: &[AovType], heatmap_path: Option<&str>) -> Vec<String> {
+    let mut paths = vec![output_path.to_string()];
+    if !ImageFormat::from_path(output_path).supports_layers() {
+        paths.extend(aovs.iter().map(|aov| aov_path(output_path, *aov)));
+    }
+    paths.extend(heatmap_path.map(|x| x.to_string()));
+
+    paths
+}
+
+fn render_frame(options: &CommandLineOptions, config: &RenderConfiguration, scene_hash: SettingsHash, render_settings: &RenderSettings, frame: usize, output_path: &str, heatmap_path: Option<&str>, checkpoint_path: Option<&str>) {
+    // Every frame gets its own random streams, so rendering part of an animation gives the same frames as rendering all of it
+    let mut render_settings = render_settings.clone();
+    render_settings.frame = frame as u32;
+    let render_settings = &render_settings;
+    let resolution = glm::Vector2::<u32>::new(options.width, options.height);
+
+    let scene = create_frame_scene(config, frame_time(frame, config.frames_per_second));
+    let camera = create_camera(&resolution);
+    let integrator = create_integrator(options.integrator, render_settings);
+
+    let image = match checkpoint_path {
+        Some(checkpoint_path) => {
+            let settings_hash = scene_hash
+                .add(format!("{}x{} {:?} {}", resolution.x, resolution.y, options.integrator, frame).as_bytes())
+                .add_settings(render_settings);
+            let interval = Duration::from_secs_f32(options.checkpoint_interval);
+
+            match render_with_checkpoints(&scene, &camera, &resolution, render_settings, &integrator, settings_hash.value(), checkpoint_path, interval) {
+                Ok(image) => image,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => render(
+            &scene,
+            &camera,
+            &resolution,
+            render_settings,
+            &integrator),
+    };
+
+    let denoised = render_settings.denoiser.map(|denoiser| denoiser.denoise(&image, render_settings.thread_count));
+    let beauty = denoised.as_ref().unwrap_or(&image);
+
+    // The image may hold more passes than were asked for, the denoiser needs some of its own
+    let format = ImageFormat::from_path(output_path);
+    if format.supports_layers() && !render_settings.aovs.is_empty() {
+        // The beauty image is a layer too, the file is written once with everything in it
+        let mut layers = vec![("", beauty)];
+        layers.extend(render_settings.aovs.iter().map(|aov| (aov.name(), image.aov(*aov).unwrap())));
+
+        if let Err(e) = output::save_layers(&layers, output_path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+
+        println!("Saved {} with {} passes", output_path, render_settings.aovs.len());
+    } else {
+        if let Err(e) = output::save_image(beauty, output_path, &render_settings.display) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+
+        println!("Saved {}", output_path);
+
+        for aov in &render_settings.aovs {
+            let aov_image = image.aov(*aov).unwrap();
+            let aov_path = aov_path(output_path, *aov);
+            let result = if format.is_high_dynamic_range() {
+                output::save_image(aov_image, &aov_path, &DisplayTransform::raw())
+            } else {
+                output::save_image(&displayable_aov(*aov, aov_image), &aov_path, &aov_display_transform(*aov, &render_settings.display))
+            };
+
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+
+            println!("Saved {}", aov_path);
+        }
+    }
+
+    if let Some(heatmap_path) = heatmap_path {
+        if let Err(e) = output::save_image(&sample_count_heatmap(&image), heatmap_path, &DisplayTransform::raw()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+
+        println!("Saved {}", heatmap_path);
+    }
+
+    // Everything is on disk, the checkpoint is only needed to add more samples later
+    if let Some(checkpoint_path) = checkpoint_path.filter(|_| !options.keep_checkpoint) {
+        if let Err(e) = std::fs::remove_file(checkpoint_path) {
+            eprintln!("Could not remove checkpoint '{}': {}", checkpoint_path, e);
+        }
+    }
+}
+
+// Everything the rendered image depends on, accumulated samples are thrown away when any of it changes.
+#[derive(Clone, PartialEq)]
+struct ViewState {
+    // Seconds into the animation
+    time: f64,
+    resolution: (u32, u32),
+    integrator: IntegratorType,
+    denoise: bool,
+}
+
+fn run_viewer(config: &RenderConfiguration, render_settings: &RenderSettings) {
+    let sdl = sdl2::init().unwrap();
+    let window = window::Window::create(&sdl).unwrap();
+
+    let mut state = ViewState {
+        time: 0.0,
+        resolution: (window.width(), window.height()),
+        integrator: IntegratorType::Whitted,
+        denoise: render_settings.denoiser.is_some(),
+    };
+    let denoiser = render_settings.denoiser.unwrap_or(Denoiser::new());
+    // Only changes how the image is shown, so it doesn't restart the accumulation
+    let mut display = render_settings.display;
+    // Space plays the animation, while it plays every frame starts over from scratch
+    let mut animate = false;
+
+    let mut accumulated_state: Option<ViewState> = None;
+    let mut accumulation = ImageBuffer::new(window.width() as usize, window.height() as usize);
+    let mut accumulated_samples = 0;
+    let mut scene = create_frame_scene(config, state.time);
+    let mut camera = create_camera(&glm::Vector2::<u32>::new(window.width(), window.height()));
+    let mut integrator = create_integrator(state.integrator, render_settings);
+
+    let pixels = vec![0u8; (window.width() * window.height() * 3) as usize];
+    let texture = GlTexture::from_pixels(window.width(), window.height(), &pixels).unwrap();
 
-    let mut angle = 3.1415 + 0.8;
-    let mut bob = 0.0f32;
     let mut event_pump = sdl.event_pump().unwrap();
     'running: loop {
         for event in event_pump.poll_iter() {
@@ -98,82 +458,60 @@ fn main() {
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running;
                 }
+                Event::KeyDown { keycode: Some(Keycode::Space), .. } => animate = !animate,
+                // Number keys switch between the beauty render and the debug views
+                Event::KeyDown { keycode: Some(Keycode::Num1), .. } => state.integrator = IntegratorType::Whitted,
+                Event::KeyDown { keycode: Some(Keycode::Num2), .. } => state.integrator = IntegratorType::PathTracing,
+                Event::KeyDown { keycode: Some(Keycode::Num3), .. } => state.integrator = IntegratorType::AmbientOcclusion,
+                Event::KeyDown { keycode: Some(Keycode::Num4), .. } => state.integrator = IntegratorType::Normal,
+                Event::KeyDown { keycode: Some(Keycode::Num5), .. } => state.integrator = IntegratorType::Depth,
+                Event::KeyDown { keycode: Some(Keycode::D), .. } => state.denoise = !state.denoise,
+                Event::KeyDown { keycode: Some(Keycode::Plus), .. } |
+                Event::KeyDown { keycode: Some(Keycode::Equals), .. } => display.exposure += 0.5,
+                Event::KeyDown { keycode: Some(Keycode::Minus), .. } => display.exposure -= 0.5,
+                Event::KeyDown { keycode: Some(Keycode::T), .. } => display.tone_mapping = display.tone_mapping.next(),
                 _ => {}
             }
         }
 
-        let mut id = 0;
-        let entities: Vec<Box<dyn SceneEntity + Sync + Send>> = vec![
-            // Floor
-            Box::new(PlaneEntity::new(
-                next_id(&mut id),
-                Plane::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
-                10.0,
-                MaterialBuilder::new()
-                    .with_diffuse_color(glm::vec3(1.0, 1.0, 1.0))
-                    .build(),
-                TransformBuilder::new()
-                    .build(),
-            )),
-
-            // Diffuse ball
-            Box::new(SphereEntity::new(
-                next_id(&mut id),
-                1.0,
-                MaterialBuilder::new()
-                    .with_diffuse_color(glm::vec3(0.5, 0.5, 1.0))
-                    // .with_transparency(1.69)
-                    // .with_reflectivity(1.0)
-                    .build(),
-                TransformBuilder::new()
-                    .with_translation(glm::vec3(glm::sin(angle) * 3.0, 3.0 + glm::sin(bob), glm::cos(angle) * 3.0))
-                    .build(),
-            )),
-
-            // Diffuse ball
-            Box::new(SphereEntity::new(
-                next_id(&mut id),
-                1.0,
-                MaterialBuilder::new()
-                    .with_diffuse_color(glm::vec3(0.5, 0.5, 1.0))
-                    .with_emissive_color(glm::vec3(1.0, 1.0, 1.0))
-                    .build(),
-                TransformBuilder::new()
-                    .with_translation(glm::vec3(0.0, 3.0, 0.0))
-                    .build(),
-            )),
-        ];
-        let scene2: Arc<dyn Scene + Sync + Send> = Arc::new(scene::octree_scene::Octree::create(entities, 4));
-
-        let mut camera = Camera::new();
-        let camera_position = glm::vec3(0.0, 2.5, -15.0) * 1.0;
-        let camera_target = glm::vec3(0.0, 0.0, 0.0);
-        camera.set_position(camera_position);
-        camera.set_direction(glm::normalize(camera_target - camera_position));
-        camera.set_resolution(glm::Vector2::new(window.width(), window.height()));
-
-        let mut pixels = vec![0u8; (window.width() * window.height() * 3) as usize];
-        let texture = GlTexture::from_pixels(window.width(), window.height(), &pixels).unwrap();
-        texture.bind();
+        if animate && config.duration > 0.0 {
+            state.time = (state.time + 1.0 / config.frames_per_second as f64) % config.duration;
+        }
+        state.resolution = (window.width(), window.height());
+
+        if accumulated_state.as_ref() != Some(&state) {
+            let resolution = glm::Vector2::<u32>::new(state.resolution.0, state.resolution.1);
+            scene = create_frame_scene(config, state.time);
+            camera = create_camera(&resolution);
+            integrator = create_integrator(state.integrator, render_settings);
+            // The denoiser needs its guide passes, only collect them while it is on
+            let aovs: &[AovType] = if state.denoise { &GUIDE_AOVS } else { &[] };
+            accumulation = ImageBuffer::with_aovs(resolution.x as usize, resolution.y as usize, aovs);
+            accumulated_samples = 0;
+            accumulated_state = Some(state.clone());
+        }
 
-        camera.update();
-        let image = render(
-            &scene2,
+        accumulate(
+            &scene,
             &camera,
-            &glm::Vector2::<u32>::new(window.width(), window.height()),
-            2,
-            &mut rng);
+            render_settings,
+            &integrator,
+            &mut accumulation,
+            &mut |_, _| {});
+        accumulated_samples += render_settings.samples;
 
+        let pixels = if state.denoise {
+            denoiser.denoise(&accumulation, render_settings.thread_count).pixels_u8(&display)
+        } else {
+            accumulation.pixels_u8(&display)
+        };
 
         window.clear();
-        texture.set_pixels(window.width(), window.height(), &image.pixels_u8());
+        texture.set_pixels(window.width(), window.height(), &pixels);
         texture.bind();
         window.render();
         window.swap();
 
-        println!("angle {}", angle);
-
-        //angle += 0.1;
-        bob += 0.1;
+        println!("{} samples per pixel", accumulated_samples);
     }
-}
\ No newline at end of file
+}
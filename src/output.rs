@@ -0,0 +1,109 @@
+use crate::renderer::ImageBuffer;
+use crate::renderer::display::DisplayTransform;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+pub mod exr;
+pub mod hdr;
+pub mod pfm;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    /// 8 bit display referred images, written with the image crate (png, jpg, bmp, ...)
+    Display,
+    OpenExr,
+    RadianceHdr,
+    Pfm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &str) -> ImageFormat {
+        let extension = std::path::Path::new(path).extension()
+            .and_then(|x| x.to_str())
+            .map(|x| x.to_lowercase());
+
+        match extension.as_deref() {
+            Some("exr") => ImageFormat::OpenExr,
+            Some("hdr") => ImageFormat::RadianceHdr,
+            Some("pfm") => ImageFormat::Pfm,
+            _ => ImageFormat::Display,
+        }
+    }
+
+    /// Floating point formats get the linear radiance, without exposure or tone mapping.
+    pub fn is_high_dynamic_range(&self) -> bool {
+        *self != ImageFormat::Display
+    }
+
+    pub fn supports_layers(&self) -> bool {
+        *self == ImageFormat::OpenExr
+    }
+}
+
+/// Saves the image in the format picked by the file extension, the display transform
+/// only applies to 8 bit formats.
+pub fn save_image(image: &ImageBuffer, path: &str, transform: &DisplayTransform) -> Result<(), String> {
+    match ImageFormat::from_path(path) {
+        ImageFormat::Display => save_display_image(image, path, transform),
+        ImageFormat::OpenExr => save_with(path, |writer| exr::write(writer, &[("", image)])),
+        ImageFormat::RadianceHdr => save_with(path, |writer| hdr::write(writer, image)),
+        ImageFormat::Pfm => save_with(path, |writer| pfm::write(writer, image)),
+    }
+}
+
+/// Saves several images of the same size into one multi-layer OpenEXR file,
+/// a layer with an empty name becomes the plain R, G and B channels.
+pub fn save_layers(layers: &[(&str, &ImageBuffer)], path: &str) -> Result<(), String> {
+    save_with(path, |writer| exr::write(writer, layers))
+}
+
+// Writes to a temporary file first and renames it, so an interrupted save never leaves a truncated image
+// behind that `--skip-existing` would take for a finished frame.
+fn save_with<F>(path: &str, write: F) -> Result<(), String>
+    where F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()> {
+    let temporary_path = format!("{}.tmp", path);
+    let result = File::create(&temporary_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()
+    }).and_then(|_| std::fs::rename(&temporary_path, path));
+
+    result.map_err(|e| format!("Could not save image to '{}': {}", path, e))
+}
+
+fn save_display_image(image: &ImageBuffer, path: &str, transform: &DisplayTransform) -> Result<(), String> {
+    let width = image.width();
+    let height = image.height();
+    let pixels = image.pixels_u8(transform);
+
+    // The image buffer is stored bottom row first (the way OpenGL expects it),
+    // image files want the top row first.
+    let row_size = width * 3;
+    let mut flipped = Vec::with_capacity(pixels.len());
+    for row in pixels.chunks(row_size).rev() {
+        flipped.extend_from_slice(row);
+    }
+
+    // The temporary file has no usable extension, so the format comes from the final path
+    let format = image::ImageFormat::from_path(path)
+        .map_err(|e| format!("Could not save image to '{}': {}", path, e))?;
+    let temporary_path = format!("{}.tmp", path);
+    image::save_buffer_with_format(&temporary_path, &flipped, width as u32, height as u32, image::ColorType::Rgb8, format)
+        .map_err(|e| e.to_string())
+        .and_then(|_| std::fs::rename(&temporary_path, path).map_err(|e| e.to_string()))
+        .map_err(|e| format!("Could not save image to '{}': {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_format_should_follow_extension() {
+        assert_eq!(ImageFormat::from_path("out/render.exr"), ImageFormat::OpenExr);
+        assert_eq!(ImageFormat::from_path("render.HDR"), ImageFormat::RadianceHdr);
+        assert_eq!(ImageFormat::from_path("render.pfm"), ImageFormat::Pfm);
+        assert_eq!(ImageFormat::from_path("render.png"), ImageFormat::Display);
+        assert_eq!(ImageFormat::from_path("render"), ImageFormat::Display);
+    }
+}
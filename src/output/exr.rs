@@ -0,0 +1,422 @@
+use crate::renderer::ImageBuffer;
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: u32 = 2;
+const PIXEL_TYPE_UINT: i32 = 0;
+const PIXEL_TYPE_HALF: i32 = 1;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_ZIPS: u8 = 2;
+const COMPRESSION_ZIP: u8 = 3;
+
+/// Writes an uncompressed scanline OpenEXR file with 32 bit float channels.
+/// Every layer adds R, G and B channels, prefixed with "<name>." unless the name is empty,
+/// so the beauty image and all passes fit in a single file. All layers must have the same size.
+pub fn write<W: Write>(writer: &mut W, layers: &[(&str, &ImageBuffer)]) -> std::io::Result<()> {
+    let width = layers[0].1.width();
+    let height = layers[0].1.height();
+
+    // EXR wants the channel list sorted by name, pixel data follows the same order
+    let mut channels: Vec<(String, &ImageBuffer, usize)> = Vec::new();
+    for (name, image) in layers {
+        assert!(image.width() == width && image.height() == height, "All EXR layers must have the same size");
+        for (component, suffix) in ["R", "G", "B"].iter().enumerate() {
+            let channel_name = if name.is_empty() { suffix.to_string() } else { format!("{}.{}", name, suffix) };
+            channels.push((channel_name, *image, component));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for (name, _, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear and three reserved bytes
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        // x and y sampling
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    write_attribute(&mut header, "channels", "chlist", &channel_list);
+
+    write_attribute(&mut header, "compression", "compression", &[COMPRESSION_NONE]);
+
+    let mut window = Vec::new();
+    for value in &[0i32, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+
+    // Increasing y
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+    write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    // Every scanline is a block of its own: y, data size, then one row per channel
+    let line_size = channels.len() * width * 4;
+    let block_size = 8 + line_size;
+    let first_block = header.len() + height * 8;
+
+    writer.write_all(&header)?;
+    for line in 0..height {
+        writer.write_all(&((first_block + line * block_size) as u64).to_le_bytes())?;
+    }
+
+    let mut block = Vec::with_capacity(block_size);
+    for line in 0..height {
+        block.clear();
+        block.extend_from_slice(&(line as i32).to_le_bytes());
+        block.extend_from_slice(&(line_size as i32).to_le_bytes());
+
+        // EXR starts at the top, the image buffer at the bottom
+        let y = height - 1 - line;
+        for (_, image, component) in &channels {
+            for x in 0..width {
+                let color = image.pixel(x, y);
+                let value = [color.x, color.y, color.z][*component];
+                block.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        writer.write_all(&block)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the R, G and B channels of a scanline OpenEXR file, uncompressed like the ones `write` produces
+/// or ZIP compressed like most downloaded HDRIs. Other compression methods (PIZ, RLE, lossy ones) are refused.
+/// Returns the width, the height and the pixels with the top row first. Other channels are skipped
+/// and missing ones read as zero.
+pub fn read<R: Read>(reader: &mut R) -> Result<(usize, usize, Vec<glm::Vec3>), String> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).map_err(|e| e.to_string())?;
+    let mut input = Input { data: &data, position: 0 };
+
+    if input.take(4)? != MAGIC {
+        return Err("Not an OpenEXR file".to_string());
+    }
+    // Tiled, long name, deep and multi-part files set flags in the upper version bits
+    if input.u32()? != VERSION {
+        return Err("Only single part scanline OpenEXR files are supported".to_string());
+    }
+
+    let mut channels = Vec::new();
+    let mut compression = None;
+    let mut window = None;
+    loop {
+        let name = input.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let _attribute_type = input.string()?;
+        let size = input.i32()? as usize;
+        let mut value = Input { data: input.take(size)?, position: 0 };
+
+        match name.as_str() {
+            "channels" => loop {
+                let channel = value.string()?;
+                if channel.is_empty() {
+                    break;
+                }
+                let pixel_type = value.i32()?;
+                value.take(4)?;
+                if value.i32()? != 1 || value.i32()? != 1 {
+                    return Err("Subsampled OpenEXR channels are not supported".to_string());
+                }
+                channels.push((channel, pixel_type));
+            },
+            "compression" => compression = Some(value.take(1)?[0]),
+            "dataWindow" => window = Some((value.i32()?, value.i32()?, value.i32()?, value.i32()?)),
+            _ => {}
+        }
+    }
+
+    // ZIPS compresses every scanline on its own, ZIP blocks of 16
+    let lines_per_block = match compression {
+        Some(COMPRESSION_NONE) | Some(COMPRESSION_ZIPS) => 1,
+        Some(COMPRESSION_ZIP) => 16,
+        Some(other) => return Err(format!("{} compressed OpenEXR files are not supported, only uncompressed, ZIP and ZIPS", compression_name(other))),
+        None => return Err("OpenEXR file without a compression".to_string()),
+    };
+    let (min_x, min_y, max_x, max_y) = window.ok_or("OpenEXR file without a data window")?;
+    let width = (max_x - min_x + 1).max(0) as usize;
+    let height = (max_y - min_y + 1).max(0) as usize;
+
+    // Channels are stored sorted by name, which the channel list already is
+    let component = |name: &str| match name {
+        "R" => Some(0),
+        "G" => Some(1),
+        "B" => Some(2),
+        _ => None,
+    };
+    let line_size: usize = channels.iter()
+        .map(|(_, pixel_type)| if *pixel_type == PIXEL_TYPE_HALF { 2 } else { 4 })
+        .sum::<usize>() * width;
+
+    let mut pixels = vec![glm::vec3(0.0, 0.0, 0.0); width * height];
+    let block_count = (height + lines_per_block - 1) / lines_per_block;
+    input.take(block_count * 8)?;
+    for _ in 0..block_count {
+        let first_line = (input.i32()? - min_y) as usize;
+        let size = input.i32()? as usize;
+        let data = input.take(size)?;
+        if first_line >= height {
+            return Err("OpenEXR scanline outside of the data window".to_string());
+        }
+
+        // A block that doesn't get smaller when compressed is stored as it is
+        let lines = lines_per_block.min(height - first_line);
+        let data = if compression != Some(COMPRESSION_NONE) && size < lines * line_size {
+            Cow::Owned(decompress_zip(data, lines * line_size)?)
+        } else {
+            Cow::Borrowed(data)
+        };
+
+        let mut block = Input { data: &data, position: 0 };
+        for line in first_line..first_line + lines {
+            for (name, pixel_type) in &channels {
+                for x in 0..width {
+                    let value = match *pixel_type {
+                        PIXEL_TYPE_UINT => block.u32()? as f32,
+                        PIXEL_TYPE_HALF => half_to_f32(block.u16()?),
+                        PIXEL_TYPE_FLOAT => f32::from_bits(block.u32()?),
+                        _ => return Err("Unknown OpenEXR pixel type".to_string()),
+                    };
+
+                    if let Some(component) = component(name) {
+                        pixels[line * width + x][component] = value;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok((width, height, pixels))
+}
+
+// A ZIP block is a zlib stream of the differences between neighbouring bytes,
+// with the even bytes of the data moved into the first half and the odd ones into the second
+fn decompress_zip(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = decompress_to_vec_zlib(data).map_err(|_| "Corrupt ZIP block in OpenEXR file".to_string())?;
+    if bytes.len() != size {
+        return Err("ZIP block of an OpenEXR file has the wrong size".to_string());
+    }
+
+    let mut previous = 0u8;
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        if index > 0 {
+            *byte = previous.wrapping_add(*byte).wrapping_sub(128);
+        }
+        previous = *byte;
+    }
+
+    let (even, odd) = bytes.split_at((size + 1) / 2);
+    Ok((0..size).map(|index| if index % 2 == 0 { even[index / 2] } else { odd[index / 2] }).collect())
+}
+
+fn compression_name(compression: u8) -> String {
+    match compression {
+        1 => "RLE".to_string(),
+        4 => "PIZ".to_string(),
+        5 => "PXR24".to_string(),
+        6 => "B44".to_string(),
+        7 => "B44A".to_string(),
+        8 => "DWAA".to_string(),
+        9 => "DWAB".to_string(),
+        _ => format!("Type {}", compression),
+    }
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.position + count > self.data.len() {
+            return Err("Unexpected end of OpenEXR file".to_string());
+        }
+
+        let result = &self.data[self.position..self.position + count];
+        self.position += count;
+        Ok(result)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.data[self.position..].iter().position(|x| *x == 0).ok_or("Unterminated string in OpenEXR header")?;
+        let bytes = self.take(length + 1)?;
+        Ok(String::from_utf8_lossy(&bytes[..length]).into_owned())
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2.0f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2.0f32.powi(exponent - 15),
+    }
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|x| x == needle)
+    }
+
+    #[test]
+    fn write_should_produce_a_valid_header() {
+        let image = ImageBuffer::new(3, 2);
+        let mut result = Vec::new();
+
+        write(&mut result, &[("", &image)]).unwrap();
+
+        assert_eq!(&result[0..4], &MAGIC);
+        assert_eq!(&result[4..8], &[2, 0, 0, 0]);
+        assert!(find(&result, b"channels\0chlist\0").is_some());
+        assert!(find(&result, b"compression\0compression\0\x01\0\0\0\0").is_some());
+    }
+
+    #[test]
+    fn read_should_return_what_write_wrote() {
+        let mut image = ImageBuffer::new(2, 2);
+        image.add_sample(0, 0, glm::vec3(1.0, 2.0, 3.0), 1.0);
+        image.add_sample(1, 1, glm::vec3(0.5, 0.25, 100.0), 1.0);
+        let mut file = Vec::new();
+        write(&mut file, &[("", &image), ("albedo", &image)]).unwrap();
+
+        let (width, height, pixels) = read(&mut file.as_slice()).unwrap();
+
+        assert_eq!((width, height), (2, 2));
+        // Top row first
+        assert_eq!(pixels[1], glm::vec3(0.5, 0.25, 100.0));
+        assert_eq!(pixels[2], glm::vec3(1.0, 2.0, 3.0));
+        assert_eq!(pixels[0], glm::vec3(0.0, 0.0, 0.0));
+    }
+
+    // The inverse of `decompress_zip`
+    fn compress_zip(data: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = data.iter().step_by(2).chain(data.iter().skip(1).step_by(2)).cloned().collect();
+        for index in (1..bytes.len()).rev() {
+            bytes[index] = bytes[index].wrapping_sub(bytes[index - 1]).wrapping_add(128);
+        }
+
+        miniz_oxide::deflate::compress_to_vec_zlib(&bytes, 6)
+    }
+
+    #[test]
+    fn read_should_decompress_zip_blocks() {
+        let mut image = ImageBuffer::new(3, 2);
+        image.add_sample(0, 0, glm::vec3(1.0, 2.0, 3.0), 1.0);
+        image.add_sample(2, 1, glm::vec3(0.5, 0.25, 100.0), 1.0);
+        let mut file = Vec::new();
+        write(&mut file, &[("", &image)]).unwrap();
+
+        // Both scanlines in one ZIP block
+        let header_size = find(&file, b"screenWindowWidth\0float\0\x04\0\0\0").unwrap() + 33;
+        let line_size = 3 * 3 * 4;
+        let mut raw = Vec::new();
+        for line in 0..2 {
+            let start = header_size + 2 * 8 + line * (8 + line_size) + 8;
+            raw.extend_from_slice(&file[start..start + line_size]);
+        }
+        let compressed = compress_zip(&raw);
+        assert!(compressed.len() < raw.len());
+
+        let mut zip_file = file[..header_size].to_vec();
+        let compression = find(&zip_file, b"compression\0compression\0\x01\0\0\0").unwrap() + 28;
+        zip_file[compression] = COMPRESSION_ZIP;
+        zip_file.extend_from_slice(&((header_size + 8) as u64).to_le_bytes());
+        zip_file.extend_from_slice(&0i32.to_le_bytes());
+        zip_file.extend_from_slice(&(compressed.len() as i32).to_le_bytes());
+        zip_file.extend_from_slice(&compressed);
+
+        let (_, _, pixels) = read(&mut zip_file.as_slice()).unwrap();
+        let (_, _, expected) = read(&mut file.as_slice()).unwrap();
+        assert_eq!(pixels, expected);
+
+        zip_file[compression] = 4;
+        assert!(read(&mut zip_file.as_slice()).unwrap_err().contains("PIZ"));
+    }
+
+    #[test]
+    fn half_to_f32_should_decode_normal_and_subnormal_values() {
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0001), 2.0f32.powi(-24));
+    }
+
+    #[test]
+    fn write_should_sort_channels_of_all_layers() {
+        let image = ImageBuffer::new(1, 1);
+        let mut result = Vec::new();
+
+        write(&mut result, &[("", &image), ("albedo", &image)]).unwrap();
+
+        let positions: Vec<usize> = ["B\0", "G\0", "R\0", "albedo.B\0", "albedo.G\0", "albedo.R\0"].iter()
+            .map(|x| find(&result, x.as_bytes()).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|x| x[0] < x[1]));
+    }
+
+    #[test]
+    fn write_should_store_top_row_first() {
+        let mut image = ImageBuffer::new(1, 2);
+        image.add_sample(0, 0, glm::vec3(1.0, 2.0, 3.0), 1.0);
+        image.add_sample(0, 1, glm::vec3(4.0, 5.0, 6.0), 1.0);
+        let mut result = Vec::new();
+
+        write(&mut result, &[("", &image)]).unwrap();
+
+        // Two blocks of y, size and B, G, R at the end of the file
+        let floats: Vec<f32> = result[result.len() - 40..].chunks(4)
+            .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect();
+        assert_eq!(&floats[2..5], &[6.0, 5.0, 4.0]);
+        assert_eq!(&floats[7..10], &[3.0, 2.0, 1.0]);
+    }
+}
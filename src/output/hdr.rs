@@ -0,0 +1,81 @@
+use crate::renderer::ImageBuffer;
+use std::io::Write;
+
+/// Writes a Radiance RGBE (.hdr) file with flat, not run length encoded scanlines.
+pub fn write<W: Write>(writer: &mut W, image: &ImageBuffer) -> std::io::Result<()> {
+    let width = image.width();
+    let height = image.height();
+
+    write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+
+    let mut line = Vec::with_capacity(width * 4);
+    for y in (0..height).rev() {
+        line.clear();
+        for x in 0..width {
+            line.extend_from_slice(&rgbe(image.pixel(x, y)));
+        }
+
+        writer.write_all(&line)?;
+    }
+
+    Ok(())
+}
+
+/// Shares one exponent between the three components, the largest one keeps 8 bits of mantissa.
+pub fn rgbe(color: glm::Vec3) -> [u8; 4] {
+    let max = color.x.max(color.y).max(color.z);
+    if max.is_nan() || max <= 1e-32 || !max.is_finite() {
+        return [0, 0, 0, 0];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / 2.0f32.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+
+    let scale = 256.0 / 2.0f32.powi(exponent);
+    let encode = |x: f32| (x.max(0.0) * scale).min(255.0) as u8;
+
+    [encode(color.x), encode(color.y), encode(color.z), (exponent + 128).clamp(0, 255) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(rgbe: [u8; 4]) -> glm::Vec3 {
+        let scale = 2.0f32.powi(rgbe[3] as i32 - 136);
+        glm::vec3(rgbe[0] as f32, rgbe[1] as f32, rgbe[2] as f32) * scale
+    }
+
+    #[test]
+    fn rgbe_should_encode_black_as_zero() {
+        assert_eq!(rgbe(glm::vec3(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(rgbe(glm::vec3(-1.0, 0.0, 0.0)), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rgbe_should_round_trip_within_mantissa_precision() {
+        for color in &[glm::vec3(1.0, 0.5, 0.25), glm::vec3(100.0, 3.0, 0.01), glm::vec3(0.001, 0.002, 0.003)] {
+            let result = decode(rgbe(*color));
+            let max = color.x.max(color.y).max(color.z);
+
+            assert!((result.x - color.x).abs() <= max / 128.0);
+            assert!((result.y - color.y).abs() <= max / 128.0);
+            assert!((result.z - color.z).abs() <= max / 128.0);
+        }
+    }
+
+    #[test]
+    fn write_should_produce_header_and_one_quadruple_per_pixel() {
+        let image = ImageBuffer::new(3, 2);
+        let mut result = Vec::new();
+
+        write(&mut result, &image).unwrap();
+
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 3\n";
+        assert!(result.starts_with(header));
+        assert_eq!(result.len(), header.len() + 3 * 2 * 4);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::renderer::ImageBuffer;
+use std::io::Write;
+
+/// Writes a little endian color Portable Float Map. PFM stores the bottom row first,
+/// the same way the image buffer does, so rows are written as they are.
+pub fn write<W: Write>(writer: &mut W, image: &ImageBuffer) -> std::io::Result<()> {
+    let width = image.width();
+    let height = image.height();
+
+    // A negative scale marks little endian data
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    let mut line = Vec::with_capacity(width * 12);
+    for y in 0..height {
+        line.clear();
+        for x in 0..width {
+            let color = image.pixel(x, y);
+            line.extend_from_slice(&color.x.to_le_bytes());
+            line.extend_from_slice(&color.y.to_le_bytes());
+            line.extend_from_slice(&color.z.to_le_bytes());
+        }
+
+        writer.write_all(&line)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_should_store_bottom_row_first() {
+        let mut image = ImageBuffer::new(1, 2);
+        image.add_sample(0, 0, glm::vec3(1.0, 2.0, 3.0), 1.0);
+        image.add_sample(0, 1, glm::vec3(4.0, 5.0, 6.0), 1.0);
+        let mut result = Vec::new();
+
+        write(&mut result, &image).unwrap();
+
+        let header = b"PF\n1 2\n-1.0\n";
+        assert!(result.starts_with(header));
+
+        let floats: Vec<f32> = result[header.len()..].chunks(4)
+            .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect();
+        assert_eq!(floats, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    }
+}
@@ -1,9 +1,14 @@
 use std::collections::HashMap;
 
 pub mod parser;
+pub mod scene_builder;
 
 // pub use self::parser::ConfigurationParser;
 use crate::content::material::Material;
+use crate::content::medium::Medium;
+use crate::content::environment_map::EnvironmentMap;
+use std::sync::Arc;
+use crate::renderer::settings::RenderSettings;
 
 pub struct RenderConfiguration {
     pub shutter_speed: f64,
@@ -11,12 +16,37 @@ pub struct RenderConfiguration {
     pub frames_per_second: i32,
     pub model_path_lookup: HashMap<String, String>,
     pub entities: HashMap<String, EntityType>,
+    pub materials: HashMap<String, Material>,
+    /// Medium filling the space between the entities.
+    pub fog: Option<Arc<Medium>>,
+    /// Light from far away, seen wherever rays leave the scene.
+    pub environment: Option<Arc<EnvironmentMap>>,
     pub keyframes: Vec<Frame>,
+    pub render_settings: RenderSettings,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EntityType {
     Sphere,
+    Plane,
     Model,
+    PointLight,
+    SpotLight,
+    DirectionalLight,
+}
+
+impl EntityType {
+    pub fn from_name(name: &str) -> Option<EntityType> {
+        match name {
+            "sphere" => Some(EntityType::Sphere),
+            "plane" => Some(EntityType::Plane),
+            "model" => Some(EntityType::Model),
+            "point_light" => Some(EntityType::PointLight),
+            "spot_light" => Some(EntityType::SpotLight),
+            "directional_light" => Some(EntityType::DirectionalLight),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -1,8 +1,25 @@
-use crate::render_configuration::{RenderConfiguration, Frame, PropertyChanges, PropertyValue};
+use crate::render_configuration::{RenderConfiguration, Frame, PropertyChanges, PropertyValue, EntityType};
+use crate::content::material::Material;
+use crate::content::material_builder::MaterialBuilder;
 use std::fs::File;
 use std::io::Read;
 use serde_json::{Value, Number, Map};
 use std::collections::HashMap;
+use crate::renderer::settings::RenderSettings;
+use crate::renderer::tiles::TileOrder;
+use crate::renderer::filter::{Filter, FilterType};
+use crate::renderer::sampler::SamplerType;
+use crate::core::optics::Dispersion;
+use crate::content::medium::{Medium, HenyeyGreenstein};
+use crate::content::density_grid::DensityGrid;
+use crate::content::environment_map::EnvironmentMap;
+use crate::content::sky::{Sky, sun_direction};
+use crate::core::geom::AABB;
+use std::sync::Arc;
+use crate::renderer::adaptive::AdaptiveSampling;
+use crate::renderer::aov::AovType;
+use crate::renderer::denoise::Denoiser;
+use crate::renderer::display::ToneMapping;
 
 fn get_f32(node: &Value) -> Option<f32> {
     match node.as_f64() {
@@ -11,6 +28,15 @@ fn get_f32(node: &Value) -> Option<f32> {
     }
 }
 
+fn get_vec3(node: &Value) -> Option<glm::Vec3> {
+    let values = node.as_array()?;
+    if values.len() != 3 {
+        return None;
+    }
+
+    Some(glm::vec3(get_f32(&values[0])?, get_f32(&values[1])?, get_f32(&values[2])?))
+}
+
 pub fn parse<R>(reader: R) -> Result<RenderConfiguration, &'static str>
     where
         R: Read
@@ -55,10 +81,506 @@ pub fn parse<R>(reader: R) -> Result<RenderConfiguration, &'static str>
         frames_per_second: scene["frames_per_second"].as_u64().unwrap() as i32,
         model_path_lookup: get_model_path_lookup(&root)?,
         keyframes: get_keyframes(&root)?,
-        entities: Default::default(),
+        entities: get_entities(&root)?,
+        materials: get_materials(&root)?,
+        fog: get_fog(&root)?,
+        environment: get_environment(&root)?,
+        render_settings: get_render_settings(&root)?,
     })
 }
 
+/// Paths of the files a scene loads besides itself, environment maps and density grids.
+/// Every one of them is given as a "file" entry.
+pub fn referenced_files(scene_text: &[u8]) -> Vec<String> {
+    fn collect(node: &Value, files: &mut Vec<String>) {
+        match node {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value.as_str()) {
+                        ("file", Some(path)) => files.push(path.to_string()),
+                        _ => collect(value, files),
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|x| collect(x, files)),
+            _ => {}
+        }
+    }
+
+    let mut files = Vec::new();
+    if let Ok(root) = serde_json::from_slice::<Value>(scene_text) {
+        collect(&root, &mut files);
+    }
+
+    files
+}
+
+fn get_render_settings(root_node: &Value) -> Result<RenderSettings, &'static str> {
+    let mut settings = RenderSettings::new();
+    let render_node = &root_node["render"];
+
+    // The whole 'render' object is optional, anything left out keeps its default
+    if render_node.is_null() {
+        return Ok(settings);
+    }
+
+    if !render_node.is_object() {
+        return Err("Expected 'render' to be an object");
+    }
+
+    if !render_node["samples"].is_null() {
+        match render_node["samples"].as_u64() {
+            Some(x) if x > 0 => settings.samples = x as u32,
+            _ => return Err("'samples' must be a positive whole number"),
+        }
+    }
+
+    if !render_node["threads"].is_null() {
+        match render_node["threads"].as_u64() {
+            Some(x) if x > 0 => settings.thread_count = x as usize,
+            _ => return Err("'threads' must be a positive whole number"),
+        }
+    }
+
+    if !render_node["seed"].is_null() {
+        match render_node["seed"].as_u64() {
+            Some(x) => settings.seed = x,
+            None => return Err("'seed' must be a whole number"),
+        }
+    }
+
+    if !render_node["tile_size"].is_null() {
+        match render_node["tile_size"].as_u64() {
+            Some(x) if x > 0 => settings.tile_size = x as usize,
+            _ => return Err("'tile_size' must be a positive whole number"),
+        }
+    }
+
+    if !render_node["tile_order"].is_null() {
+        match render_node["tile_order"].as_str().and_then(TileOrder::from_name) {
+            Some(x) => settings.tile_order = x,
+            None => return Err("'tile_order' must be one of 'scanline', 'spiral' or 'hilbert'"),
+        }
+    }
+
+    if !render_node["spectral"].is_null() {
+        match render_node["spectral"].as_bool() {
+            Some(x) => settings.spectral = x,
+            None => return Err("'spectral' must be true or false"),
+        }
+    }
+
+    if !render_node["sampler"].is_null() {
+        match render_node["sampler"].as_str().and_then(SamplerType::from_name) {
+            Some(x) => settings.sampler = x,
+            None => return Err("'sampler' must be one of 'independent', 'stratified', 'halton' or 'sobol'"),
+        }
+    }
+
+    if !render_node["filter"].is_null() {
+        match render_node["filter"].as_str().and_then(FilterType::from_name) {
+            Some(x) => settings.filter = Filter::new(x, x.default_radius()),
+            None => return Err("'filter' must be one of 'box', 'tent', 'gaussian' or 'mitchell'"),
+        }
+    }
+
+    if !render_node["filter_radius"].is_null() {
+        match get_f32(&render_node["filter_radius"]) {
+            Some(x) if x > 0.0 => settings.filter.radius = x,
+            _ => return Err("'filter_radius' must be a positive number"),
+        }
+    }
+
+    if !render_node["max_depth"].is_null() {
+        match render_node["max_depth"].as_u64() {
+            Some(x) if x > 0 => settings.path_termination.max_depth = x as u32,
+            _ => return Err("'max_depth' must be a positive whole number"),
+        }
+
+        // Only an explicit min_depth should be able to conflict with max_depth
+        settings.path_termination.min_depth = settings.path_termination.min_depth.min(settings.path_termination.max_depth);
+    }
+
+    if !render_node["min_depth"].is_null() {
+        match render_node["min_depth"].as_u64() {
+            Some(x) => settings.path_termination.min_depth = x as u32,
+            _ => return Err("'min_depth' must be a whole number"),
+        }
+    }
+
+    if settings.path_termination.min_depth > settings.path_termination.max_depth {
+        return Err("'min_depth' can not be larger than 'max_depth'");
+    }
+
+    if !render_node["russian_roulette"].is_null() {
+        match render_node["russian_roulette"].as_bool() {
+            Some(x) => settings.path_termination.russian_roulette = x,
+            _ => return Err("'russian_roulette' must be true or false"),
+        }
+    }
+
+    if !render_node["aovs"].is_null() {
+        let names = match render_node["aovs"].as_array() {
+            Some(x) => x,
+            None => return Err("'aovs' must be an array of pass names"),
+        };
+
+        settings.aovs = Vec::new();
+        for name in names {
+            match name.as_str().and_then(AovType::from_name) {
+                Some(x) => settings.aovs.push(x),
+                None => return Err("'aovs' can only contain 'depth', 'normal', 'albedo', 'entity_id', 'material_id', 'direct' and 'indirect'"),
+            }
+        }
+    }
+
+    if !render_node["denoise"].is_null() {
+        match render_node["denoise"].as_bool() {
+            Some(true) => settings.denoiser = Some(Denoiser::new()),
+            Some(false) => settings.denoiser = None,
+            None => return Err("'denoise' must be true or false"),
+        }
+    }
+
+    if !render_node["exposure"].is_null() {
+        match get_f32(&render_node["exposure"]) {
+            Some(x) => settings.display.exposure = x,
+            None => return Err("'exposure' must be a number"),
+        }
+    }
+
+    if !render_node["tone_mapping"].is_null() {
+        match render_node["tone_mapping"].as_str().and_then(ToneMapping::from_name) {
+            Some(x) => settings.display.tone_mapping = x,
+            None => return Err("'tone_mapping' must be one of 'clamp', 'reinhard' or 'aces'"),
+        }
+    }
+
+    if !render_node["adaptive"].is_null() {
+        settings.adaptive = Some(get_adaptive_sampling(&render_node["adaptive"])?);
+    }
+
+    Ok(settings)
+}
+
+fn get_adaptive_sampling(adaptive_node: &Value) -> Result<AdaptiveSampling, &'static str> {
+    if !adaptive_node.is_object() {
+        return Err("Expected 'adaptive' to be an object");
+    }
+
+    let mut adaptive = match get_f32(&adaptive_node["threshold"]) {
+        Some(x) if x > 0.0 => AdaptiveSampling::new(x),
+        _ => return Err("'threshold' must be a positive number"),
+    };
+
+    if !adaptive_node["min_samples"].is_null() {
+        match adaptive_node["min_samples"].as_u64() {
+            Some(x) if x > 0 => adaptive.min_samples = x as u32,
+            _ => return Err("'min_samples' must be a positive whole number"),
+        }
+    }
+
+    if !adaptive_node["max_samples"].is_null() {
+        match adaptive_node["max_samples"].as_u64() {
+            Some(x) if x > 0 => adaptive.max_samples = x as u32,
+            _ => return Err("'max_samples' must be a positive whole number"),
+        }
+    }
+
+    if adaptive.min_samples > adaptive.max_samples {
+        return Err("'min_samples' can not be larger than 'max_samples'");
+    }
+
+    Ok(adaptive)
+}
+
+fn get_materials(root_node: &Value) -> Result<HashMap<String, Material>, &'static str> {
+    let mut materials = HashMap::new();
+    let materials_node = &root_node["materials"];
+
+    if materials_node.is_null() {
+        return Ok(materials);
+    }
+
+    if !materials_node.is_array() {
+        return Err("Expected 'materials' to be an array");
+    }
+
+    for (index, material_node) in materials_node.as_array().unwrap().iter().enumerate() {
+        if !material_node.is_object() {
+            return Err("Expected 'materials' array to contain objects only");
+        }
+
+        let name = match material_node["name"].as_str() {
+            Some(x) => x.to_string(),
+            None => return Err("Material must contain a name"),
+        };
+
+        // Ids start at 1 in the order the materials are declared, 0 is left for materials nobody named
+        let mut builder = MaterialBuilder::new();
+        builder.with_id(index as u32 + 1);
+
+        if !material_node["diffuse"].is_null() {
+            match get_vec3(&material_node["diffuse"]) {
+                Some(x) => builder.with_diffuse_color(x),
+                None => return Err("'diffuse' must be an array of three numbers"),
+            };
+        }
+
+        if !material_node["emission"].is_null() {
+            match get_vec3(&material_node["emission"]) {
+                Some(x) => builder.with_emissive_color(x),
+                None => return Err("'emission' must be an array of three numbers"),
+            };
+        }
+
+        if !material_node["reflectivity"].is_null() {
+            match get_f32(&material_node["reflectivity"]) {
+                Some(x) if (0.0..=1.0).contains(&x) => builder.with_reflectivity(x),
+                _ => return Err("'reflectivity' must be a number between 0 and 1"),
+            };
+        }
+
+        if !material_node["refractive_index"].is_null() {
+            match get_f32(&material_node["refractive_index"]) {
+                Some(x) if x > 0.0 => builder.with_transparency(x),
+                _ => return Err("'refractive_index' must be a positive number"),
+            };
+        }
+
+        if let Some(dispersion) = get_dispersion(material_node)? {
+            builder.with_dispersion(dispersion);
+        }
+
+        if !material_node["medium"].is_null() {
+            builder.with_medium(get_medium(&material_node["medium"])?);
+        }
+
+        if !material_node["absorption"].is_null() {
+            match get_vec3(&material_node["absorption"]) {
+                Some(x) => builder.with_absorption_color(x),
+                None => return Err("'absorption' must be an array of three numbers"),
+            };
+        }
+
+        materials.insert(name, builder.build());
+    }
+
+    Ok(materials)
+}
+
+fn get_dispersion(material_node: &Value) -> Result<Option<Dispersion>, &'static str> {
+    let cauchy = &material_node["cauchy"];
+    let sellmeier = &material_node["sellmeier"];
+    let dispersion = match (cauchy.is_null(), sellmeier.is_null()) {
+        (true, true) => return Ok(None),
+        (false, false) => return Err("A material can have 'cauchy' or 'sellmeier' dispersion, not both"),
+        (false, true) => match (get_f32(&cauchy["a"]), get_f32(&cauchy["b"])) {
+            (Some(a), Some(b)) if a > 0.0 => Dispersion::Cauchy { a, b },
+            _ => return Err("'cauchy' must be an object with numbers 'a' (positive) and 'b'"),
+        },
+        (true, false) => match (get_vec3(&sellmeier["b"]), get_vec3(&sellmeier["c"])) {
+            (Some(b), Some(c)) => Dispersion::Sellmeier { b: [b.x, b.y, b.z], c: [c.x, c.y, c.z] },
+            _ => return Err("'sellmeier' must be an object with arrays of three numbers 'b' and 'c'"),
+        },
+    };
+
+    if !dispersion.is_valid() {
+        return Err("Dispersion must give a positive refractive index for every visible wavelength (360-830 nm)");
+    }
+
+    Ok(Some(dispersion))
+}
+
+fn get_fog(root_node: &Value) -> Result<Option<Arc<Medium>>, &'static str> {
+    let fog_node = &root_node["fog"];
+    if fog_node.is_null() {
+        return Ok(None);
+    }
+
+    Ok(Some(Arc::new(get_medium(fog_node)?)))
+}
+
+/// The environment is an object with the "file" of an equirectangular .hdr or .exr image
+/// (uncompressed or ZIP compressed, PIZ and the lossy EXR compressions are not supported),
+/// an optional "rotation" around the y axis in degrees and an optional "intensity" multiplier.
+/// Instead of an image the environment can be a "sky", see `get_sky`.
+fn get_environment(root_node: &Value) -> Result<Option<Arc<EnvironmentMap>>, &'static str> {
+    let environment_node = &root_node["environment"];
+    let sky_node = &root_node["sky"];
+    if !sky_node.is_null() {
+        if !environment_node.is_null() {
+            return Err("A scene can have an 'environment' or a 'sky', not both");
+        }
+
+        return Ok(Some(Arc::new(get_sky(sky_node)?)));
+    }
+
+    if environment_node.is_null() {
+        return Ok(None);
+    }
+
+    let path = match environment_node["file"].as_str() {
+        Some(path) => path,
+        None => return Err("'environment' must be an object with the 'file' of an image"),
+    };
+
+    let rotation = if environment_node["rotation"].is_null() {
+        0.0
+    } else {
+        get_f32(&environment_node["rotation"]).ok_or("The 'rotation' of the environment must be a number")?
+    };
+
+    let intensity = if environment_node["intensity"].is_null() {
+        1.0
+    } else {
+        match get_f32(&environment_node["intensity"]) {
+            Some(x) if x >= 0.0 => x,
+            _ => return Err("The 'intensity' of the environment must be a number, not negative"),
+        }
+    };
+
+    match EnvironmentMap::load(path, rotation, intensity) {
+        Ok(environment) => Ok(Some(Arc::new(environment))),
+        Err(e) => {
+            eprintln!("{}", e);
+            Err("Could not load the environment map")
+        }
+    }
+}
+
+/// The sky is an object with an optional "turbidity" (2 for a clear to 10 for a hazy sky), an optional
+/// "intensity" multiplier and where the sun is: either a "sun_direction" or the "latitude" in degrees,
+/// the "day" of the year and the solar "time" in hours.
+fn get_sky(sky_node: &Value) -> Result<EnvironmentMap, &'static str> {
+    if !sky_node.is_object() {
+        return Err("'sky' must be an object");
+    }
+
+    let turbidity = if sky_node["turbidity"].is_null() {
+        3.0
+    } else {
+        match get_f32(&sky_node["turbidity"]) {
+            Some(x) if (1.7..=10.0).contains(&x) => x,
+            _ => return Err("The 'turbidity' of the sky must be a number from 1.7 to 10"),
+        }
+    };
+
+    let intensity = if sky_node["intensity"].is_null() {
+        1.0
+    } else {
+        match get_f32(&sky_node["intensity"]) {
+            Some(x) if x >= 0.0 => x,
+            _ => return Err("The 'intensity' of the sky must be a number, not negative"),
+        }
+    };
+
+    let direction = if !sky_node["sun_direction"].is_null() {
+        match get_vec3(&sky_node["sun_direction"]) {
+            Some(x) if glm::length(x) > 0.0 => x,
+            _ => return Err("'sun_direction' must be an array of three numbers, not all zero"),
+        }
+    } else {
+        match (get_f32(&sky_node["latitude"]), get_f32(&sky_node["day"]), get_f32(&sky_node["time"])) {
+            (Some(latitude), Some(day), Some(time)) if latitude.abs() <= 90.0 => sun_direction(latitude, day, time),
+            _ => return Err("The sky needs a 'sun_direction' or the 'latitude', 'day' and 'time' to place the sun"),
+        }
+    };
+
+    Ok(Sky::new(turbidity, direction).to_environment_map(intensity))
+}
+
+/// A medium is an object with "absorption" and "scattering" coefficients per unit of distance,
+/// an optional "asymmetry" of the phase function and an optional "density" grid,
+/// given as {"file": path, "min": [x, y, z], "max": [x, y, z]} with the box it covers in world space.
+fn get_medium(medium_node: &Value) -> Result<Medium, &'static str> {
+    if !medium_node.is_object() {
+        return Err("A medium must be an object");
+    }
+
+    let mut coefficients = Vec::new();
+    for name in &["absorption", "scattering"] {
+        let node = &medium_node[*name];
+        coefficients.push(if node.is_null() {
+            glm::vec3(0.0, 0.0, 0.0)
+        } else {
+            match get_vec3(node) {
+                Some(x) if x.x >= 0.0 && x.y >= 0.0 && x.z >= 0.0 => x,
+                _ => return Err("The 'absorption' and 'scattering' of a medium must be arrays of three numbers, none negative"),
+            }
+        });
+    }
+
+    let asymmetry = if medium_node["asymmetry"].is_null() {
+        0.0
+    } else {
+        match get_f32(&medium_node["asymmetry"]) {
+            Some(x) if x > -1.0 && x < 1.0 => x,
+            _ => return Err("'asymmetry' must be a number between -1 and 1"),
+        }
+    };
+
+    let density_node = &medium_node["density"];
+    let density = if density_node.is_null() {
+        None
+    } else {
+        let path = density_node["file"].as_str();
+        let min = get_vec3(&density_node["min"]);
+        let max = get_vec3(&density_node["max"]);
+        match (path, min, max) {
+            (Some(path), Some(min), Some(max)) if min.x < max.x && min.y < max.y && min.z < max.z => {
+                match DensityGrid::load(path, AABB { min, max }) {
+                    Ok(grid) => Some(Arc::new(grid)),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return Err("Could not load the density grid of a medium");
+                    }
+                }
+            }
+            _ => return Err("'density' must be an object with a 'file' and the 'min' and 'max' corners of its box"),
+        }
+    };
+
+    Ok(Medium::new(coefficients[0], coefficients[1], HenyeyGreenstein::new(asymmetry), density))
+}
+
+fn get_entities(root_node: &Value) -> Result<HashMap<String, EntityType>, &'static str> {
+    let mut entities = HashMap::new();
+    let entities_node = &root_node["entities"];
+
+    if entities_node.is_null() {
+        return Ok(entities);
+    }
+
+    if !entities_node.is_array() {
+        return Err("Expected 'entities' to be an array");
+    }
+
+    for entity in entities_node.as_array().unwrap() {
+        // Each entity is an object with a single key naming its type, e.g. { "sphere": { "id": "ball" } }
+        let (type_name, entity_node) = match entity.as_object() {
+            Some(x) if x.len() == 1 => x.iter().next().unwrap(),
+            _ => return Err("Each entity must be an object with a single key naming its type, like 'sphere' or 'point_light'"),
+        };
+
+        let entity_type = match EntityType::from_name(type_name) {
+            Some(x) => x,
+            None => return Err("Entity type must be one of 'sphere', 'plane', 'model', 'point_light', 'spot_light' or 'directional_light'"),
+        };
+
+        let id = match entity_node["id"].as_str() {
+            Some(x) => x.to_string(),
+            None => return Err("Entity must contain an id"),
+        };
+
+        if entities.insert(id, entity_type).is_some() {
+            return Err("Entity ids must be unique");
+        }
+    }
+
+    Ok(entities)
+}
+
 fn get_model_path_lookup(root_node: &Value) -> Result<HashMap<String, String>, &'static str> {
     let models_node = &root_node["models"];
 
@@ -146,8 +668,7 @@ fn get_property_value(value: &Value) -> PropertyValue {
     match value {
         Value::String(x) => PropertyValue::String(x.clone()),
         Value::Number(x) => PropertyValue::Float(x.as_f64().unwrap() as f32),
-        Value::Array(x)if x.len() == 3 && x.iter().all(|y| y.is_f64())
-        => PropertyValue::Vec3(glm::vec3(x[0].as_f64().unwrap() as f32, x[1].as_f64().unwrap() as f32, x[2].as_f64().unwrap() as f32)),
+        Value::Array(_) if get_vec3(value).is_some() => PropertyValue::Vec3(get_vec3(value).unwrap()),
         _ => panic!("WHAT")
     }
 }
\ No newline at end of file
@@ -0,0 +1,207 @@
+use crate::render_configuration::{RenderConfiguration, EntityType, PropertyChanges, PropertyValue};
+use crate::frame_interpolator::FrameInterpolator;
+use crate::content::material::Material;
+use crate::content::material_builder::MaterialBuilder;
+use crate::scene::SceneEntity;
+use crate::scene::octree_scene::Octree;
+use crate::scene::sphere_entity::SphereEntity;
+use crate::scene::plane_entity::PlaneEntity;
+use crate::scene::transform::Transform;
+use crate::scene::transform_builder::TransformBuilder;
+use crate::scene::animated_transform::AnimatedTransform;
+use crate::core::plane::Plane;
+use crate::scene::light::{Light, PointLight, SpotLight, DirectionalLight};
+
+const OCTREE_DEPTH: usize = 4;
+
+/// Builds the scene as it is while the shutter is open, from `shutter_open` to `shutter_close` seconds.
+/// Entities move between the keyframed state at both ends of the interval, so rays traced at different
+/// times of the interval see them at different places.
+///
+/// Entities are positioned by the "position", "rotation" (degrees) and "scale" properties of the keyframes,
+/// spheres also take a "radius" and planes a "normal" and "extent". "material" names one of the materials.
+///
+/// Lights have a "color" and an "intensity" multiplier. Point and spot lights have a "position",
+/// spot and directional lights a "direction" and spot lights an "inner_angle" and "outer_angle" in degrees.
+/// Like planes, lights stay where they are when the shutter opens.
+pub fn build_scene(config: &RenderConfiguration, shutter_open: f64, shutter_close: f64) -> Result<Octree, String> {
+    let interpolator = FrameInterpolator::new(&config.keyframes);
+    let open_frame = interpolator.frame_at(shutter_open);
+    let close_frame = interpolator.frame_at(shutter_close);
+
+    // Sorted so entity ids don't change between runs
+    let mut ids: Vec<&String> = config.entities.keys().collect();
+    ids.sort();
+
+    let mut entities: Vec<Box<dyn SceneEntity + Sync + Send>> = Vec::new();
+    let mut lights = Vec::new();
+    for (index, id) in ids.into_iter().enumerate() {
+        let entity_id = index as u32 + 1;
+
+        // Entities only exist once their first keyframe has been reached
+        let open_values = match open_frame.updates().iter().find(|x| x.id() == id) {
+            Some(x) => x,
+            None => continue,
+        };
+        let close_values = close_frame.updates().iter().find(|x| x.id() == id).unwrap_or(open_values);
+
+        match config.entities[id] {
+            EntityType::Sphere => {
+                let material = get_material(config, id, open_values)?;
+                let transform = AnimatedTransform::new(get_transform(open_values), get_transform(close_values));
+                entities.push(Box::new(SphereEntity::new_animated(entity_id, 1.0, material, transform)));
+            }
+            EntityType::Plane => {
+                // Planes are infinitely thin boxes in world space, they stay where they are when the shutter opens
+                let origin = get_vec3(open_values, "position").unwrap_or(glm::vec3(0.0, 0.0, 0.0));
+                let normal = get_vec3(open_values, "normal").unwrap_or(glm::vec3(0.0, 1.0, 0.0));
+                let extent = get_float(open_values, "extent").unwrap_or(10.0);
+                let material = get_material(config, id, open_values)?;
+
+                entities.push(Box::new(PlaneEntity::new(
+                    entity_id,
+                    Plane::new(origin, glm::normalize(normal)),
+                    extent,
+                    material,
+                    TransformBuilder::new().build())));
+            }
+            EntityType::Model => return Err(format!("Entity '{}' is a model, models can not be rendered yet", id)),
+            EntityType::PointLight | EntityType::SpotLight | EntityType::DirectionalLight => {
+                lights.push(get_light(config.entities[id], open_values));
+            }
+        }
+    }
+
+    Ok(Octree::create(entities, OCTREE_DEPTH)
+        .with_medium(config.fog.clone())
+        .with_environment(config.environment.clone())
+        .with_lights(lights))
+}
+
+fn get_light(entity_type: EntityType, values: &PropertyChanges) -> Box<dyn Light + Sync + Send> {
+    let intensity = get_vec3(values, "color").unwrap_or(glm::vec3(1.0, 1.0, 1.0)) * get_float(values, "intensity").unwrap_or(1.0);
+    let position = get_vec3(values, "position").unwrap_or(glm::vec3(0.0, 0.0, 0.0));
+    let direction = get_vec3(values, "direction").unwrap_or(glm::vec3(0.0, -1.0, 0.0));
+
+    match entity_type {
+        EntityType::PointLight => Box::new(PointLight::new(position, intensity)),
+        EntityType::SpotLight => {
+            let inner_angle = get_float(values, "inner_angle").unwrap_or(30.0);
+            let outer_angle = get_float(values, "outer_angle").unwrap_or(45.0);
+            Box::new(SpotLight::new(position, direction, intensity, inner_angle.to_radians(), outer_angle.to_radians()))
+        }
+        EntityType::DirectionalLight => Box::new(DirectionalLight::new(direction, intensity)),
+        _ => unreachable!(),
+    }
+}
+
+fn get_material(config: &RenderConfiguration, id: &str, values: &PropertyChanges) -> Result<Material, String> {
+    match values.values().get("material") {
+        Some(PropertyValue::String(name)) => config.materials.get(name)
+            .cloned()
+            .ok_or(format!("Entity '{}' references material '{}' which is not defined", id, name)),
+        Some(_) => Err(format!("The material of entity '{}' must be a material name", id)),
+        None => Ok(MaterialBuilder::new().build()),
+    }
+}
+
+fn get_transform(values: &PropertyChanges) -> Transform {
+    let radius = get_float(values, "radius").unwrap_or(1.0);
+    let rotation = get_vec3(values, "rotation").unwrap_or(glm::vec3(0.0, 0.0, 0.0));
+    let scale = get_vec3(values, "scale").unwrap_or(glm::vec3(1.0, 1.0, 1.0));
+
+    TransformBuilder::new()
+        .with_translation(get_vec3(values, "position").unwrap_or(glm::vec3(0.0, 0.0, 0.0)))
+        .with_rotation(glm::vec3(rotation.x.to_radians(), rotation.y.to_radians(), rotation.z.to_radians()))
+        // A sphere of radius r is a unit sphere scaled by r, so an animated radius blurs like any other scale
+        .with_scale(scale * radius)
+        .build()
+}
+
+fn get_float(values: &PropertyChanges, name: &str) -> Option<f32> {
+    match values.values().get(name) {
+        Some(PropertyValue::Float(x)) => Some(*x),
+        _ => None,
+    }
+}
+
+fn get_vec3(values: &PropertyChanges, name: &str) -> Option<glm::Vec3> {
+    match values.values().get(name) {
+        Some(PropertyValue::Vec3(x)) => Some(*x),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_configuration::parser::parse;
+    use crate::scene::Scene;
+    use crate::core::Ray;
+
+    const SCENE: &str = r#"{
+        "scene": { "duration": 2.0, "frames_per_second": 10, "shutter_speed": { "numerator": 1.0, "denominator": 10.0 } },
+        "models": [],
+        "materials": [ { "name": "red", "diffuse": [1.0, 0.0, 0.0] } ],
+        "entities": [ { "sphere": { "id": "ball" } }, { "sphere": { "id": "late" } } ],
+        "keyframes": [
+            { "timestamp": 0.0, "updates": [ { "id": "ball", "position": [0.0, 0.0, 0.0], "material": "red" } ] },
+            { "timestamp": 1.0, "updates": [ { "id": "ball", "position": [10.0, 0.0, 0.0] } ] },
+            { "timestamp": 1.5, "updates": [ { "id": "late", "position": [0.0, 5.0, 0.0] } ] }
+        ]
+    }"#;
+
+    fn ray_down_at(x: f32, time: f32) -> Ray {
+        Ray::new(glm::vec3(x, 0.0, 10.0), glm::vec3(0.0, 0.0, -1.0)).with_time(time)
+    }
+
+    #[test]
+    fn build_scene_should_move_entities_during_the_shutter_interval() {
+        let config = parse(SCENE.as_bytes()).unwrap();
+
+        let scene = build_scene(&config, 0.0, 0.5).unwrap();
+
+        // The ball travels from x = 0 to x = 5 while the shutter is open
+        assert!(scene.find_intersection(&ray_down_at(0.0, 0.0)).is_some());
+        assert!(scene.find_intersection(&ray_down_at(5.0, 0.0)).is_none());
+        assert!(scene.find_intersection(&ray_down_at(5.0, 1.0)).is_some());
+        assert!(scene.find_intersection(&ray_down_at(2.5, 0.5)).is_some());
+    }
+
+    #[test]
+    fn build_scene_should_only_include_entities_that_exist() {
+        let config = parse(SCENE.as_bytes()).unwrap();
+
+        let scene = build_scene(&config, 1.0, 1.1).unwrap();
+
+        assert!(scene.find_intersection(&Ray::new(glm::vec3(0.0, 5.0, 10.0), glm::vec3(0.0, 0.0, -1.0))).is_none());
+        assert!(scene.find_intersection(&ray_down_at(10.0, 0.0)).unwrap().material().id() == 1);
+    }
+
+    #[test]
+    fn build_scene_should_create_lights() {
+        let scene_with_lights = SCENE
+            .replace(r#"{ "sphere": { "id": "late" } }"#, r#"{ "point_light": { "id": "lamp" } }, { "directional_light": { "id": "sun" } }"#)
+            .replace(r#"{ "id": "ball", "position": [0.0, 0.0, 0.0], "material": "red" }"#,
+                     r#"{ "id": "ball", "position": [0.0, 0.0, 0.0], "material": "red" },
+                        { "id": "lamp", "position": [0.0, 2.0, 0.0], "color": [1.0, 0.5, 0.5], "intensity": 8.0 },
+                        { "id": "sun", "direction": [0.0, 0.0, -1.0] }"#);
+        let config = parse(scene_with_lights.as_bytes()).unwrap();
+
+        let scene = build_scene(&config, 0.0, 0.1).unwrap();
+
+        // Sorted by id, the lamp comes first
+        assert_eq!(scene.lights().len(), 2);
+        let lamp = scene.lights()[0].sample(&glm::vec3(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(lamp.irradiance, glm::vec3(2.0, 1.0, 1.0));
+        let sun = scene.lights()[1].sample(&glm::vec3(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(sun.direction, glm::vec3(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn build_scene_should_reject_unknown_materials() {
+        let config = parse(SCENE.replace("\"material\": \"red\"", "\"material\": \"blue\"").as_bytes()).unwrap();
+
+        assert!(build_scene(&config, 0.0, 0.1).is_err());
+    }
+}
@@ -1,52 +1,63 @@
-use crate::scene::{Scene, SurfaceDescription};
+use crate::scene::Scene;
 use crate::camera::Camera;
-use crate::core::{Ray, Intersection};
-use rand::rngs::StdRng;
-use crate::core::math::lerp;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use crate::color::Color;
-use std::thread::{Thread, JoinHandle};
+use std::thread::JoinHandle;
 use std::thread;
 use std::sync::Arc;
 use std::sync::mpsc::Sender;
 use std::time::Instant;
-use rand::{RngCore, SeedableRng, Rng};
-use rand::seq::index::sample;
-
+use std::collections::BTreeMap;
+use crate::integrator::Integrator;
+use crate::renderer::settings::RenderSettings;
+use crate::renderer::tiles::{Tile, TileProducer, create_tiles};
+use crate::renderer::filter::Filter;
+use crate::color::luminance;
+use crate::renderer::aov::{AovType, first_hit_values};
+use crate::renderer::denoise::GUIDE_AOVS;
+use crate::renderer::display::DisplayTransform;
+use crate::renderer::sampler::SamplerFactory;
+
+pub mod settings;
+pub mod tiles;
+pub mod filter;
+pub mod adaptive;
+pub mod aov;
+pub mod denoise;
+pub mod display;
+pub mod checkpoint;
+pub mod random;
+pub mod sampler;
+#[cfg(test)]
+pub mod test_scene;
+
+/// Accumulates weighted color samples per pixel, the value of a pixel is the weighted average of its samples.
+/// Next to the filtered colors it keeps unfiltered statistics of the samples taken inside each pixel,
+/// which is what adaptive sampling uses to estimate the remaining noise.
 pub struct ImageBuffer {
     pixels: Vec<f32>,
+    weights: Vec<f32>,
+    sample_counts: Vec<u32>,
+    luminance_sums: Vec<f64>,
+    luminance_square_sums: Vec<f64>,
+    // Extra passes rendered along with this image, see `with_aovs`
+    aovs: Vec<(AovType, ImageBuffer)>,
     width: usize,
     height: usize,
 }
 
-struct WorkerResult {
-    scanline_number: usize,
-    pixels: Vec<glm::Vec3>,
-}
-
-#[derive(Clone)]
-pub struct ScanlineProducer {
-    scanline: Arc<AtomicUsize>,
-    total_scanlines: usize,
+struct PixelSample {
+    // The pixel the sample was taken for. Not derived from `position`, which can round onto the next pixel's edge
+    x: usize,
+    y: usize,
+    // Continuous position on the image plane, see Camera::cast_ray_at
+    position: glm::Vec2,
+    color: glm::Vec3,
+    // One value per pass the image collects, in the order of ImageBuffer::aovs
+    aovs: Vec<glm::Vec3>,
 }
 
-impl ScanlineProducer {
-    pub fn new(total_scanlines: usize) -> Self {
-        ScanlineProducer {
-            scanline: Arc::new(AtomicUsize::new(0)),
-            total_scanlines,
-        }
-    }
-
-    pub fn next(&self) -> Option<usize> {
-        let next = self.scanline.fetch_add(1, Ordering::SeqCst);
-
-        if next < self.total_scanlines {
-            Some(next)
-        } else {
-            None
-        }
-    }
+struct WorkerResult {
+    tile: Tile,
+    samples: Vec<PixelSample>,
 }
 
 impl ImageBuffer {
@@ -55,208 +66,236 @@ impl ImageBuffer {
     pub fn new(width: usize, height: usize) -> Self {
         ImageBuffer {
             pixels: vec![0.0f32; width * height * ImageBuffer::COMPONENTS_PER_PIXEL],
+            weights: vec![0.0f32; width * height],
+            sample_counts: vec![0; width * height],
+            luminance_sums: vec![0.0; width * height],
+            luminance_square_sums: vec![0.0; width * height],
+            aovs: Vec::new(),
             width,
             height,
         }
     }
 
+    /// An image that also collects the given passes, each in a buffer of its own.
+    pub fn with_aovs(width: usize, height: usize, aovs: &[AovType]) -> Self {
+        let mut image = ImageBuffer::new(width, height);
+        image.aovs = aovs.iter().map(|aov| (*aov, ImageBuffer::new(width, height))).collect();
+
+        image
+    }
+
+    pub fn aovs(&self) -> &Vec<(AovType, ImageBuffer)> {
+        &self.aovs
+    }
+
+    pub fn aov(&self, aov: AovType) -> Option<&ImageBuffer> {
+        self.aovs.iter().find(|(x, _)| *x == aov).map(|(_, image)| image)
+    }
+
+    /// The raw weighted sums, use `pixel` to get the averaged color.
     pub fn pixels(&self) -> &Vec<f32> {
         &self.pixels
     }
 
-    pub fn pixels_u8(&self) -> Vec<u8> {
+    pub fn width(&self) -> usize { self.width }
+
+    pub fn height(&self) -> usize { self.height }
+
+    /// 8 bit RGB pixels, bottom row first, with `transform` applied to every pixel.
+    pub fn pixels_u8(&self, transform: &DisplayTransform) -> Vec<u8> {
         let mut result = vec![0u8; self.width * self.height * ImageBuffer::COMPONENTS_PER_PIXEL];
-        for offset in 0..(self.width * self.height * ImageBuffer::COMPONENTS_PER_PIXEL) {
-            result[offset] = (self.pixels[offset] * 255.0) as u8;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let color = transform.to_u8(self.pixel(x, y));
+                let offset = y * self.width * ImageBuffer::COMPONENTS_PER_PIXEL + (x * ImageBuffer::COMPONENTS_PER_PIXEL);
+
+                result[offset..offset + ImageBuffer::COMPONENTS_PER_PIXEL].copy_from_slice(&color);
+            }
         }
 
         result
     }
 
     #[inline(always)]
-    pub fn add_pixel(&mut self, x: usize, y: usize, color: glm::Vec3) {
+    pub fn add_sample(&mut self, x: usize, y: usize, color: glm::Vec3, weight: f32) {
         debug_assert!(x < self.width);
         debug_assert!(y < self.height);
 
-        let pixel_offset = y * self.width * ImageBuffer::COMPONENTS_PER_PIXEL + (x * ImageBuffer::COMPONENTS_PER_PIXEL);
+        let pixel_index = y * self.width + x;
+        let pixel_offset = pixel_index * ImageBuffer::COMPONENTS_PER_PIXEL;
 
-        self.pixels[pixel_offset] += color.x;
-        self.pixels[pixel_offset + 1] += color.y;
-        self.pixels[pixel_offset + 2] += color.z;
+        self.pixels[pixel_offset] += color.x * weight;
+        self.pixels[pixel_offset + 1] += color.y * weight;
+        self.pixels[pixel_offset + 2] += color.z * weight;
+        self.weights[pixel_index] += weight;
     }
 
-    #[inline(always)]
-    pub fn pixel(&self, x: usize, y: usize) -> glm::Vec3 {
-        debug_assert!(x < self.width);
-        debug_assert!(y < self.height);
-
-        let pixel_offset = y * self.width * ImageBuffer::COMPONENTS_PER_PIXEL + (x * ImageBuffer::COMPONENTS_PER_PIXEL);
-
-        glm::vec3(self.pixels[pixel_offset], self.pixels[pixel_offset + 1], self.pixels[pixel_offset + 2])
-    }
-}
+    /// Spreads a sample taken at a continuous image position over every pixel the filter reaches.
+    pub fn add_filtered_sample(&mut self, position: glm::Vec2, color: glm::Vec3, filter: &Filter) {
+        // Pixel centers sit at +0.5, find every center within the filter radius
+        let min_x = (position.x - 0.5 - filter.radius).ceil().max(0.0) as usize;
+        let min_y = (position.y - 0.5 - filter.radius).ceil().max(0.0) as usize;
+        let max_x = (position.x - 0.5 + filter.radius).floor().min(self.width as f32 - 1.0);
+        let max_y = (position.y - 0.5 + filter.radius).floor().min(self.height as f32 - 1.0);
+        if max_x < 0.0 || max_y < 0.0 {
+            return;
+        }
 
-fn shade(scene: &Arc<dyn Scene + Sync + Send>, ray: &Ray, light: &SurfaceDescription, depth_limit: u32) -> glm::Vec3 {
-    if depth_limit == 0 {
-        return glm::vec3(0.0, 0.0, 0.0);
+        for y in min_y..=max_y as usize {
+            for x in min_x..=max_x as usize {
+                let weight = filter.evaluate(x as f32 + 0.5 - position.x, y as f32 + 0.5 - position.y);
+                if weight != 0.0 {
+                    self.add_sample(x, y, color, weight);
+                }
+            }
+        }
     }
 
-    match scene.find_intersection(ray) {
-        None => {
-            glm::vec3(0.0, 0.0, 0.0)
+    /// Keeps the first value written to a pixel and ignores the rest.
+    pub fn add_first_sample(&mut self, x: usize, y: usize, color: glm::Vec3) {
+        if self.weights[y * self.width + x] == 0.0 {
+            self.add_sample(x, y, color, 1.0);
         }
-        Some(intersection) => {
-            let coordinate = intersection.coordinate();
-            let norm = intersection.world_space_normal();
-
-            // Collect incoming light
-            // - own emission
-            // - bidirectional
-
-            let mut refracted = glm::vec3(0.0, 0.0, 0.0);
-            if intersection.material().transparent() {
-                let is_entering = glm::dot(ray.direction, norm) < 0.0;
-                let mut refractive_index1 = 1.0;
-                let mut refractive_index2 = intersection.material().refractive_index();
-                if !is_entering {
-                    std::mem::swap(&mut refractive_index1, &mut refractive_index2);
-                }
-                let refracted_dir = glm::normalize(glm::refract(ray.direction, if is_entering { norm } else { norm * -1.0 }, refractive_index1 / refractive_index2));
-
-                let refracted_ray = Ray {
-                    origin: coordinate + (norm * (if is_entering { -0.1 } else { 0.1 })),
-                    direction: refracted_dir,
-                };
+    }
 
-                refracted = shade(scene, &refracted_ray, light, depth_limit - 1);
-            }
+    /// Counts a sample taken inside pixel (x, y) towards its noise estimate.
+    pub fn record_sample(&mut self, x: usize, y: usize, color: glm::Vec3) {
+        debug_assert!(x < self.width);
+        debug_assert!(y < self.height);
 
-            let mut reflected = glm::vec3(0.0, 0.0, 0.0);
-            if intersection.material().reflectivity() > 0.0 {
-                let reflected_dir =
-                    glm::reflect(ray.direction, intersection.world_space_normal());
+        let pixel_index = y * self.width + x;
+        let value = luminance(&color) as f64;
 
-                let reflected_ray = Ray {
-                    origin: coordinate + (norm * 0.1),
-                    direction: reflected_dir,
-                };
+        self.sample_counts[pixel_index] += 1;
+        self.luminance_sums[pixel_index] += value;
+        self.luminance_square_sums[pixel_index] += value * value;
+    }
 
-                reflected = shade(scene, &reflected_ray, light, depth_limit - 1);
-            }
+    pub fn sample_count(&self, x: usize, y: usize) -> u32 {
+        self.sample_counts[y * self.width + x]
+    }
 
-            let mut diffuse = intersection.material().sample_diffuse(&intersection.texture_coordinates());
-            let mut direct_light = glm::vec3(0.0, 0.0, 0.0);
+    /// Standard error of the mean luminance relative to the mean itself.
+    /// The mean is clamped from below so dark pixels don't soak up the whole sample budget.
+    pub fn relative_error(&self, x: usize, y: usize) -> f32 {
+        let pixel_index = y * self.width + x;
+        let count = self.sample_counts[pixel_index] as f64;
+        if count < 2.0 {
+            return f32::INFINITY;
+        }
 
-            let new_origin = coordinate + (norm * 0.1);
-            let shadow_ray = Ray {
-                origin: new_origin,
-                direction: glm::normalize(light.coordinate - new_origin),
-            };
+        let mean = self.luminance_sums[pixel_index] / count;
+        let variance = ((self.luminance_square_sums[pixel_index] - mean * mean * count) / (count - 1.0)).max(0.0);
 
-            if intersection.entity_id() != light.entity_id {
-                if let Some(light_intersection) = scene.find_intersection(&shadow_ray) {
-                    let coo = light_intersection.coordinate();
-                    if coordinate.x < 3.0 && intersection.entity_id() == 1 && light_intersection.entity_id() == intersection.entity_id() {
-                        let fsdf = 34;
-                    }
-                    if light_intersection.entity_id() == light.entity_id {
-                        // TODO: Should check that collision is close to light.coordinate too!!
-                        direct_light = direct_light + *light_intersection.material().emission();
-                    }
-                }
-            }
+        ((variance / count).sqrt() / mean.abs().max(0.01)) as f32
+    }
 
+    #[inline(always)]
+    pub fn pixel(&self, x: usize, y: usize) -> glm::Vec3 {
+        debug_assert!(x < self.width);
+        debug_assert!(y < self.height);
 
-            /*for light in scene.get_emissive_entities() {
-                let new_origin = coordinate + (norm * 0.1);
-                let shadow_ray = Ray {
-                    origin: new_origin,
-                    direction: glm::normalize(*light.transform().translation() - new_origin),
-                };
+        let pixel_index = y * self.width + x;
+        let pixel_offset = pixel_index * ImageBuffer::COMPONENTS_PER_PIXEL;
 
-                if let Some(light_intersection) = scene.find_intersection(&shadow_ray) {
-                    let coo = light_intersection.coordinate();
-                    if coordinate.x < 3.0 && intersection.entity_id() == 1 && light_intersection.entity_id() == intersection.entity_id() {
-                        let fsdf = 34;
-                    }
-                    if light_intersection.entity_id() == light.entity_id() {
-                        direct_light = direct_light + *light_intersection.material().emission();
-                    }
-                }
-            }*/
-            if intersection.material().transparent() {
-                refracted
-            } else {
-                lerp(
-                    *intersection.material().emission() + (diffuse * direct_light),
-                    reflected,
-                    intersection.material().reflectivity())
-            }
+        let weight = self.weights[pixel_index];
+        if weight <= 0.0 {
+            return glm::vec3(0.0, 0.0, 0.0);
         }
+
+        glm::vec3(self.pixels[pixel_offset], self.pixels[pixel_offset + 1], self.pixels[pixel_offset + 2]) / weight
     }
 }
 
-fn render_sample_thread(scene: Arc<dyn Scene + Sync + Send>, camera: Camera, render_width: usize, scanline_producer: ScanlineProducer, mut rng: StdRng, tx: Sender<Vec<WorkerResult>>) -> JoinHandle<()> {
+// `sample_indices` holds the number of samples every pixel had when the pass started, see `SamplerFactory`
+fn render_sample_thread(scene: Arc<dyn Scene + Sync + Send>, camera: Camera, integrator: Arc<dyn Integrator + Sync + Send>, tile_producer: TileProducer, aovs: Vec<AovType>, active_pixels: Option<Arc<Vec<bool>>>, samplers: SamplerFactory, sample_indices: Arc<Vec<u32>>, tx: Sender<WorkerResult>) -> JoinHandle<()> {
     thread::spawn(move || {
-        let mut pixels = vec![glm::vec3(0.0, 0.0, 0.0); render_width];
-        // Performance idea: use with_capacity and set it to (scanline_count / thread_count)
-        // Since for a perfectly balanced workload (however unlikely) thats how many scanlines each thread
-        // will render.
-        let mut results = Vec::new();
-
-        loop {
-            match scanline_producer.next() {
-                None => break,
-                Some(scanline_number) => {
-                    for x in 0..render_width {
-                        if x == 256 && scanline_number == 256 {
-                            let asd = 321;
-                        }
-                        let r = camera.cast_ray(x as usize, scanline_number);
-
-                        let emissive_entities = scene.get_emissive_entities();
-                        let emissive_surface = if emissive_entities.is_empty() {
-                            SurfaceDescription {
-                                coordinate: glm::vec3(0.0, 20.0, 0.0),
-                                world_normal: glm::vec3(0.0, -1.0, 0.0),
-                                emission: glm::vec3(1.0, 1.0, 1.0),
-                                entity_id: 999
-                            }
+        let needs_first_hit = aovs.iter().any(|aov| !aov.is_lighting());
+        let needs_components = aovs.iter().any(|aov| aov.is_lighting());
+
+        while let Some(tile) = tile_producer.next() {
+            let mut samples = Vec::with_capacity(tile.width * tile.height);
+
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    let pixel_index = y * camera.resolution().x as usize + x;
+                    if let Some(active_pixels) = &active_pixels {
+                        if !active_pixels[pixel_index] {
+                            continue;
                         }
-                        else {
-                            let random_emissive_entity =
-                                emissive_entities[rng.gen_range(0..emissive_entities.len())];
-
-                            random_emissive_entity.get_random_emissive_surface(&mut rng)
-                        };
-
-
+                    }
 
-                        pixels[x] = shade(&scene, &r, &emissive_surface, 3);
+                    let mut sampler = samplers.create(x, y, sample_indices[pixel_index]);
+
+                    // Jitter the sample inside the pixel, the reconstruction filter takes care of the rest
+                    let position = glm::vec2(x as f32, y as f32) + sampler.get_2d();
+                    // Each sample sees the scene at a random moment while the shutter is open, which blurs moving entities
+                    let r = camera.cast_ray_at(position.x, position.y).with_time(sampler.get_1d());
+
+                    if aovs.is_empty() {
+                        samples.push(PixelSample {
+                            x,
+                            y,
+                            position,
+                            color: integrator.radiance(&scene, r, sampler.as_mut()),
+                            aovs: Vec::new(),
+                        });
+                        continue;
                     }
 
-                    results.push(WorkerResult {
-                        scanline_number,
-                        pixels: pixels.clone(),
+                    let mut values = if needs_first_hit {
+                        first_hit_values(&scene, &r, &aovs)
+                    } else {
+                        vec![glm::vec3(0.0, 0.0, 0.0); aovs.len()]
+                    };
+
+                    let color = if needs_components {
+                        let components = integrator.radiance_components(&scene, r, sampler.as_mut());
+                        for (value, aov) in values.iter_mut().zip(aovs.iter()) {
+                            match aov {
+                                AovType::Direct => *value = components.direct,
+                                AovType::Indirect => *value = components.indirect,
+                                _ => {}
+                            }
+                        }
+
+                        components.total()
+                    } else {
+                        integrator.radiance(&scene, r, sampler.as_mut())
+                    };
+
+                    samples.push(PixelSample {
+                        x,
+                        y,
+                        position,
+                        color,
+                        aovs: values,
                     });
                 }
             }
-        }
 
-        tx.send(results).unwrap();
+            // Send every tile as soon as it is done so the caller can show progress.
+            // The receiver only goes away if the render was abandoned, then there is nothing left to do.
+            if tx.send(WorkerResult { tile, samples }).is_err() {
+                break;
+            }
+        }
     })
 }
 
-fn render_sample(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, resolution: &glm::Vector2<u32>, rng: &mut StdRng, image: &mut ImageBuffer, sample_importance: f32) {
+fn render_sample(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, resolution: &glm::Vector2<u32>, settings: &RenderSettings, integrator: &Arc<dyn Integrator + Sync + Send>, samples_per_pixel: u32, active_pixels: Option<Arc<Vec<bool>>>, image: &mut ImageBuffer, on_tile: &mut dyn FnMut(&Tile, &ImageBuffer)) {
     let now = Instant::now();
-    let sp = ScanlineProducer::new(resolution.y as usize);
+    let tile_producer = TileProducer::new(
+        create_tiles(resolution.x as usize, resolution.y as usize, settings.tile_size, settings.tile_order));
+    let sample_indices = Arc::new(image.sample_counts.clone());
+    let samplers = SamplerFactory::new(settings.sampler, settings.seed, settings.frame, samples_per_pixel);
+
+    let aovs: Vec<AovType> = image.aovs().iter().map(|(aov, _)| *aov).collect();
 
     let (tx, rx) = std::sync::mpsc::channel();
     let mut threads = Vec::new();
-    let thread_count = 1;
-    for i in 0..thread_count {
-        let thread_rng = rand::rngs::StdRng::seed_from_u64(rng.next_u64());
-        threads.push(render_sample_thread(scene.clone(), camera.clone(), resolution.x as usize, sp.clone(), thread_rng, tx.clone()));
+    for _ in 0..settings.thread_count.max(1).min(tile_producer.tile_count()) {
+        threads.push(render_sample_thread(scene.clone(), camera.clone(), integrator.clone(), tile_producer.clone(), aovs.clone(), active_pixels.clone(), samplers, sample_indices.clone(), tx.clone()));
     }
 
     // Once all senders (tx) have closed the receiver (rx) will close.
@@ -264,11 +303,33 @@ fn render_sample(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, resoluti
     // Or else the for loop below will never stop
     std::mem::drop(tx);
 
-    for worker_results in rx {
-        for scanline in worker_results {
-            for x in 0..resolution.x as usize {
-                image.add_pixel(x, scanline.scanline_number, scanline.pixels[x].clone() * sample_importance);
+    // Tiles are committed in the order they were scheduled rather than the order they finish in.
+    // Filtered samples spill over into neighbouring tiles and floating point addition is not associative,
+    // so a fixed order is what keeps the image independent of the thread count.
+    let mut finished_tiles = BTreeMap::new();
+    let mut next_tile = 0;
+    for result in rx {
+        finished_tiles.insert(result.tile.index, result);
+
+        while let Some(result) = finished_tiles.remove(&next_tile) {
+            for sample in &result.samples {
+                image.add_filtered_sample(sample.position, sample.color, &settings.filter);
+                image.record_sample(sample.x, sample.y, sample.color);
+
+                for (value, (aov, aov_image)) in sample.aovs.iter().zip(image.aovs.iter_mut()) {
+                    let (x, y) = (sample.x, sample.y);
+                    if aov.is_lighting() {
+                        aov_image.add_filtered_sample(sample.position, *value, &settings.filter);
+                    } else if aov.is_id() {
+                        aov_image.add_first_sample(x, y, *value);
+                    } else {
+                        aov_image.add_sample(x, y, *value, 1.0);
+                    }
+                }
             }
+
+            on_tile(&result.tile, image);
+            next_tile += 1;
         }
     }
 
@@ -276,32 +337,223 @@ fn render_sample(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, resoluti
         thread.join().unwrap();
     }
     println!("Render time: {}ms", now.elapsed().as_millis());
+}
 
+pub fn render(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, resolution: &glm::Vector2<u32>, settings: &RenderSettings, integrator: &Arc<dyn Integrator + Sync + Send>) -> ImageBuffer {
+    render_with_progress(scene, camera, resolution, settings, integrator, &mut |_, _| {})
+}
 
-    /*let now = Instant::now();
-    for y in 0..resolution.y {
-        for x in 0..resolution.x {
-            let r = camera.cast_ray(x as usize, y as usize);
+/// Same as `render`, but calls `on_tile` with the partially finished image every time a tile completes.
+pub fn render_with_progress(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, resolution: &glm::Vector2<u32>, settings: &RenderSettings, integrator: &Arc<dyn Integrator + Sync + Send>, on_tile: &mut dyn FnMut(&Tile, &ImageBuffer)) -> ImageBuffer {
+    let mut image = ImageBuffer::with_aovs(resolution.x as usize, resolution.y as usize, &collected_aovs(settings));
 
-            let color = shade(scene, &r, 2);
+    // With adaptive sampling converged pixels drop out early, so keep going up to the full budget
+    let passes = match &settings.adaptive {
+        Some(adaptive) => adaptive.max_samples,
+        None => settings.samples,
+    };
+    accumulate_passes(scene, camera, settings, integrator, 0..passes, &mut image, on_tile, &mut |_, _| {});
+
+    image
+}
 
-            image.add_pixel(x as usize, y as usize, Color::from_vec3(&(color * sample_importance)));
+/// The passes an image needs to collect for `settings`, the requested ones plus what the denoiser needs.
+pub fn collected_aovs(settings: &RenderSettings) -> Vec<AovType> {
+    let mut aovs = settings.aovs.clone();
+    if settings.denoiser.is_some() {
+        for aov in GUIDE_AOVS.iter() {
+            if !aovs.contains(aov) {
+                aovs.push(*aov);
+            }
         }
     }
 
-    println!("Render time: {}ms", now.elapsed().as_millis());*/
+    aovs
 }
 
-pub fn render(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, resolution: &glm::Vector2<u32>, nsamples: u32, rng: &mut StdRng) -> ImageBuffer {
-    let mut image = ImageBuffer::new(resolution.x as usize, resolution.y as usize);
+/// Adds `settings.samples` more samples per pixel to an existing image,
+/// rendering progressively is just calling this repeatedly with the same image.
+/// With adaptive sampling enabled only pixels that still need samples are rendered.
+/// The passes collected are the ones the image was created with, see `collected_aovs`.
+pub fn accumulate(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, settings: &RenderSettings, integrator: &Arc<dyn Integrator + Sync + Send>, image: &mut ImageBuffer, on_tile: &mut dyn FnMut(&Tile, &ImageBuffer)) {
+    accumulate_passes(scene, camera, settings, integrator, 0..settings.samples, image, on_tile, &mut |_, _| {});
+}
 
-    for sample in 0..nsamples {
-        println!("sample {} of {}", sample + 1, nsamples);
-        render_sample(scene, camera, resolution, rng, &mut image, 1.0 / nsamples as f32);
-    }
+/// Renders the passes in `passes`, a range so an interrupted render can pick up where it stopped.
+/// `on_pass` gets the number of passes finished so far after each one.
+fn accumulate_passes(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, settings: &RenderSettings, integrator: &Arc<dyn Integrator + Sync + Send>, passes: std::ops::Range<u32>, image: &mut ImageBuffer, on_tile: &mut dyn FnMut(&Tile, &ImageBuffer), on_pass: &mut dyn FnMut(u32, &ImageBuffer)) {
+    let resolution = glm::Vector2::<u32>::new(image.width() as u32, image.height() as u32);
+    let pass_count = passes.end;
+
+    for sample in passes {
+        let active_pixels = match &settings.adaptive {
+            Some(adaptive) => {
+                let active_pixels = adaptive.active_pixels(image);
+                let active_count = active_pixels.iter().filter(|x| **x).count();
+                if active_count == 0 {
+                    println!("All pixels converged after {} passes", sample);
+                    break;
+                }
 
+                println!("sample {} of at most {}, {} pixels active", sample + 1, pass_count, active_count);
+                Some(Arc::new(active_pixels))
+            }
+            None => {
+                println!("sample {} of {}", sample + 1, pass_count);
+                None
+            }
+        };
 
-    image
+        render_sample(scene, camera, &resolution, settings, integrator, pass_count, active_pixels, image, on_tile);
+        on_pass(sample + 1, image);
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::test_scene::{create_test_scene, create_test_camera};
+    use crate::integrator::path_tracer::PathTracingIntegrator;
+    use crate::integrator::termination::PathTermination;
+    use crate::renderer::filter::FilterType;
+    use crate::renderer::tiles::TileOrder;
+    use crate::renderer::adaptive::AdaptiveSampling;
+    use crate::renderer::aov::AovType;
+
+    fn render_with_threads(thread_count: usize) -> ImageBuffer {
+        let resolution = glm::Vector2::<u32>::new(16, 12);
+        let camera = create_test_camera(&resolution);
+
+        let mut settings = RenderSettings::new();
+        settings.samples = 3;
+        settings.thread_count = thread_count;
+        settings.tile_size = 4;
+        settings.filter = Filter::new(FilterType::Mitchell, 2.0);
+
+        let integrator: Arc<dyn Integrator + Sync + Send> = Arc::new(PathTracingIntegrator::new(PathTermination::new()));
+
+        render(&create_test_scene(), &camera, &resolution, &settings, &integrator)
+    }
+
+    #[test]
+    fn render_should_not_depend_on_thread_count() {
+        let single_threaded = render_with_threads(1);
+        let multi_threaded = render_with_threads(5);
+
+        assert!(single_threaded.pixels().iter().any(|x| *x > 0.0));
+        assert_eq!(single_threaded.pixels(), multi_threaded.pixels());
+    }
+
+    #[test]
+    fn render_should_not_depend_on_tiles() {
+        let resolution = glm::Vector2::<u32>::new(16, 12);
+        let camera = create_test_camera(&resolution);
+        let integrator: Arc<dyn Integrator + Sync + Send> = Arc::new(PathTracingIntegrator::new(PathTermination::new()));
+
+        // With the box filter every sample stays in its own pixel, so the order tiles are committed in doesn't matter either
+        let mut settings = RenderSettings::new();
+        settings.samples = 2;
+        settings.tile_size = 4;
+        settings.tile_order = TileOrder::Scanline;
+        let mut other_tiles = settings.clone();
+        other_tiles.tile_size = 7;
+        other_tiles.tile_order = TileOrder::Hilbert;
+        let mut other_seed = settings.clone();
+        other_seed.seed = 1;
+
+        let image = render(&create_test_scene(), &camera, &resolution, &settings, &integrator);
+
+        assert_eq!(image.pixels(), render(&create_test_scene(), &camera, &resolution, &other_tiles, &integrator).pixels());
+        assert_ne!(image.pixels(), render(&create_test_scene(), &camera, &resolution, &other_seed, &integrator).pixels());
+    }
+
+    #[test]
+    fn adaptive_render_should_stop_sampling_converged_pixels() {
+        let resolution = glm::Vector2::<u32>::new(16, 12);
+        let camera = create_test_camera(&resolution);
+
+        let mut settings = RenderSettings::new();
+        settings.thread_count = 2;
+        settings.adaptive = Some(AdaptiveSampling { threshold: 0.05, min_samples: 4, max_samples: 32 });
+
+        let integrator: Arc<dyn Integrator + Sync + Send> = Arc::new(PathTracingIntegrator::new(PathTermination::new()));
+        let image = render(&create_test_scene(), &camera, &resolution, &settings, &integrator);
+
+        let counts: Vec<u32> = (0..12).flat_map(|y| (0..16).map(move |x| (x, y)))
+            .map(|(x, y)| image.sample_count(x, y))
+            .collect();
+
+        // The top row only sees the black background and converges right away, the lit floor is noisy
+        assert_eq!(image.sample_count(0, 11), 4);
+        assert!(counts.iter().all(|x| *x >= 4 && *x <= 32));
+        assert!(counts.iter().any(|x| *x > 4));
+    }
+
+    #[test]
+    fn lighting_passes_should_add_up_to_the_beauty_image() {
+        let resolution = glm::Vector2::<u32>::new(16, 12);
+        let camera = create_test_camera(&resolution);
+
+        let mut settings = RenderSettings::new();
+        settings.samples = 2;
+        settings.aovs = vec![AovType::Direct, AovType::Indirect, AovType::EntityId];
+
+        let integrator: Arc<dyn Integrator + Sync + Send> = Arc::new(PathTracingIntegrator::new(PathTermination::new()));
+        let image = render(&create_test_scene(), &camera, &resolution, &settings, &integrator);
+
+        let direct = image.aov(AovType::Direct).unwrap();
+        let indirect = image.aov(AovType::Indirect).unwrap();
+        assert!(image.aov(AovType::Depth).is_none());
+        for y in 0..12 {
+            for x in 0..16 {
+                let difference = image.pixel(x, y) - (direct.pixel(x, y) + indirect.pixel(x, y));
+                assert!(glm::length(difference) < 1e-4);
+            }
+        }
+
+        // Ids are never blended, the bottom row only sees the floor
+        let entity_ids = image.aov(AovType::EntityId).unwrap();
+        assert_eq!(entity_ids.pixel(8, 0), glm::vec3(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn add_filtered_sample_should_only_touch_own_pixel_with_box_filter() {
+        let mut image = ImageBuffer::new(4, 4);
+        image.add_filtered_sample(glm::vec2(1.5, 2.5), glm::vec3(1.0, 0.5, 0.25), &Filter::new(FilterType::Box, 0.5));
+
+        assert_eq!(image.pixel(1, 2), glm::vec3(1.0, 0.5, 0.25));
+        assert_eq!(image.pixel(0, 2), glm::vec3(0.0, 0.0, 0.0));
+        assert_eq!(image.pixel(2, 2), glm::vec3(0.0, 0.0, 0.0));
+        assert_eq!(image.pixel(1, 1), glm::vec3(0.0, 0.0, 0.0));
+    }
 
+    #[test]
+    fn add_filtered_sample_should_spread_over_neighbours_with_wide_filter() {
+        let mut image = ImageBuffer::new(4, 4);
+        image.add_filtered_sample(glm::vec2(0.2, 0.2), glm::vec3(1.0, 1.0, 1.0), &Filter::new(FilterType::Gaussian, 1.5));
+
+        assert_eq!(image.pixel(0, 0), glm::vec3(1.0, 1.0, 1.0));
+        assert_eq!(image.pixel(1, 1), glm::vec3(1.0, 1.0, 1.0));
+        assert_eq!(image.pixel(2, 2), glm::vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn accumulating_in_steps_should_match_a_single_render() {
+        let single_render = render_with_threads(2);
+        let camera = create_test_camera(&glm::Vector2::<u32>::new(16, 12));
+
+        let mut settings = RenderSettings::new();
+        settings.samples = 1;
+        settings.thread_count = 2;
+        settings.tile_size = 4;
+        settings.filter = Filter::new(FilterType::Mitchell, 2.0);
+
+        let integrator: Arc<dyn Integrator + Sync + Send> = Arc::new(PathTracingIntegrator::new(PathTermination::new()));
+        let mut image = ImageBuffer::new(16, 12);
+        for _ in 0..3 {
+            accumulate(&create_test_scene(), &camera, &settings, &integrator, &mut image, &mut |_, _| {});
+        }
+
+        assert_eq!(single_render.pixels(), image.pixels());
+    }
+}
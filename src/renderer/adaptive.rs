@@ -0,0 +1,117 @@
+use crate::renderer::ImageBuffer;
+
+/// Keeps sampling a pixel only while the estimated error of its mean is above `threshold`.
+/// Every pixel gets at least `min_samples` and at most `max_samples` samples.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveSampling {
+    pub threshold: f32,
+    pub min_samples: u32,
+    pub max_samples: u32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(threshold: f32) -> Self {
+        AdaptiveSampling {
+            threshold,
+            min_samples: 4,
+            max_samples: 256,
+        }
+    }
+
+    pub fn needs_samples(&self, image: &ImageBuffer, x: usize, y: usize) -> bool {
+        let count = image.sample_count(x, y);
+
+        // A variance estimate needs at least two samples
+        if count < self.min_samples.max(2) {
+            return true;
+        }
+
+        count < self.max_samples && image.relative_error(x, y) > self.threshold
+    }
+
+    /// One entry per pixel in row order, true for every pixel that should be sampled in the next pass.
+    pub fn active_pixels(&self, image: &ImageBuffer) -> Vec<bool> {
+        let mut active = Vec::with_capacity(image.width() * image.height());
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                active.push(self.needs_samples(image, x, y));
+            }
+        }
+
+        active
+    }
+}
+
+/// Visualizes how many samples each pixel received, from blue (fewest) over green to red (most).
+pub fn sample_count_heatmap(image: &ImageBuffer) -> ImageBuffer {
+    let mut heatmap = ImageBuffer::new(image.width(), image.height());
+
+    let mut max_count = 1;
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            max_count = max_count.max(image.sample_count(x, y));
+        }
+    }
+
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let t = image.sample_count(x, y) as f32 / max_count as f32;
+            let color = if t < 0.5 {
+                glm::vec3(0.0, t * 2.0, 1.0 - t * 2.0)
+            } else {
+                glm::vec3(t * 2.0 - 1.0, 2.0 - t * 2.0, 0.0)
+            };
+
+            heatmap.add_sample(x, y, color, 1.0);
+        }
+    }
+
+    heatmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_pixel_should_stop_after_min_samples() {
+        let adaptive = AdaptiveSampling { threshold: 0.01, min_samples: 4, max_samples: 64 };
+        let mut image = ImageBuffer::new(1, 1);
+
+        for _ in 0..3 {
+            image.record_sample(0, 0, glm::vec3(0.5, 0.5, 0.5));
+        }
+        assert!(adaptive.needs_samples(&image, 0, 0));
+
+        image.record_sample(0, 0, glm::vec3(0.5, 0.5, 0.5));
+        assert!(!adaptive.needs_samples(&image, 0, 0));
+    }
+
+    #[test]
+    fn noisy_pixel_should_be_sampled_up_to_max_samples() {
+        let adaptive = AdaptiveSampling { threshold: 0.01, min_samples: 4, max_samples: 8 };
+        let mut image = ImageBuffer::new(1, 1);
+
+        for i in 0..7 {
+            image.record_sample(0, 0, glm::vec3(1.0, 1.0, 1.0) * (i % 2) as f32);
+        }
+        assert!(adaptive.needs_samples(&image, 0, 0));
+
+        image.record_sample(0, 0, glm::vec3(0.0, 0.0, 0.0));
+        assert!(!adaptive.needs_samples(&image, 0, 0));
+    }
+
+    #[test]
+    fn heatmap_should_go_from_blue_to_red() {
+        let mut image = ImageBuffer::new(2, 1);
+        image.record_sample(0, 0, glm::vec3(1.0, 1.0, 1.0));
+        for _ in 0..4 {
+            image.record_sample(1, 0, glm::vec3(1.0, 1.0, 1.0));
+        }
+
+        let heatmap = sample_count_heatmap(&image);
+
+        assert_eq!(heatmap.pixel(0, 0), glm::vec3(0.0, 0.5, 0.5));
+        assert_eq!(heatmap.pixel(1, 0), glm::vec3(1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::renderer::ImageBuffer;
+use crate::renderer::display::DisplayTransform;
+use crate::scene::Scene;
+use crate::core::Ray;
+use std::sync::Arc;
+
+/// Extra passes that can be rendered next to the beauty image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AovType {
+    /// Distance from the camera to the first hit, 0 where nothing was hit.
+    Depth,
+    /// World space normal at the first hit.
+    Normal,
+    /// Diffuse color at the first hit, without any lighting.
+    Albedo,
+    EntityId,
+    MaterialId,
+    /// Light that reached the first hit straight from an emitter, including emitters seen by the camera.
+    Direct,
+    /// Light that bounced at least once more before reaching the first hit.
+    Indirect,
+}
+
+impl AovType {
+    pub fn from_name(name: &str) -> Option<AovType> {
+        match name {
+            "depth" => Some(AovType::Depth),
+            "normal" => Some(AovType::Normal),
+            "albedo" => Some(AovType::Albedo),
+            "entity_id" => Some(AovType::EntityId),
+            "material_id" => Some(AovType::MaterialId),
+            "direct" => Some(AovType::Direct),
+            "indirect" => Some(AovType::Indirect),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AovType::Depth => "depth",
+            AovType::Normal => "normal",
+            AovType::Albedo => "albedo",
+            AovType::EntityId => "entity_id",
+            AovType::MaterialId => "material_id",
+            AovType::Direct => "direct",
+            AovType::Indirect => "indirect",
+        }
+    }
+
+    /// Id passes must never blend neighbouring ids, they keep the first sample of each pixel instead of the average.
+    pub fn is_id(&self) -> bool {
+        *self == AovType::EntityId || *self == AovType::MaterialId
+    }
+
+    /// Lighting passes add up to the beauty image and are filtered like it.
+    pub fn is_lighting(&self) -> bool {
+        *self == AovType::Direct || *self == AovType::Indirect
+    }
+}
+
+/// Parses a comma separated list of pass names like "depth,normal,albedo".
+pub fn parse_aov_list(names: &str) -> Option<Vec<AovType>> {
+    names.split(',')
+        .map(|name| AovType::from_name(name.trim()))
+        .collect()
+}
+
+/// Values of the geometric passes for a single camera ray, found by tracing it to the first hit.
+pub fn first_hit_values(scene: &Arc<dyn Scene + Sync + Send>, ray: &Ray, aovs: &[AovType]) -> Vec<glm::Vec3> {
+    let intersection = scene.find_intersection(ray);
+
+    aovs.iter().map(|aov| {
+        let intersection = match &intersection {
+            Some(intersection) => intersection,
+            None => return glm::vec3(0.0, 0.0, 0.0),
+        };
+
+        match aov {
+            AovType::Depth => {
+                let distance = intersection.distance();
+                glm::vec3(distance, distance, distance)
+            }
+            AovType::Normal => glm::normalize(intersection.world_space_normal()),
+            AovType::Albedo => intersection.material().sample_diffuse(&intersection.texture_coordinates()),
+            AovType::EntityId => {
+                let id = intersection.entity_id() as f32;
+                glm::vec3(id, id, id)
+            }
+            AovType::MaterialId => {
+                let id = intersection.material().id() as f32;
+                glm::vec3(id, id, id)
+            }
+            // Filled in from the integrator, not from the first hit
+            AovType::Direct | AovType::Indirect => glm::vec3(0.0, 0.0, 0.0),
+        }
+    }).collect()
+}
+
+/// Turns a pass into something that can be looked at in an 8 bit image.
+/// Depth is scaled by the furthest hit, normals are mapped from [-1, 1] to [0, 1] and ids get a random color each.
+pub fn displayable_aov(aov: AovType, image: &ImageBuffer) -> ImageBuffer {
+    let mut result = ImageBuffer::new(image.width(), image.height());
+
+    let mut max_depth = 0.0f32;
+    if aov == AovType::Depth {
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                max_depth = max_depth.max(image.pixel(x, y).x);
+            }
+        }
+    }
+
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let value = image.pixel(x, y);
+            let color = match aov {
+                AovType::Depth if max_depth > 0.0 => value / max_depth,
+                AovType::Normal => value * 0.5 + glm::vec3(0.5, 0.5, 0.5),
+                AovType::EntityId | AovType::MaterialId => id_color(value.x.round() as u32),
+                _ => value,
+            };
+
+            result.add_sample(x, y, color, 1.0);
+        }
+    }
+
+    result
+}
+
+// A stable, well spread color per id so neighbouring ids are easy to tell apart. Id 0 (nothing hit) stays black.
+fn id_color(id: u32) -> glm::Vec3 {
+    if id == 0 {
+        return glm::vec3(0.0, 0.0, 0.0);
+    }
+
+    let mut hash = id.wrapping_mul(0x9E3779B9);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85EBCA6B);
+    hash ^= hash >> 13;
+
+    glm::vec3(
+        (hash & 0xFF) as f32 / 255.0,
+        ((hash >> 8) & 0xFF) as f32 / 255.0,
+        ((hash >> 16) & 0xFF) as f32 / 255.0)
+}
+
+/// How a pass is turned into display values. Lighting passes are treated like the beauty image,
+/// albedo is a color but unlit so it only gets the sRGB encoding, everything else is data and written as is.
+pub fn aov_display_transform(aov: AovType, beauty: &DisplayTransform) -> DisplayTransform {
+    match aov {
+        AovType::Direct | AovType::Indirect => *beauty,
+        AovType::Albedo => DisplayTransform::new(),
+        _ => DisplayTransform::raw(),
+    }
+}
+
+/// Where a pass is written next to the beauty image, "render.png" becomes "render.depth.png".
+pub fn aov_path(output_path: &str, aov: AovType) -> String {
+    let path = std::path::Path::new(output_path);
+    let stem = path.file_stem().and_then(|x| x.to_str()).unwrap_or("render");
+    let file_name = match path.extension().and_then(|x| x.to_str()) {
+        Some(extension) => format!("{}.{}.{}", stem, aov.name(), extension),
+        None => format!("{}.{}", stem, aov.name()),
+    };
+
+    path.with_file_name(file_name).to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_aov_list_should_read_all_names() {
+        assert_eq!(parse_aov_list("depth, normal,albedo"), Some(vec![AovType::Depth, AovType::Normal, AovType::Albedo]));
+        assert_eq!(parse_aov_list("entity_id,material_id,direct,indirect").unwrap().len(), 4);
+        assert_eq!(parse_aov_list("depth,motion"), None);
+    }
+
+    #[test]
+    fn aov_path_should_insert_pass_name_before_extension() {
+        assert_eq!(aov_path("out/render.png", AovType::Depth), "out/render.depth.png");
+        assert_eq!(aov_path("render", AovType::EntityId), "render.entity_id");
+    }
+
+    #[test]
+    fn displayable_aov_should_normalize_depth() {
+        let mut image = ImageBuffer::new(2, 1);
+        image.add_sample(0, 0, glm::vec3(5.0, 5.0, 5.0), 1.0);
+        image.add_sample(1, 0, glm::vec3(20.0, 20.0, 20.0), 1.0);
+
+        let result = displayable_aov(AovType::Depth, &image);
+
+        assert_eq!(result.pixel(0, 0), glm::vec3(0.25, 0.25, 0.25));
+        assert_eq!(result.pixel(1, 0), glm::vec3(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn id_color_should_keep_background_black() {
+        assert_eq!(id_color(0), glm::vec3(0.0, 0.0, 0.0));
+        assert!(id_color(1) != id_color(2));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::renderer::{ImageBuffer, accumulate_passes, collected_aovs};
+use crate::renderer::settings::RenderSettings;
+use crate::renderer::aov::AovType;
+use crate::renderer::sampler::SamplerType;
+use crate::integrator::Integrator;
+use crate::scene::Scene;
+use crate::camera::Camera;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"RTCP";
+const VERSION: u32 = 2;
+
+/// Fingerprint of everything the accumulated samples depend on, a checkpoint is only resumed when it matches.
+/// FNV-1a, so it stays the same between runs and machines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SettingsHash(u64);
+
+impl SettingsHash {
+    pub fn new() -> Self {
+        SettingsHash(0xcbf29ce484222325)
+    }
+
+    pub fn add(mut self, bytes: &[u8]) -> Self {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+
+        // Keeps ("ab", "c") apart from ("a", "bc")
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+        self
+    }
+
+    /// The settings that change which samples are taken or how they are summed up.
+    /// The sample count is left out so a checkpoint kept with `--keep-checkpoint` can be continued with a larger
+    /// budget, the thread count and display settings don't change the accumulated image.
+    pub fn add_settings(self, settings: &RenderSettings) -> Self {
+        // Stratified samples depend on the sample budget, the other samplers don't
+        let sampler = match settings.sampler {
+            SamplerType::Stratified => format!("{:?} {}", settings.sampler, settings.samples),
+            _ => format!("{:?}", settings.sampler),
+        };
+
+        self.add(sampler.as_bytes())
+            .add(format!("{:?}", settings.tile_size).as_bytes())
+            .add(format!("{:?}", settings.tile_order).as_bytes())
+            .add(format!("{:?}", settings.filter).as_bytes())
+            .add(format!("{:?}", settings.adaptive).as_bytes())
+            .add(format!("{:?}", settings.path_termination).as_bytes())
+            .add(format!("{:?}", collected_aovs(settings)).as_bytes())
+            .add(format!("{:?} {:?}", settings.seed, settings.frame).as_bytes())
+            .add(format!("{:?}", settings.spectral).as_bytes())
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The state of an unfinished render. The random streams follow from the settings and the sample counts
+/// of the image, so nothing else is needed to continue exactly where the render stopped.
+pub struct Checkpoint {
+    pub settings_hash: u64,
+    pub passes: u32,
+    pub image: ImageBuffer,
+}
+
+/// Writes to a temporary file first and renames it, so a render killed while saving keeps the previous checkpoint.
+pub fn save_checkpoint(path: &str, settings_hash: u64, passes: u32, image: &ImageBuffer) -> Result<(), String> {
+    let temporary_path = format!("{}.tmp", path);
+    let result = File::create(&temporary_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write_checkpoint(&mut writer, settings_hash, passes, image)?;
+        writer.flush()
+    }).and_then(|_| std::fs::rename(&temporary_path, path));
+
+    result.map_err(|e| format!("Could not save checkpoint '{}': {}", path, e))
+}
+
+/// Loads a checkpoint of a render at `resolution`, a checkpoint of another size is refused.
+pub fn load_checkpoint(path: &str, resolution: &glm::Vector2<u32>) -> Result<Checkpoint, String> {
+    let file = File::open(path).map_err(|e| format!("Could not open checkpoint '{}': {}", path, e))?;
+
+    read_checkpoint(&mut BufReader::new(file), resolution.x as usize, resolution.y as usize)
+        .map_err(|e| format!("Could not read checkpoint '{}': {}", path, e))
+}
+
+/// Renders like `render`, but saves the progress to `path` whenever `interval` has passed since the last save
+/// and once more when done. If `path` already holds a checkpoint of the same render it continues from there,
+/// a checkpoint made with another scene or other settings is refused.
+pub fn render_with_checkpoints(scene: &Arc<dyn Scene + Sync + Send>, camera: &Camera, resolution: &glm::Vector2<u32>, settings: &RenderSettings, integrator: &Arc<dyn Integrator + Sync + Send>, settings_hash: u64, path: &str, interval: Duration) -> Result<ImageBuffer, String> {
+    let (mut image, first_pass) = if std::path::Path::new(path).exists() {
+        let checkpoint = load_checkpoint(path, resolution)?;
+        if checkpoint.settings_hash != settings_hash {
+            return Err(format!("Checkpoint '{}' belongs to a different scene or different settings, remove it to start over", path));
+        }
+
+        println!("Resuming from {} after {} passes", path, checkpoint.passes);
+        (checkpoint.image, checkpoint.passes)
+    } else {
+        (ImageBuffer::with_aovs(resolution.x as usize, resolution.y as usize, &collected_aovs(settings)), 0)
+    };
+
+    let passes = match &settings.adaptive {
+        Some(adaptive) => adaptive.max_samples,
+        None => settings.samples,
+    };
+
+    let mut last_save = Instant::now();
+    let mut save_result = Ok(());
+    let mut finished_passes = first_pass;
+    accumulate_passes(scene, camera, settings, integrator, first_pass..passes.max(first_pass), &mut image, &mut |_, _| {}, &mut |passes, image| {
+        finished_passes = passes;
+        if save_result.is_ok() && last_save.elapsed() >= interval {
+            save_result = save_checkpoint(path, settings_hash, passes, image);
+            last_save = Instant::now();
+        }
+    });
+
+    save_result?;
+    save_checkpoint(path, settings_hash, finished_passes, &image)?;
+
+    Ok(image)
+}
+
+fn write_checkpoint<W: Write>(writer: &mut W, settings_hash: u64, passes: u32, image: &ImageBuffer) -> std::io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&settings_hash.to_le_bytes())?;
+    writer.write_all(&passes.to_le_bytes())?;
+    writer.write_all(&(image.width as u32).to_le_bytes())?;
+    writer.write_all(&(image.height as u32).to_le_bytes())?;
+    writer.write_all(&(image.aovs.len() as u32).to_le_bytes())?;
+
+    write_buffers(writer, image)?;
+    for (aov, aov_image) in &image.aovs {
+        let name = aov.name().as_bytes();
+        writer.write_all(&[name.len() as u8])?;
+        writer.write_all(name)?;
+        write_buffers(writer, aov_image)?;
+    }
+
+    Ok(())
+}
+
+fn write_buffers<W: Write>(writer: &mut W, image: &ImageBuffer) -> std::io::Result<()> {
+    let mut bytes = Vec::with_capacity(image.pixels.len() * 4);
+    bytes.extend(image.pixels.iter().flat_map(|x| x.to_le_bytes()));
+    bytes.extend(image.weights.iter().flat_map(|x| x.to_le_bytes()));
+    bytes.extend(image.sample_counts.iter().flat_map(|x| x.to_le_bytes()));
+    bytes.extend(image.luminance_sums.iter().flat_map(|x| x.to_le_bytes()));
+    bytes.extend(image.luminance_square_sums.iter().flat_map(|x| x.to_le_bytes()));
+
+    writer.write_all(&bytes)
+}
+
+fn read_checkpoint<R: Read>(reader: &mut R, expected_width: usize, expected_height: usize) -> std::io::Result<Checkpoint> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string());
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a checkpoint file"));
+    }
+
+    if read_u32(reader)? != VERSION {
+        return Err(invalid("checkpoint was written by another version"));
+    }
+
+    let settings_hash = read_u64(reader)?;
+    let passes = read_u32(reader)?;
+    let width = read_u32(reader)? as usize;
+    let height = read_u32(reader)? as usize;
+    let aov_count = read_u32(reader)?;
+    // Checked before allocating, the size comes from the file and may be anything
+    if (width, height) != (expected_width, expected_height) {
+        return Err(invalid(&format!("checkpoint is {}x{}, the render is {}x{}", width, height, expected_width, expected_height)));
+    }
+
+    let mut image = ImageBuffer::new(width, height);
+    read_buffers(reader, &mut image)?;
+
+    for _ in 0..aov_count {
+        let mut length = [0u8];
+        reader.read_exact(&mut length)?;
+        let mut name = vec![0u8; length[0] as usize];
+        reader.read_exact(&mut name)?;
+
+        let aov = std::str::from_utf8(&name).ok()
+            .and_then(AovType::from_name)
+            .ok_or_else(|| invalid("unknown pass"))?;
+
+        let mut aov_image = ImageBuffer::new(width, height);
+        read_buffers(reader, &mut aov_image)?;
+        image.aovs.push((aov, aov_image));
+    }
+
+    Ok(Checkpoint {
+        settings_hash,
+        passes,
+        image,
+    })
+}
+
+fn read_buffers<R: Read>(reader: &mut R, image: &mut ImageBuffer) -> std::io::Result<()> {
+    for x in image.pixels.iter_mut().chain(image.weights.iter_mut()) {
+        *x = f32::from_le_bytes(read_bytes(reader)?);
+    }
+
+    for x in image.sample_counts.iter_mut() {
+        *x = u32::from_le_bytes(read_bytes(reader)?);
+    }
+
+    for x in image.luminance_sums.iter_mut().chain(image.luminance_square_sums.iter_mut()) {
+        *x = f64::from_le_bytes(read_bytes(reader)?);
+    }
+
+    Ok(())
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::render;
+    use crate::renderer::test_scene::{create_test_scene, create_test_camera};
+    use crate::integrator::path_tracer::PathTracingIntegrator;
+    use crate::integrator::termination::PathTermination;
+
+    fn checkpoint_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("rust-rt-{}-{}.checkpoint", name, std::process::id())).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn settings_hash_should_ignore_sample_count_and_threads() {
+        let settings = RenderSettings::new();
+        let mut more_samples = settings.clone();
+        more_samples.samples *= 4;
+        more_samples.thread_count += 1;
+        let mut other_depth = settings.clone();
+        other_depth.path_termination.max_depth += 1;
+        let mut other_frame = settings.clone();
+        other_frame.frame = 1;
+
+        let hash = SettingsHash::new().add_settings(&settings);
+
+        assert_eq!(hash, SettingsHash::new().add_settings(&more_samples));
+        assert_ne!(hash, SettingsHash::new().add_settings(&other_depth));
+        assert_ne!(hash, SettingsHash::new().add_settings(&other_frame));
+        assert_ne!(SettingsHash::new().add(b"ab").add(b"c"), SettingsHash::new().add(b"a").add(b"bc"));
+    }
+
+    #[test]
+    fn checkpoint_should_round_trip() {
+        let mut image = ImageBuffer::with_aovs(2, 1, &[AovType::Depth]);
+        image.add_sample(1, 0, glm::vec3(1.0, 2.0, 3.0), 0.5);
+        image.record_sample(1, 0, glm::vec3(1.0, 2.0, 3.0));
+        let mut bytes = Vec::new();
+
+        write_checkpoint(&mut bytes, 7, 3, &image).unwrap();
+        let result = read_checkpoint(&mut bytes.as_slice(), 2, 1).unwrap();
+
+        assert_eq!((result.settings_hash, result.passes), (7, 3));
+        assert_eq!(result.image.pixel(1, 0), glm::vec3(1.0, 2.0, 3.0));
+        assert_eq!(result.image.sample_count(1, 0), 1);
+        assert_eq!(result.image.aovs()[0].0, AovType::Depth);
+        assert!(read_checkpoint(&mut &bytes[..bytes.len() - 1], 2, 1).is_err());
+        assert!(read_checkpoint(&mut bytes.as_slice(), 4, 1).is_err());
+    }
+
+    #[test]
+    fn resumed_render_should_match_uninterrupted_render() {
+        let scene = create_test_scene();
+        let resolution = glm::Vector2::<u32>::new(16, 12);
+        let camera = create_test_camera(&resolution);
+        let integrator: Arc<dyn Integrator + Sync + Send> = Arc::new(PathTracingIntegrator::new(PathTermination::new()));
+        let mut settings = RenderSettings::new();
+        settings.samples = 4;
+        settings.thread_count = 2;
+        let hash = SettingsHash::new().add_settings(&settings).value();
+        let path = checkpoint_path("resume");
+
+        let expected = render(&scene, &camera, &resolution, &settings, &integrator);
+
+        // Stop halfway, then continue with the full budget
+        let mut half = settings.clone();
+        half.samples = 2;
+        render_with_checkpoints(&scene, &camera, &resolution, &half, &integrator, hash, &path, Duration::from_secs(0)).unwrap();
+        assert_eq!(load_checkpoint(&path, &resolution).unwrap().passes, 2);
+        let result = render_with_checkpoints(&scene, &camera, &resolution, &settings, &integrator, hash, &path, Duration::from_secs(0)).unwrap();
+
+        assert!(render_with_checkpoints(&scene, &camera, &resolution, &settings, &integrator, hash + 1, &path, Duration::from_secs(0)).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.pixels(), expected.pixels());
+    }
+}
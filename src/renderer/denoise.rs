@@ -0,0 +1,238 @@
+use crate::renderer::ImageBuffer;
+use crate::renderer::aov::AovType;
+use std::sync::Arc;
+use std::thread;
+
+/// The passes the denoiser uses to tell edges from noise, the image has to be rendered with them.
+pub const GUIDE_AOVS: [AovType; 3] = [AovType::Albedo, AovType::Normal, AovType::Depth];
+
+/// Joint bilateral filter. Noise is averaged away with neighbouring pixels, but only with neighbours
+/// that look like the same surface in the albedo, normal and depth passes, which keeps edges and textures sharp.
+/// The noisy color itself is not used to find edges since at low sample counts it is mostly noise.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Denoiser {
+    /// Half width of the filter window in pixels.
+    pub radius: usize,
+    pub sigma_spatial: f32,
+    pub sigma_albedo: f32,
+    pub sigma_normal: f32,
+    /// Relative to the depth of the center pixel, so the filter behaves the same near and far.
+    pub sigma_depth: f32,
+}
+
+impl Denoiser {
+    pub fn new() -> Self {
+        Denoiser {
+            radius: 5,
+            sigma_spatial: 3.0,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.2,
+            sigma_depth: 0.05,
+        }
+    }
+
+    /// Returns a filtered copy of the beauty image, guided by whichever of `GUIDE_AOVS` the image has.
+    pub fn denoise(&self, image: &ImageBuffer, thread_count: usize) -> ImageBuffer {
+        let width = image.width();
+        let height = image.height();
+        let albedo = image.aov(AovType::Albedo);
+        let input = Arc::new(FilterInput::new(image));
+
+        // Every thread filters its own band of rows
+        let rows_per_thread = (height + thread_count.max(1) - 1) / thread_count.max(1);
+        let mut threads = Vec::new();
+        for first_row in (0..height).step_by(rows_per_thread.max(1)) {
+            let denoiser = *self;
+            let input = input.clone();
+            let last_row = (first_row + rows_per_thread).min(height);
+            threads.push(thread::spawn(move || {
+                (first_row * width..last_row * width)
+                    .map(|index| denoiser.filter_pixel(index % width, index / width, &input))
+                    .collect::<Vec<glm::Vec3>>()
+            }));
+        }
+
+        let mut filtered = Vec::with_capacity(width * height);
+        for thread in threads {
+            filtered.extend(thread.join().unwrap());
+        }
+
+        let mut result = ImageBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut color = filtered[y * width + x];
+                if let Some(albedo) = albedo {
+                    color = remodulate(color, albedo.pixel(x, y));
+                }
+
+                result.add_sample(x, y, color, 1.0);
+            }
+        }
+
+        result
+    }
+
+    fn filter_pixel(&self, x: usize, y: usize, input: &FilterInput) -> glm::Vec3 {
+        let width = input.width;
+        let center = y * width + x;
+        let center_albedo = input.albedo.as_ref().map(|buffer| buffer[center]);
+        let center_normal = input.normal.as_ref().map(|buffer| buffer[center]);
+        let center_depth = input.depth.as_ref().map(|buffer| buffer[center]);
+
+        let mut sum = glm::vec3(0.0, 0.0, 0.0);
+        let mut weight_sum = 0.0;
+        for qy in y.saturating_sub(self.radius)..(y + self.radius + 1).min(input.height) {
+            for qx in x.saturating_sub(self.radius)..(x + self.radius + 1).min(width) {
+                let neighbour = qy * width + qx;
+                let dx = qx as f32 - x as f32;
+                let dy = qy as f32 - y as f32;
+                let mut exponent = (dx * dx + dy * dy) / (2.0 * self.sigma_spatial * self.sigma_spatial);
+
+                if let (Some(albedo), Some(center)) = (&input.albedo, center_albedo) {
+                    exponent += squared_distance(albedo[neighbour], center) / (2.0 * self.sigma_albedo * self.sigma_albedo);
+                }
+
+                if let (Some(normal), Some(center)) = (&input.normal, center_normal) {
+                    exponent += squared_distance(normal[neighbour], center) / (2.0 * self.sigma_normal * self.sigma_normal);
+                }
+
+                if let (Some(depth), Some(center)) = (&input.depth, center_depth) {
+                    let sigma = self.sigma_depth * center.max(1e-3);
+                    let difference = depth[neighbour] - center;
+                    exponent += difference * difference / (2.0 * sigma * sigma);
+                }
+
+                let weight = (-exponent).exp();
+                sum = sum + input.illumination[neighbour] * weight;
+                weight_sum += weight;
+            }
+        }
+
+        // The center pixel always has weight one, so weight_sum never ends up zero
+        sum / weight_sum
+    }
+}
+
+// The pixels the filter reads, copied out of the image so the worker threads can share them
+struct FilterInput {
+    width: usize,
+    height: usize,
+    // The lighting without the surface color, filtering it keeps textures from being blurred along with the noise
+    illumination: Vec<glm::Vec3>,
+    albedo: Option<Vec<glm::Vec3>>,
+    normal: Option<Vec<glm::Vec3>>,
+    depth: Option<Vec<f32>>,
+}
+
+impl FilterInput {
+    fn new(image: &ImageBuffer) -> Self {
+        let width = image.width();
+        let height = image.height();
+        let pixels = |buffer: &ImageBuffer| -> Vec<glm::Vec3> {
+            (0..width * height).map(|index| buffer.pixel(index % width, index / width)).collect()
+        };
+
+        let color = pixels(image);
+        let albedo = image.aov(AovType::Albedo).map(pixels);
+        let illumination = match &albedo {
+            Some(albedo) => color.iter().zip(albedo.iter()).map(|(color, albedo)| demodulate(*color, *albedo)).collect(),
+            None => color,
+        };
+
+        FilterInput {
+            width,
+            height,
+            illumination,
+            albedo,
+            normal: image.aov(AovType::Normal).map(pixels),
+            depth: image.aov(AovType::Depth).map(|buffer| pixels(buffer).iter().map(|x| x.x).collect()),
+        }
+    }
+}
+
+fn squared_distance(a: glm::Vec3, b: glm::Vec3) -> f32 {
+    let difference = a - b;
+    glm::dot(difference, difference)
+}
+
+// Surfaces without albedo (pure emitters, nothing hit) are left alone
+const MIN_ALBEDO: f32 = 0.01;
+
+fn demodulate(color: glm::Vec3, albedo: glm::Vec3) -> glm::Vec3 {
+    let divide = |c: f32, a: f32| if a > MIN_ALBEDO { c / a } else { c };
+    glm::vec3(divide(color.x, albedo.x), divide(color.y, albedo.y), divide(color.z, albedo.z))
+}
+
+fn remodulate(color: glm::Vec3, albedo: glm::Vec3) -> glm::Vec3 {
+    let multiply = |c: f32, a: f32| if a > MIN_ALBEDO { c * a } else { c };
+    glm::vec3(multiply(color.x, albedo.x), multiply(color.y, albedo.y), multiply(color.z, albedo.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, Rng};
+    use rand::rngs::StdRng;
+
+    // Left half is a red surface facing up, right half a white one facing the camera, both with noisy lighting
+    fn create_noisy_image() -> ImageBuffer {
+        let mut image = ImageBuffer::with_aovs(16, 8, &GUIDE_AOVS);
+        let mut rng = StdRng::seed_from_u64(7);
+        for y in 0..8 {
+            for x in 0..16 {
+                let (albedo, normal) = if x < 8 {
+                    (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0))
+                } else {
+                    (glm::vec3(1.0, 1.0, 1.0), glm::vec3(0.0, 0.0, -1.0))
+                };
+                let lighting = 0.5 + (rng.gen::<f32>() - 0.5) * 0.8;
+
+                image.add_sample(x, y, albedo * lighting, 1.0);
+                image.aovs[0].1.add_sample(x, y, albedo, 1.0);
+                image.aovs[1].1.add_sample(x, y, normal, 1.0);
+                image.aovs[2].1.add_sample(x, y, glm::vec3(10.0, 10.0, 10.0), 1.0);
+            }
+        }
+
+        image
+    }
+
+    fn variance(image: &ImageBuffer, columns: std::ops::Range<usize>) -> f32 {
+        let values: Vec<f32> = (0..image.height())
+            .flat_map(|y| columns.clone().map(move |x| (x, y)))
+            .map(|(x, y)| image.pixel(x, y).x)
+            .collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+
+        values.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / values.len() as f32
+    }
+
+    #[test]
+    fn denoise_should_reduce_noise() {
+        let image = create_noisy_image();
+
+        let result = Denoiser::new().denoise(&image, 3);
+
+        assert!(variance(&result, 8..16) < variance(&image, 8..16) * 0.25);
+    }
+
+    #[test]
+    fn denoise_should_not_blur_across_guide_edges() {
+        let image = create_noisy_image();
+
+        let result = Denoiser::new().denoise(&image, 1);
+
+        // No white light may leak into the red surface
+        for y in 0..8 {
+            assert!(result.pixel(7, y).y < 1e-6);
+            assert!(result.pixel(8, y).y > 0.2);
+        }
+    }
+
+    #[test]
+    fn denoise_should_not_depend_on_thread_count() {
+        let image = create_noisy_image();
+
+        assert_eq!(Denoiser::new().denoise(&image, 1).pixels(), Denoiser::new().denoise(&image, 5).pixels());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::color::luminance;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMapping {
+    /// Cuts everything above 1 off, fine for scenes without bright highlights.
+    Clamp,
+    /// Compresses highlights by luminance, keeps hues but looks a bit flat.
+    Reinhard,
+    /// Narkowicz' fit of the ACES filmic curve, more contrast and a soft shoulder.
+    Aces,
+}
+
+impl ToneMapping {
+    pub fn from_name(name: &str) -> Option<ToneMapping> {
+        match name {
+            "clamp" => Some(ToneMapping::Clamp),
+            "reinhard" => Some(ToneMapping::Reinhard),
+            "aces" => Some(ToneMapping::Aces),
+            _ => None,
+        }
+    }
+
+    pub fn next(&self) -> ToneMapping {
+        match self {
+            ToneMapping::Clamp => ToneMapping::Reinhard,
+            ToneMapping::Reinhard => ToneMapping::Aces,
+            ToneMapping::Aces => ToneMapping::Clamp,
+        }
+    }
+
+    fn apply(&self, color: glm::Vec3) -> glm::Vec3 {
+        match self {
+            ToneMapping::Clamp => color,
+            ToneMapping::Reinhard => color / (1.0 + luminance(&color).max(0.0)),
+            ToneMapping::Aces => {
+                let curve = |x: f32| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+                glm::vec3(curve(color.x), curve(color.y), curve(color.z))
+            }
+        }
+    }
+}
+
+/// Turns linear radiance into display values: exposure, then tone mapping, then sRGB encoding.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DisplayTransform {
+    /// In stops, every +1 doubles the brightness.
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+    /// Off for data like normals and depth, which should reach the file unchanged.
+    pub srgb: bool,
+}
+
+impl DisplayTransform {
+    pub fn new() -> Self {
+        DisplayTransform {
+            exposure: 0.0,
+            tone_mapping: ToneMapping::Clamp,
+            srgb: true,
+        }
+    }
+
+    /// Writes values as they are, only clamped to [0, 1].
+    pub fn raw() -> Self {
+        DisplayTransform {
+            exposure: 0.0,
+            tone_mapping: ToneMapping::Clamp,
+            srgb: false,
+        }
+    }
+
+    /// Display value in [0, 1] for a linear color.
+    pub fn apply(&self, color: glm::Vec3) -> glm::Vec3 {
+        let exposed = color * 2.0f32.powf(self.exposure);
+        let mapped = self.tone_mapping.apply(glm::vec3(exposed.x.max(0.0), exposed.y.max(0.0), exposed.z.max(0.0)));
+        let encode = |x: f32| {
+            let x = x.clamp(0.0, 1.0);
+            if self.srgb { srgb_encode(x) } else { x }
+        };
+
+        glm::vec3(encode(mapped.x), encode(mapped.y), encode(mapped.z))
+    }
+
+    pub fn to_u8(self, color: glm::Vec3) -> [u8; 3] {
+        let display = self.apply(color);
+
+        // Rounding instead of truncating, otherwise 1.0 - epsilon already drops a level
+        [
+            (display.x * 255.0 + 0.5) as u8,
+            (display.y * 255.0 + 0.5) as u8,
+            (display.z * 255.0 + 0.5) as u8,
+        ]
+    }
+}
+
+/// The sRGB transfer function, for a linear value in [0, 1].
+pub fn srgb_encode(x: f32) -> f32 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_transform_should_clamp_instead_of_wrapping() {
+        let transform = DisplayTransform::raw();
+
+        assert_eq!(transform.to_u8(glm::vec3(2.0, -1.0, 0.5)), [255, 0, 128]);
+    }
+
+    #[test]
+    fn srgb_encode_should_match_reference_values() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_encode(0.18) - 0.4614).abs() < 1e-3);
+    }
+
+    #[test]
+    fn exposure_should_double_per_stop() {
+        let mut transform = DisplayTransform::raw();
+        transform.exposure = 1.0;
+
+        assert_eq!(transform.apply(glm::vec3(0.25, 0.25, 0.25)), glm::vec3(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn tone_mapping_should_compress_highlights_below_one() {
+        for tone_mapping in &[ToneMapping::Reinhard, ToneMapping::Aces] {
+            let mut transform = DisplayTransform::raw();
+            transform.tone_mapping = *tone_mapping;
+
+            let bright = transform.apply(glm::vec3(2.0, 2.0, 2.0));
+            let brighter = transform.apply(glm::vec3(4.0, 4.0, 4.0));
+
+            assert!(bright.x < 1.0);
+            assert!(brighter.x > bright.x);
+        }
+    }
+}
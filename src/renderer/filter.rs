@@ -0,0 +1,131 @@
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FilterType {
+    Box,
+    Tent,
+    Gaussian,
+    /// Mitchell-Netravali with B = C = 1/3, sharper than a gaussian but has small negative lobes.
+    Mitchell,
+}
+
+impl FilterType {
+    pub fn from_name(name: &str) -> Option<FilterType> {
+        match name {
+            "box" => Some(FilterType::Box),
+            "tent" => Some(FilterType::Tent),
+            "gaussian" => Some(FilterType::Gaussian),
+            "mitchell" => Some(FilterType::Mitchell),
+            _ => None,
+        }
+    }
+
+    /// A radius that gives sensible results for the filter when none is configured.
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            FilterType::Box => 0.5,
+            FilterType::Tent => 1.0,
+            FilterType::Gaussian => 1.5,
+            FilterType::Mitchell => 2.0,
+        }
+    }
+}
+
+/// Pixel reconstruction filter, decides how much a sample contributes to the pixels around it.
+/// `radius` is measured in pixels from the center of the filter.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Filter {
+    pub filter_type: FilterType,
+    pub radius: f32,
+}
+
+impl Filter {
+    pub fn new(filter_type: FilterType, radius: f32) -> Self {
+        Filter {
+            filter_type,
+            radius,
+        }
+    }
+
+    /// Weight of a sample at offset (dx, dy) from the pixel center. All filters are separable.
+    pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x > self.radius {
+            return 0.0;
+        }
+
+        match self.filter_type {
+            FilterType::Box => 1.0,
+            FilterType::Tent => 1.0 - x / self.radius,
+            FilterType::Gaussian => {
+                // Shifted down so the filter reaches zero exactly at the radius instead of being cut off
+                let alpha = 2.0 / (self.radius * self.radius);
+                (-alpha * x * x).exp() - (-alpha * self.radius * self.radius).exp()
+            }
+            FilterType::Mitchell => mitchell_1d(2.0 * x / self.radius, 1.0 / 3.0, 1.0 / 3.0),
+        }
+    }
+}
+
+// The Mitchell-Netravali cubic, defined on [0, 2]
+fn mitchell_1d(x: f32, b: f32, c: f32) -> f32 {
+    if x > 1.0 {
+        ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x +
+            (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+    } else {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x +
+            (6.0 - 2.0 * b)) / 6.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_filter_should_be_constant_inside_radius() {
+        let filter = Filter::new(FilterType::Box, 0.5);
+
+        assert_eq!(filter.evaluate(0.0, 0.0), 1.0);
+        assert_eq!(filter.evaluate(0.4, -0.3), 1.0);
+        assert_eq!(filter.evaluate(0.6, 0.0), 0.0);
+    }
+
+    #[test]
+    fn tent_filter_should_fall_off_linearly() {
+        let filter = Filter::new(FilterType::Tent, 2.0);
+
+        assert_eq!(filter.evaluate(0.0, 0.0), 1.0);
+        assert_eq!(filter.evaluate(1.0, 0.0), 0.5);
+        assert_eq!(filter.evaluate(2.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn filters_should_vanish_outside_radius() {
+        for filter_type in &[FilterType::Box, FilterType::Tent, FilterType::Gaussian, FilterType::Mitchell] {
+            let filter = Filter::new(*filter_type, 1.5);
+
+            assert_eq!(filter.evaluate(1.6, 0.0), 0.0);
+            assert_eq!(filter.evaluate(0.0, -1.6), 0.0);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+        }
+    }
+
+    #[test]
+    fn gaussian_filter_should_reach_zero_at_radius() {
+        let filter = Filter::new(FilterType::Gaussian, 1.5);
+
+        assert!(filter.evaluate(1.5, 0.0).abs() < 1e-6);
+        assert_eq!(filter.evaluate(0.7, 0.2), filter.evaluate(-0.7, -0.2));
+    }
+
+    #[test]
+    fn mitchell_filter_should_have_negative_lobes() {
+        let filter = Filter::new(FilterType::Mitchell, 2.0);
+
+        assert!((filter.evaluate(0.0, 0.0) - (8.0 / 9.0) * (8.0 / 9.0)).abs() < 1e-6);
+        assert!(filter.evaluate(1.5, 0.0) < 0.0);
+    }
+}
@@ -0,0 +1,57 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+const GOLDEN_GAMMA: u64 = 0x9E3779B97F4A7C15;
+
+/// The random stream of one sample. It only depends on what is being rendered and never on which thread
+/// renders it or in what order, so the same seed gives the same image on any machine and with any thread count.
+/// `sample_index` counts the samples the pixel already had, so progressive renders continue the sequence.
+pub fn sample_rng(seed: u64, frame: u32, x: usize, y: usize, sample_index: u32) -> StdRng {
+    StdRng::seed_from_u64(stream_seed(seed, frame, x, y, sample_index))
+}
+
+fn stream_seed(seed: u64, frame: u32, x: usize, y: usize, sample_index: u32) -> u64 {
+    hash(pixel_seed(seed, frame, x, y), sample_index as u64)
+}
+
+/// Identifies a pixel of a frame, samplers derive their per pixel scrambling from it.
+pub fn pixel_seed(seed: u64, frame: u32, x: usize, y: usize) -> u64 {
+    [frame as u64, x as u64, y as u64].iter()
+        .fold(mix(seed), |hash, value| self::hash(hash, *value))
+}
+
+/// Combines a hash with another value into a new, well mixed hash.
+pub fn hash(hash: u64, value: u64) -> u64 {
+    mix(hash.wrapping_add(GOLDEN_GAMMA) ^ value)
+}
+
+// The SplitMix64 finalizer, every input bit affects every output bit
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    #[test]
+    fn sample_rng_should_repeat_for_the_same_sample() {
+        assert_eq!(sample_rng(1, 2, 3, 4, 5).next_u64(), sample_rng(1, 2, 3, 4, 5).next_u64());
+    }
+
+    #[test]
+    fn stream_seed_should_differ_for_every_input() {
+        let base = stream_seed(1, 2, 3, 4, 5);
+
+        assert_ne!(base, stream_seed(0, 2, 3, 4, 5));
+        assert_ne!(base, stream_seed(1, 3, 3, 4, 5));
+        assert_ne!(base, stream_seed(1, 2, 4, 4, 5));
+        assert_ne!(base, stream_seed(1, 2, 3, 5, 5));
+        assert_ne!(base, stream_seed(1, 2, 3, 4, 6));
+        // Swapped coordinates are a different pixel
+        assert_ne!(stream_seed(1, 2, 3, 4, 5), stream_seed(1, 2, 4, 3, 5));
+    }
+}
@@ -0,0 +1,150 @@
+use rand::rngs::StdRng;
+use crate::renderer::random::{sample_rng, pixel_seed};
+
+pub mod independent;
+pub mod stratified;
+pub mod halton;
+pub mod sobol;
+
+use crate::renderer::sampler::independent::IndependentSampler;
+use crate::renderer::sampler::stratified::StratifiedSampler;
+use crate::renderer::sampler::halton::HaltonSampler;
+use crate::renderer::sampler::sobol::SobolSampler;
+
+/// The largest f32 below 1, samples are clamped to it so they stay inside [0, 1).
+pub const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Hands out the random numbers of one pixel sample.
+/// Every call moves on to the next dimension of the sample, so the camera, light selection and
+/// BSDF sampling each get their own dimensions. Samplers that know which sample of the pixel they
+/// are producing spread the values of a dimension more evenly over the pixel's samples than white noise.
+pub trait Sampler {
+    /// The next number in [0, 1).
+    fn get_1d(&mut self) -> f32;
+
+    /// The next pair of numbers in [0, 1), well distributed as a pair.
+    fn get_2d(&mut self) -> glm::Vec2;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SamplerType {
+    /// Plain white noise.
+    Independent,
+    /// Jittered strata, needs to know the number of samples per pixel up front.
+    Stratified,
+    /// Halton sequence, randomly shifted per pixel.
+    Halton,
+    /// Sobol sequence with Owen scrambling.
+    Sobol,
+}
+
+impl SamplerType {
+    pub fn from_name(name: &str) -> Option<SamplerType> {
+        match name {
+            "independent" => Some(SamplerType::Independent),
+            "stratified" => Some(SamplerType::Stratified),
+            "halton" => Some(SamplerType::Halton),
+            "sobol" => Some(SamplerType::Sobol),
+            _ => None,
+        }
+    }
+}
+
+/// Creates the samplers of a render. Like `sample_rng` a sampler only depends on what is being rendered,
+/// `samples_per_pixel` is the number of samples the render aims for.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerFactory {
+    sampler_type: SamplerType,
+    seed: u64,
+    frame: u32,
+    samples_per_pixel: u32,
+}
+
+impl SamplerFactory {
+    pub fn new(sampler_type: SamplerType, seed: u64, frame: u32, samples_per_pixel: u32) -> Self {
+        SamplerFactory {
+            sampler_type,
+            seed,
+            frame,
+            samples_per_pixel,
+        }
+    }
+
+    /// The sampler of sample `sample_index` of pixel (x, y).
+    pub fn create(&self, x: usize, y: usize, sample_index: u32) -> Box<dyn Sampler> {
+        let rng: StdRng = sample_rng(self.seed, self.frame, x, y, sample_index);
+        let pixel = pixel_seed(self.seed, self.frame, x, y);
+
+        match self.sampler_type {
+            SamplerType::Independent => Box::new(IndependentSampler::new(rng)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(pixel, sample_index, self.samples_per_pixel, rng)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(pixel, sample_index)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(pixel, sample_index)),
+        }
+    }
+}
+
+// Maps the upper bits of a hash to [0, 1)
+fn to_unit_float(bits: u32) -> f32 {
+    ((bits >> 8) as f32 / (1u32 << 24) as f32).min(ONE_MINUS_EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Discrepancy-like measure: the largest deviation of any [0, x) interval from its expected share
+    fn worst_interval_error(values: &[f32]) -> f32 {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n = sorted.len() as f32;
+
+        sorted.iter().enumerate()
+            .map(|(i, x)| (x - i as f32 / n).abs().max((x - (i + 1) as f32 / n).abs()))
+            .fold(0.0, f32::max)
+    }
+
+    fn first_dimension(sampler_type: SamplerType, samples: u32) -> Vec<f32> {
+        (0..samples)
+            .map(|i| SamplerFactory::new(sampler_type, 3, 0, samples).create(5, 7, i).get_1d())
+            .collect()
+    }
+
+    #[test]
+    fn samples_should_be_in_unit_interval() {
+        for sampler_type in &[SamplerType::Independent, SamplerType::Stratified, SamplerType::Halton, SamplerType::Sobol] {
+            for i in 0..64 {
+                let mut sampler = SamplerFactory::new(*sampler_type, 1, 2, 64).create(3, 4, i);
+                for _ in 0..40 {
+                    let value = sampler.get_1d();
+                    let pair = sampler.get_2d();
+                    assert!((0.0..1.0).contains(&value));
+                    assert!((0.0..1.0).contains(&pair.x) && (0.0..1.0).contains(&pair.y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_should_cover_the_pixel_more_evenly_than_white_noise() {
+        let independent = worst_interval_error(&first_dimension(SamplerType::Independent, 64));
+
+        for sampler_type in &[SamplerType::Stratified, SamplerType::Halton, SamplerType::Sobol] {
+            let error = worst_interval_error(&first_dimension(*sampler_type, 64));
+            assert!(error <= 1.0 / 64.0 + 1e-6, "{:?} has error {}", sampler_type, error);
+            assert!(error < independent);
+        }
+    }
+
+    #[test]
+    fn samplers_should_repeat_for_the_same_sample() {
+        for sampler_type in &[SamplerType::Independent, SamplerType::Stratified, SamplerType::Halton, SamplerType::Sobol] {
+            let factory = SamplerFactory::new(*sampler_type, 1, 2, 16);
+            let mut a = factory.create(3, 4, 5);
+            let mut b = factory.create(3, 4, 5);
+            for _ in 0..10 {
+                assert_eq!(a.get_1d(), b.get_1d());
+            }
+        }
+    }
+}
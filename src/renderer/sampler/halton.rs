@@ -0,0 +1,91 @@
+use crate::renderer::random::hash;
+use crate::renderer::sampler::{Sampler, to_unit_float, ONE_MINUS_EPSILON};
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, dimension `d` is the radical inverse of the sample index in the d-th prime.
+/// Every pixel shifts each dimension by its own random offset (a Cranley-Patterson rotation),
+/// otherwise all pixels would sample the exact same positions. Dimensions past the prime table get plain random values,
+/// reusing its bases would make those dimensions repeat earlier ones.
+pub struct HaltonSampler {
+    pixel_seed: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    pub fn new(pixel_seed: u64, sample_index: u32) -> Self {
+        HaltonSampler {
+            pixel_seed,
+            sample_index,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let dimension_seed = hash(self.pixel_seed, dimension as u64);
+        let base = match PRIMES.get(dimension as usize) {
+            Some(base) => *base,
+            None => return to_unit_float(hash(dimension_seed, self.sample_index as u64) as u32),
+        };
+
+        let value = radical_inverse(base, self.sample_index) + to_unit_float(dimension_seed as u32);
+        (value - value.floor()).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> glm::Vec2 {
+        glm::vec2(self.get_1d(), self.get_1d())
+    }
+}
+
+/// Mirrors the digits of `index` written in `base` around the decimal point.
+pub fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut inverse_base_power = 1.0;
+    let mut reversed = 0.0;
+    while index > 0 {
+        inverse_base_power *= inverse_base;
+        reversed += (index % base) as f64 * inverse_base_power;
+        index /= base;
+    }
+
+    (reversed as f32).min(ONE_MINUS_EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radical_inverse_should_mirror_digits() {
+        assert_eq!(radical_inverse(2, 0), 0.0);
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 2), 0.25);
+        assert_eq!(radical_inverse(2, 3), 0.75);
+        assert!((radical_inverse(3, 1) - 1.0 / 3.0).abs() < 1e-6);
+        assert!((radical_inverse(3, 5) - 7.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn dimensions_past_the_prime_table_should_not_repeat_earlier_ones() {
+        let values = |sample_index| {
+            let mut sampler = HaltonSampler::new(5, sample_index);
+            (0..PRIMES.len() + 1).map(|_| sampler.get_1d()).collect::<Vec<f32>>()
+        };
+
+        // With a reused base, dimension 32 would move in step with dimension 0
+        let differences = (0..16)
+            .map(values)
+            .map(|x| (x[PRIMES.len()] - x[0]).rem_euclid(1.0))
+            .collect::<Vec<f32>>();
+        assert!(differences.iter().any(|x| (x - differences[0]).abs() > 1e-3));
+    }
+}
@@ -0,0 +1,24 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use crate::renderer::sampler::{Sampler, ONE_MINUS_EPSILON};
+
+/// White noise, every number is drawn independently of all other samples.
+pub struct IndependentSampler {
+    rng: StdRng,
+}
+
+impl IndependentSampler {
+    pub fn new(rng: StdRng) -> Self {
+        IndependentSampler { rng }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn get_1d(&mut self) -> f32 {
+        self.rng.gen::<f32>().min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> glm::Vec2 {
+        glm::vec2(self.get_1d(), self.get_1d())
+    }
+}
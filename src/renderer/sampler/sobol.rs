@@ -0,0 +1,162 @@
+use crate::renderer::random::hash;
+use crate::renderer::sampler::{Sampler, to_unit_float};
+
+const DIMENSIONS_PER_GROUP: u32 = 4;
+
+// Direction numbers of the first four Sobol dimensions (Joe & Kuo), the first one is the van der Corput sequence
+const DIRECTIONS: [[u32; 32]; 4] = [
+    van_der_corput_directions(),
+    directions(1, 0, [1, 0, 0]),
+    directions(2, 1, [1, 3, 0]),
+    directions(3, 1, [1, 3, 1]),
+];
+
+/// The Sobol sequence with Owen scrambling, following Burley's "Practical Hash-based Owen Scrambling".
+/// Only the first four Sobol dimensions are used: dimensions are handed out in groups of four and every group
+/// scrambles those four dimensions and shuffles the sample order with its own seed. That keeps each group
+/// well stratified and the groups uncorrelated, without needing direction numbers for hundreds of dimensions.
+pub struct SobolSampler {
+    pixel_seed: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(pixel_seed: u64, sample_index: u32) -> Self {
+        SobolSampler {
+            pixel_seed,
+            sample_index,
+            dimension: 0,
+        }
+    }
+
+    fn sample(&self, dimension: u32) -> f32 {
+        let group = dimension / DIMENSIONS_PER_GROUP;
+        let group_seed = hash(self.pixel_seed, group as u64);
+        let index = nested_uniform_scramble(self.sample_index, group_seed as u32);
+
+        let within_group = dimension % DIMENSIONS_PER_GROUP;
+        let value = sobol(index, within_group as usize);
+        to_unit_float(nested_uniform_scramble(value, hash(group_seed, within_group as u64) as u32))
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn get_1d(&mut self) -> f32 {
+        let value = self.sample(self.dimension);
+        self.dimension += 1;
+
+        value
+    }
+
+    fn get_2d(&mut self) -> glm::Vec2 {
+        // Pairs are (0, 1) and (2, 3) of a group, those are stratified together
+        if self.dimension % 2 == 1 {
+            self.dimension += 1;
+        }
+
+        let value = glm::vec2(self.sample(self.dimension), self.sample(self.dimension + 1));
+        self.dimension += 2;
+
+        value
+    }
+}
+
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let mut value = 0;
+    let mut bits = index;
+    let mut bit = 0;
+    while bits != 0 {
+        if bits & 1 == 1 {
+            value ^= DIRECTIONS[dimension][bit];
+        }
+        bits >>= 1;
+        bit += 1;
+    }
+
+    value
+}
+
+/// Owen scrambling: randomly flips every digit depending on the digits before it,
+/// the result is still a stratified sequence but decorrelated between seeds.
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+// Every output bit only depends on the input bits below it
+fn laine_karras_permutation(mut value: u32, seed: u32) -> u32 {
+    value ^= value.wrapping_mul(0x3d20adea);
+    value = value.wrapping_add(seed);
+    value = value.wrapping_mul((seed >> 16) | 1);
+    value ^= value.wrapping_mul(0x05526c56);
+    value ^= value.wrapping_mul(0x53a22864);
+
+    value
+}
+
+const fn van_der_corput_directions() -> [u32; 32] {
+    let mut result = [0; 32];
+    let mut i = 0;
+    while i < 32 {
+        result[i] = 1 << (31 - i);
+        i += 1;
+    }
+
+    result
+}
+
+// Expands the primitive polynomial of degree `degree` with coefficients `coefficients` and the initial
+// direction numbers `initial` into the 32 direction numbers of a dimension
+const fn directions(degree: usize, coefficients: u32, initial: [u32; 3]) -> [u32; 32] {
+    let mut result = [0; 32];
+    let mut i = 0;
+    while i < 32 {
+        if i < degree {
+            result[i] = initial[i] << (31 - i);
+        } else {
+            result[i] = result[i - degree] ^ (result[i - degree] >> degree);
+            let mut k = 1;
+            while k < degree {
+                if (coefficients >> (degree - 1 - k)) & 1 == 1 {
+                    result[i] ^= result[i - k];
+                }
+                k += 1;
+            }
+        }
+        i += 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sobol_should_match_the_reference_sequence() {
+        let to_float = |value: u32| value as f64 / 4294967296.0;
+
+        let second: Vec<f64> = (0..8).map(|i| to_float(sobol(i, 1))).collect();
+        assert_eq!(second, vec![0.0, 0.5, 0.75, 0.25, 0.625, 0.125, 0.375, 0.875]);
+
+        let third: Vec<f64> = (0..8).map(|i| to_float(sobol(i, 2))).collect();
+        assert_eq!(third, vec![0.0, 0.5, 0.75, 0.25, 0.375, 0.875, 0.625, 0.125]);
+    }
+
+    #[test]
+    fn scrambled_pairs_should_be_stratified() {
+        // 16 samples of a (0, 2)-sequence hit each of the 4 x 4 cells exactly once
+        let mut cells: Vec<(u32, u32)> = (0..16)
+            .map(|i| {
+                let mut sampler = SobolSampler::new(99, i);
+                let position = sampler.get_2d();
+                ((position.x * 4.0) as u32, (position.y * 4.0) as u32)
+            })
+            .collect();
+        cells.sort();
+        cells.dedup();
+
+        assert_eq!(cells.len(), 16);
+    }
+}
@@ -0,0 +1,126 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use crate::renderer::random::hash;
+use crate::renderer::sampler::{Sampler, ONE_MINUS_EPSILON};
+
+/// Splits every dimension into one stratum per sample and places each sample at a random spot in its own stratum.
+/// Which sample gets which stratum is shuffled per pixel and dimension, so the dimensions don't correlate.
+/// Samples past `samples_per_pixel` start a new round with a fresh shuffle.
+pub struct StratifiedSampler {
+    pixel_seed: u64,
+    sample_index: u32,
+    samples_per_pixel: u32,
+    dimension: u32,
+    rng: StdRng,
+}
+
+impl StratifiedSampler {
+    pub fn new(pixel_seed: u64, sample_index: u32, samples_per_pixel: u32, rng: StdRng) -> Self {
+        StratifiedSampler {
+            pixel_seed,
+            sample_index,
+            samples_per_pixel: samples_per_pixel.max(1),
+            dimension: 0,
+            rng,
+        }
+    }
+
+    // The stratum out of `strata` this sample covers in the current dimension
+    fn stratum(&mut self, strata: u32) -> u32 {
+        let round = self.sample_index / self.samples_per_pixel;
+        let index = self.sample_index % self.samples_per_pixel;
+        let scramble = hash(self.pixel_seed, (round as u64) << 32 | self.dimension as u64) as u32;
+        self.dimension += 1;
+
+        permute(index, strata, scramble)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn get_1d(&mut self) -> f32 {
+        let strata = self.samples_per_pixel;
+        let stratum = self.stratum(strata);
+
+        ((stratum as f32 + self.rng.gen::<f32>()) / strata as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> glm::Vec2 {
+        // A grid with at least one cell per sample, every sample lands in a different cell
+        let columns = (self.samples_per_pixel as f32).sqrt().ceil() as u32;
+        let rows = (self.samples_per_pixel + columns - 1) / columns;
+        let cell = self.stratum(columns * rows);
+
+        glm::vec2(
+            (((cell % columns) as f32 + self.rng.gen::<f32>()) / columns as f32).min(ONE_MINUS_EPSILON),
+            (((cell / columns) as f32 + self.rng.gen::<f32>()) / rows as f32).min(ONE_MINUS_EPSILON))
+    }
+}
+
+/// Kensler's hashed permutation: maps `index` in [0, length) to a unique position in [0, length),
+/// a different permutation for every `scramble`.
+fn permute(mut index: u32, length: u32, scramble: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // Permutes within the next power of two and retries until the result falls inside the range
+    loop {
+        index ^= scramble;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= scramble >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= scramble >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= scramble >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | scramble >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+
+        if index < length {
+            return (index + scramble) % length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn permute_should_be_a_permutation() {
+        for &length in &[1, 5, 16, 33] {
+            let mut positions: Vec<u32> = (0..length).map(|i| permute(i, length, 0x1234_5678)).collect();
+            positions.sort();
+            assert_eq!(positions, (0..length).collect::<Vec<u32>>());
+        }
+    }
+
+    #[test]
+    fn every_sample_should_get_its_own_cell() {
+        let samples = 10;
+        let mut cells: Vec<(u32, u32)> = (0..samples)
+            .map(|i| {
+                let mut sampler = StratifiedSampler::new(42, i, samples, StdRng::seed_from_u64(i as u64));
+                let position = sampler.get_2d();
+                // 10 samples use a 4 x 3 grid
+                ((position.x * 4.0) as u32, (position.y * 3.0) as u32)
+            })
+            .collect();
+        cells.sort();
+        cells.dedup();
+
+        assert_eq!(cells.len(), samples as usize);
+    }
+}
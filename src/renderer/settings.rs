@@ -0,0 +1,62 @@
+use crate::renderer::tiles::TileOrder;
+use crate::renderer::filter::{Filter, FilterType};
+use crate::renderer::adaptive::AdaptiveSampling;
+use crate::integrator::termination::PathTermination;
+use crate::renderer::aov::AovType;
+use crate::renderer::denoise::Denoiser;
+use crate::renderer::display::DisplayTransform;
+use crate::renderer::sampler::SamplerType;
+
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub samples: u32,
+    pub thread_count: usize,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub filter: Filter,
+    /// When set, pixels stop receiving samples once their noise estimate is low enough.
+    pub adaptive: Option<AdaptiveSampling>,
+    pub path_termination: PathTermination,
+    /// Extra passes to render next to the beauty image.
+    pub aovs: Vec<AovType>,
+    /// Set to denoise the image once it is rendered, the renderer then also collects the guide passes.
+    pub denoiser: Option<Denoiser>,
+    /// How the finished image is shown in the viewer and written to 8 bit files.
+    pub display: DisplayTransform,
+    /// Random streams are derived from the seed, the frame and the sample, renders with the same seed are identical.
+    pub seed: u64,
+    /// The animation frame being rendered, so consecutive frames don't share the same noise.
+    pub frame: u32,
+    /// How the random numbers of a pixel's samples are spread out.
+    pub sampler: SamplerType,
+    /// Trace wavelengths instead of RGB, so dispersive materials split light into colors.
+    /// Only the path tracer renders spectrally, the other integrators ignore it.
+    pub spectral: bool,
+}
+
+impl RenderSettings {
+    pub fn new() -> Self {
+        RenderSettings {
+            samples: 16,
+            thread_count: available_cores(),
+            tile_size: 32,
+            tile_order: TileOrder::Hilbert,
+            filter: Filter::new(FilterType::Box, FilterType::Box.default_radius()),
+            adaptive: None,
+            path_termination: PathTermination::new(),
+            aovs: Vec::new(),
+            denoiser: None,
+            display: DisplayTransform::new(),
+            seed: 0,
+            frame: 0,
+            sampler: SamplerType::Sobol,
+            spectral: false,
+        }
+    }
+}
+
+pub fn available_cores() -> usize {
+    std::thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1)
+}
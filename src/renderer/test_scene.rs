@@ -0,0 +1,41 @@
+use crate::scene::{Scene, SceneEntity};
+use crate::scene::octree_scene::Octree;
+use crate::scene::sphere_entity::SphereEntity;
+use crate::scene::plane_entity::PlaneEntity;
+use crate::scene::transform_builder::TransformBuilder;
+use crate::content::material_builder::MaterialBuilder;
+use crate::core::plane::Plane;
+use crate::camera::Camera;
+use std::sync::Arc;
+
+/// A diffuse floor lit by an emissive sphere above it, small enough to render in tests.
+pub fn create_test_scene() -> Arc<dyn Scene + Sync + Send> {
+    let entities: Vec<Box<dyn SceneEntity + Sync + Send>> = vec![
+        Box::new(PlaneEntity::new(
+            1,
+            Plane::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
+            10.0,
+            MaterialBuilder::new().with_diffuse_color(glm::vec3(0.8, 0.8, 0.8)).build(),
+            TransformBuilder::new().build(),
+        )),
+        Box::new(SphereEntity::new(
+            2,
+            1.0,
+            MaterialBuilder::new().with_emissive_color(glm::vec3(1.0, 1.0, 1.0)).build(),
+            TransformBuilder::new().with_translation(glm::vec3(0.0, 2.0, 0.0)).build(),
+        )),
+    ];
+
+    Arc::new(Octree::create(entities, 4))
+}
+
+/// A camera looking at the sphere of `create_test_scene`.
+pub fn create_test_camera(resolution: &glm::Vector2<u32>) -> Camera {
+    let mut camera = Camera::new();
+    camera.set_position(glm::vec3(0.0, 2.0, -8.0));
+    camera.set_direction(glm::vec3(0.0, -0.2, 1.0));
+    camera.set_resolution(*resolution);
+    camera.update();
+
+    camera
+}
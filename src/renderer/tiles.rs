@@ -0,0 +1,198 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Tile {
+    pub index: usize,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TileOrder {
+    /// Row by row, starting at the first scanline.
+    Scanline,
+    /// Outwards from the center of the image, so the interesting part shows up first.
+    Spiral,
+    /// Along a Hilbert curve, consecutive tiles are always neighbours which keeps caches warm.
+    Hilbert,
+}
+
+impl TileOrder {
+    pub fn from_name(name: &str) -> Option<TileOrder> {
+        match name {
+            "scanline" => Some(TileOrder::Scanline),
+            "spiral" => Some(TileOrder::Spiral),
+            "hilbert" => Some(TileOrder::Hilbert),
+            _ => None,
+        }
+    }
+}
+
+/// Splits the image into tiles of (at most) `tile_size` x `tile_size` pixels, sorted in the requested order.
+/// The `index` of each tile is its position in the returned list.
+pub fn create_tiles(width: usize, height: usize, tile_size: usize, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let tiles_x = (width + tile_size - 1) / tile_size;
+    let tiles_y = (height + tile_size - 1) / tile_size;
+
+    let mut grid_positions = Vec::with_capacity(tiles_x * tiles_y);
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            grid_positions.push((tx, ty));
+        }
+    }
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let center_x = (tiles_x as f32 - 1.0) / 2.0;
+            let center_y = (tiles_y as f32 - 1.0) / 2.0;
+            let ring_and_angle = |position: &(usize, usize)| {
+                let dx = position.0 as f32 - center_x;
+                let dy = position.1 as f32 - center_y;
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+
+            grid_positions.sort_by(|a, b| ring_and_angle(a).partial_cmp(&ring_and_angle(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let curve_size = tiles_x.max(tiles_y).next_power_of_two();
+            grid_positions.sort_by_key(|position| hilbert_index(curve_size, position.0, position.1));
+        }
+    }
+
+    grid_positions.into_iter().enumerate().map(|(index, (tx, ty))| {
+        let x = tx * tile_size;
+        let y = ty * tile_size;
+        Tile {
+            index,
+            x,
+            y,
+            width: tile_size.min(width - x),
+            height: tile_size.min(height - y),
+        }
+    }).collect()
+}
+
+// Distance along a Hilbert curve filling a `size` x `size` grid, size must be a power of two.
+fn hilbert_index(size: usize, x: usize, y: usize) -> usize {
+    let mut x = x;
+    let mut y = y;
+    let mut index = 0;
+    let mut s = size / 2;
+
+    while s > 0 {
+        let rx = if (x & s) > 0 { 1 } else { 0 };
+        let ry = if (y & s) > 0 { 1 } else { 0 };
+        index += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant so the curve inside it has the right orientation
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        s /= 2;
+    }
+
+    index
+}
+
+/// Hands out tiles to worker threads, each tile is handed out exactly once.
+#[derive(Clone)]
+pub struct TileProducer {
+    next_tile: Arc<AtomicUsize>,
+    tiles: Arc<Vec<Tile>>,
+}
+
+impl TileProducer {
+    pub fn new(tiles: Vec<Tile>) -> Self {
+        TileProducer {
+            next_tile: Arc::new(AtomicUsize::new(0)),
+            tiles: Arc::new(tiles),
+        }
+    }
+
+    pub fn next(&self) -> Option<Tile> {
+        let next = self.next_tile.fetch_add(1, Ordering::SeqCst);
+
+        self.tiles.get(next).copied()
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_covers_every_pixel_once(width: usize, height: usize, tiles: &Vec<Tile>) {
+        let mut coverage = vec![0; width * height];
+        for tile in tiles {
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    coverage[y * width + x] += 1;
+                }
+            }
+        }
+
+        assert!(coverage.iter().all(|x| *x == 1));
+    }
+
+    #[test]
+    fn create_tiles_should_cover_every_pixel_once() {
+        for order in &[TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = create_tiles(100, 37, 16, *order);
+
+            assert_eq!(tiles.len(), 7 * 3);
+            assert_covers_every_pixel_once(100, 37, &tiles);
+        }
+    }
+
+    #[test]
+    fn create_tiles_should_number_tiles_in_order() {
+        let tiles = create_tiles(64, 64, 16, TileOrder::Hilbert);
+
+        for (index, tile) in tiles.iter().enumerate() {
+            assert_eq!(tile.index, index);
+        }
+    }
+
+    #[test]
+    fn spiral_order_should_start_in_the_center() {
+        let tiles = create_tiles(48, 48, 16, TileOrder::Spiral);
+
+        assert_eq!((tiles[0].x, tiles[0].y), (16, 16));
+    }
+
+    #[test]
+    fn hilbert_order_should_only_step_to_neighbours() {
+        let tiles = create_tiles(128, 128, 16, TileOrder::Hilbert);
+
+        for pair in tiles.windows(2) {
+            let distance = (pair[0].x as i32 - pair[1].x as i32).abs() + (pair[0].y as i32 - pair[1].y as i32).abs();
+            assert_eq!(distance, 16);
+        }
+    }
+
+    #[test]
+    fn tile_producer_should_hand_out_each_tile_once() {
+        let producer = TileProducer::new(create_tiles(32, 32, 16, TileOrder::Scanline));
+
+        let mut handed_out = Vec::new();
+        while let Some(tile) = producer.next() {
+            handed_out.push(tile.index);
+        }
+
+        assert_eq!(handed_out, vec![0, 1, 2, 3]);
+        assert!(producer.next().is_none());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::scene::transform::Transform;
+use crate::core::geom::AABB;
+use std::borrow::Cow;
+
+/// Where an entity is while the shutter is open, interpolated between the transform at shutter open (time 0)
+/// and the one at shutter close (time 1). Entities that don't move keep a single transform.
+pub struct AnimatedTransform {
+    start: Transform,
+    end: Option<Transform>,
+}
+
+impl AnimatedTransform {
+    // Rotation can sweep an entity outside the bounds at both ends of the interval,
+    // so the bounds are taken over this many steps.
+    const BOUNDS_STEPS: usize = 8;
+
+    pub fn new(start: Transform, end: Transform) -> Self {
+        let end = if start.is_same_as(&end) { None } else { Some(end) };
+
+        AnimatedTransform {
+            start,
+            end,
+        }
+    }
+
+    pub fn fixed(transform: Transform) -> Self {
+        AnimatedTransform {
+            start: transform,
+            end: None,
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// The transform when the shutter opens.
+    #[allow(dead_code)]
+    pub fn start(&self) -> &Transform {
+        &self.start
+    }
+
+    pub fn at(&self, time: f32) -> Cow<'_, Transform> {
+        match &self.end {
+            Some(end) => Cow::Owned(self.start.lerp(end, time)),
+            None => Cow::Borrowed(&self.start),
+        }
+    }
+
+    /// World space bounds covering `object_bounds` during the whole shutter interval.
+    pub fn bounds(&self, object_bounds: &AABB) -> AABB {
+        let mut result = object_bounds.transform(self.start.world());
+        if self.is_animated() {
+            for step in 1..=Self::BOUNDS_STEPS {
+                let time = step as f32 / Self::BOUNDS_STEPS as f32;
+                result.expand(&object_bounds.transform(self.at(time).world()));
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::transform_builder::TransformBuilder;
+
+    #[test]
+    fn new_should_not_animate_identical_transforms() {
+        let transform = AnimatedTransform::new(
+            TransformBuilder::new().with_translation(glm::vec3(1.0, 0.0, 0.0)).build(),
+            TransformBuilder::new().with_translation(glm::vec3(1.0, 0.0, 0.0)).build());
+
+        assert!(!transform.is_animated());
+    }
+
+    #[test]
+    fn bounds_should_cover_the_whole_motion() {
+        let transform = AnimatedTransform::new(
+            TransformBuilder::new().build(),
+            TransformBuilder::new().with_translation(glm::vec3(10.0, 0.0, 0.0)).build());
+        let object_bounds = AABB { min: glm::vec3(-1.0, -1.0, -1.0), max: glm::vec3(1.0, 1.0, 1.0) };
+
+        let result = transform.bounds(&object_bounds);
+
+        assert_eq!(result.min, glm::vec3(-1.0, -1.0, -1.0));
+        assert_eq!(result.max, glm::vec3(11.0, 1.0, 1.0));
+        assert_eq!(transform.at(0.5).world().c3, glm::vec4(5.0, 0.0, 0.0, 1.0));
+    }
+}
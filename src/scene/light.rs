@@ -0,0 +1,166 @@
+/// A light that is not part of the scene geometry. Rays never hit it, it only lights surfaces
+/// that ask it for the light it sends their way.
+pub trait Light {
+    /// The light arriving at `point`, None when the light sends nothing that way.
+    fn sample(&self, point: &glm::Vec3) -> Option<LightSample>;
+}
+
+pub struct LightSample {
+    /// Unit direction from the point towards the light.
+    pub direction: glm::Vec3,
+    /// How far away the light is, `f32::MAX` for lights infinitely far away.
+    pub distance: f32,
+    /// Irradiance on a surface facing the light, a surface at an angle gets this times the cosine.
+    pub irradiance: glm::Vec3,
+}
+
+/// Shines equally in all directions from a single point.
+pub struct PointLight {
+    position: glm::Vec3,
+    intensity: glm::Vec3,
+}
+
+impl PointLight {
+    /// `intensity` is the light sent per unit of solid angle.
+    pub fn new(position: glm::Vec3, intensity: glm::Vec3) -> Self {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: &glm::Vec3) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance = glm::length(to_light);
+        if distance <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            irradiance: self.intensity / (distance * distance),
+        })
+    }
+}
+
+/// A point light that only shines into a cone around `direction`. Full intensity inside `inner_angle`,
+/// fading out smoothly until `outer_angle`, both measured from the center of the cone.
+pub struct SpotLight {
+    light: PointLight,
+    direction: glm::Vec3,
+    cos_inner_angle: f32,
+    cos_outer_angle: f32,
+}
+
+impl SpotLight {
+    /// Angles are in radians.
+    pub fn new(position: glm::Vec3, direction: glm::Vec3, intensity: glm::Vec3, inner_angle: f32, outer_angle: f32) -> Self {
+        SpotLight {
+            light: PointLight::new(position, intensity),
+            direction: glm::normalize(direction),
+            cos_inner_angle: inner_angle.min(outer_angle).cos(),
+            cos_outer_angle: outer_angle.cos(),
+        }
+    }
+
+    fn falloff(&self, cos_angle: f32) -> f32 {
+        if cos_angle >= self.cos_inner_angle {
+            return 1.0;
+        }
+        if cos_angle <= self.cos_outer_angle {
+            return 0.0;
+        }
+
+        let t = (cos_angle - self.cos_outer_angle) / (self.cos_inner_angle - self.cos_outer_angle);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: &glm::Vec3) -> Option<LightSample> {
+        let sample = self.light.sample(point)?;
+        let falloff = self.falloff(glm::dot(sample.direction * -1.0, self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            irradiance: sample.irradiance * falloff,
+            ..sample
+        })
+    }
+}
+
+/// Parallel light from infinitely far away, like sunlight.
+pub struct DirectionalLight {
+    direction: glm::Vec3,
+    irradiance: glm::Vec3,
+}
+
+impl DirectionalLight {
+    /// `direction` is the way the light travels.
+    pub fn new(direction: glm::Vec3, irradiance: glm::Vec3) -> Self {
+        DirectionalLight {
+            direction: glm::normalize(direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: &glm::Vec3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.direction * -1.0,
+            distance: f32::MAX,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn point_light_should_fall_off_with_the_square_of_the_distance() {
+        let light = PointLight::new(glm::vec3(0.0, 4.0, 0.0), glm::vec3(16.0, 32.0, 8.0));
+
+        let sample = light.sample(&glm::vec3(0.0, 0.0, 0.0)).unwrap();
+
+        assert_eq!(sample.direction, glm::vec3(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, 4.0);
+        assert_eq!(sample.irradiance, glm::vec3(1.0, 2.0, 0.5));
+    }
+
+    #[test]
+    fn spot_light_should_only_light_its_cone() {
+        let light = SpotLight::new(glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, -1.0, 0.0), glm::vec3(1.0, 1.0, 1.0),
+                                   20f32.to_radians(), 40f32.to_radians());
+        let at_angle = |degrees: f32| {
+            let point = glm::vec3(degrees.to_radians().tan(), 0.0, 0.0);
+            light.sample(&point).map(|x| x.irradiance.x * glm::dot(x.direction, point - glm::vec3(0.0, 1.0, 0.0)).powi(2))
+        };
+
+        // Irradiance times squared distance is the intensity the spot sends that way
+        assert!((at_angle(0.0).unwrap() - 1.0).abs() < 1e-5);
+        assert!((at_angle(19.0).unwrap() - 1.0).abs() < 1e-5);
+        let halfway = at_angle(30.0).unwrap();
+        assert!(halfway > 0.0 && halfway < 1.0);
+        assert!(at_angle(41.0).is_none());
+    }
+
+    #[test]
+    fn directional_light_should_be_the_same_everywhere() {
+        let light = DirectionalLight::new(glm::vec3(0.0, -2.0, 0.0), glm::vec3(3.0, 3.0, 3.0));
+
+        for point in &[glm::vec3(0.0, 0.0, 0.0), glm::vec3(100.0, -5.0, 7.0)] {
+            let sample = light.sample(point).unwrap();
+            assert_eq!(sample.direction, glm::vec3(0.0, 1.0, 0.0));
+            assert_eq!(sample.distance, f32::MAX);
+            assert_eq!(sample.irradiance, glm::vec3(3.0, 3.0, 3.0));
+        }
+    }
+}
@@ -6,15 +6,21 @@ use num_traits::One;
 use std::rc::Rc;
 use glm::ext::rotate;
 use crate::content::material::Material;
-use rand::rngs::StdRng;
 use crate::scene::transform::Transform;
+use crate::renderer::sampler::Sampler;
+use crate::content::medium::Medium;
+use crate::content::environment_map::EnvironmentMap;
+use crate::scene::light::Light;
+use std::sync::Arc;
 
 pub mod octree_scene;
 pub mod sphere_entity;
 pub mod mesh_entity;
 pub mod transform;
+pub mod animated_transform;
 pub mod transform_builder;
 pub mod plane_entity;
+pub mod light;
 
 pub trait Intersectable {
     fn intersect<'a >(&'a self, world_ray: &Ray) -> Option<Box<dyn Intersection + 'a>>;
@@ -32,7 +38,8 @@ pub struct SurfaceDescription {
 
 pub trait Renderable {
     fn is_emissive(&self) -> bool;
-    fn get_random_emissive_surface(&self, rng: &mut StdRng) -> SurfaceDescription;
+    /// A random point on the surface at `time` within the shutter interval.
+    fn get_random_emissive_surface(&self, time: f32, sampler: &mut dyn Sampler) -> SurfaceDescription;
     // fn get_random_emissive_surface(&self, rng: &mut StdRng) -> Box<dyn Intersection + '_>;
 }
 
@@ -114,4 +121,10 @@ pub trait Scene {
     fn find_intersection(&self, ray: &crate::core::Ray) -> Option<Box<dyn Intersection + '_>>;
     // fn get_random_emissive_surface(&self, rng: &mut StdRng) -> Box<dyn Intersection + '_>;
     fn get_emissive_entities(&self) -> Vec<&Box<dyn SceneEntity+Sync+Send>>;
+    /// The medium filling the space outside of all entities, like fog. None for empty space.
+    fn medium(&self) -> Option<&Arc<Medium>>;
+    /// The light arriving from far away in directions where rays don't hit anything. None for black.
+    fn environment(&self) -> Option<&Arc<EnvironmentMap>>;
+    /// Lights that are not part of the geometry, like point lights.
+    fn lights(&self) -> &[Box<dyn Light + Sync + Send>];
 }
\ No newline at end of file
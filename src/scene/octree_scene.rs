@@ -3,6 +3,10 @@ use crate::core::geom::{AABB, ray_aabb_intersect};
 use crate::core::{Intersection, Ray};
 use crate::scene::{Scene, SceneEntity};
 use rand::rngs::StdRng;
+use crate::content::medium::Medium;
+use crate::content::environment_map::EnvironmentMap;
+use crate::scene::light::Light;
+use std::sync::Arc;
 use rand::Rng;
 
 extern crate test;
@@ -16,6 +20,9 @@ struct EntityId { pub id: usize }
 pub struct Octree {
     entities: Vec<Box<dyn SceneEntity+Sync+Send>>,
     octants: Vec<Octant>,
+    medium: Option<Arc<Medium>>,
+    environment: Option<Arc<EnvironmentMap>>,
+    lights: Vec<Box<dyn Light + Sync + Send>>,
 }
 
 struct Octant {
@@ -43,6 +50,18 @@ impl Scene for Octree {
     fn get_emissive_entities(&self) -> Vec<&Box<dyn SceneEntity+Sync+Send>> {
         self.entities.iter().filter(|x| x.is_emissive()).collect()
     }
+
+    fn medium(&self) -> Option<&Arc<Medium>> {
+        self.medium.as_ref()
+    }
+
+    fn environment(&self) -> Option<&Arc<EnvironmentMap>> {
+        self.environment.as_ref()
+    }
+
+    fn lights(&self) -> &[Box<dyn Light + Sync + Send>] {
+        &self.lights
+    }
 }
 
 impl Octree {
@@ -86,6 +105,23 @@ impl Octree {
         self.trace_octant(ray, &OctantId { id: 0 })
     }
 
+    /// Fills the space around the entities with a medium, like fog.
+    pub fn with_medium(mut self, medium: Option<Arc<Medium>>) -> Octree {
+        self.medium = medium;
+        self
+    }
+
+    /// Surrounds the scene with light from an environment map.
+    pub fn with_environment(mut self, environment: Option<Arc<EnvironmentMap>>) -> Octree {
+        self.environment = environment;
+        self
+    }
+
+    pub fn with_lights(mut self, lights: Vec<Box<dyn Light + Sync + Send>>) -> Octree {
+        self.lights = lights;
+        self
+    }
+
     pub fn create(entities: Vec<Box<dyn SceneEntity+Sync+Send>>, depth_limit: usize) -> Octree {
         // let bounds = AABB::from_bounds(&entities.iter().map(|x| x.model.bounds().transform(&glm::inverse(&x.inverse_transform))));
         let bounds = AABB::from_bounds(&entities.iter().map(|x| x.bounds().clone()));
@@ -99,6 +135,9 @@ impl Octree {
         let mut tree = Octree {
             entities,
             octants: vec![root],
+            medium: None,
+            environment: None,
+            lights: Vec::new(),
         };
 
 
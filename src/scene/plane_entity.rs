@@ -3,11 +3,10 @@ use crate::content::material::Material;
 use crate::scene::transform::Transform;
 use float_cmp::{ApproxEq, F32Margin};
 use crate::scene::{SceneEntity, Renderable, SurfaceDescription, Intersectable};
-use rand::prelude::StdRng;
+use crate::renderer::sampler::Sampler;
 use crate::core::{Intersection, Ray};
 use num_traits::Zero;
 use crate::core::plane::Plane;
-use rand::Rng;
 use glm::{Vec2, Vec3, Vec4};
 
 pub struct PlaneEntity {
@@ -40,10 +39,11 @@ impl Renderable for PlaneEntity {
         !self.material.emission().is_zero()
     }
 
-    fn get_random_emissive_surface(&self, rng: &mut StdRng) -> SurfaceDescription {
+    fn get_random_emissive_surface(&self, _time: f32, sampler: &mut dyn Sampler) -> SurfaceDescription {
+        let u = sampler.get_2d();
         let coordinate = self.plane.origin() +
-            (self.plane.v() * self.extent * ((rng.gen::<f32>() * 2.0) - 1.0)) +
-            (self.plane.u() * self.extent * ((rng.gen::<f32>() * 2.0) - 1.0));
+            (self.plane.v() * self.extent * ((u.x * 2.0) - 1.0)) +
+            (self.plane.u() * self.extent * ((u.y * 2.0) - 1.0));
         SurfaceDescription
         {
             coordinate,
@@ -66,6 +66,9 @@ impl Intersectable for PlaneEntity {
 
         let x = self.plane.origin() - world_ray.origin;
         let t = glm::dot(x, self.plane.normal()) / denominator;
+        if t < world_ray.t_min || t > world_ray.t_max {
+            return None; // The plane is behind the ray or outside its range
+        }
 
         Some(Box::new(PlaneIntersection {
             world_space_normal: self.plane.normal(),
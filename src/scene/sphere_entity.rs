@@ -4,16 +4,15 @@ use crate::core::geom::AABB;
 use glm::{Vec2, Vec3};
 use crate::content::material::Material;
 use num_traits::{Zero, One};
-use rand::rngs::StdRng;
-use rand::Rng;
+use crate::renderer::sampler::Sampler;
 use crate::scene::transform::Transform;
+use crate::scene::animated_transform::AnimatedTransform;
 
 pub struct SphereIntersection<'a> {
     entity_id: u32,
     material: &'a Material,
-    transform: &'a Transform,
     world_space_hit_point: glm::Vec3,
-    object_space_normal: glm::Vec4,
+    world_space_normal: glm::Vec3,
     distance: f32,
 }
 
@@ -25,8 +24,7 @@ impl Intersection for SphereIntersection<'_> {
     }
 
     fn world_space_normal(&self) -> Vec3 {
-        let tmp = *self.transform.world() * self.object_space_normal;
-        glm::vec3(tmp.x, tmp.y, tmp.z)
+        self.world_space_normal
     }
 
     fn texture_coordinates(&self) -> Vec2 {
@@ -64,15 +62,20 @@ pub struct SphereEntity {
     squared_radius: f32,
     bounds: AABB,
     material: Material,
-    transform: Transform,
+    transform: AnimatedTransform,
 }
 
 impl SphereEntity {
-    pub fn new(id: u32, radius: f32, material: Material, mut transform: Transform) -> Self {
-        let bounds = AABB {
+    pub fn new(id: u32, radius: f32, material: Material, transform: Transform) -> Self {
+        Self::new_animated(id, radius, material, AnimatedTransform::fixed(transform))
+    }
+
+    /// A sphere that moves while the shutter is open, rays are intersected with it where it is at their time.
+    pub fn new_animated(id: u32, radius: f32, material: Material, transform: AnimatedTransform) -> Self {
+        let bounds = transform.bounds(&AABB {
             min: glm::vec3(-radius, -radius, -radius),
             max: glm::vec3(radius, radius, radius),
-        }.transform(transform.world());
+        });
 
 
         SphereEntity {
@@ -117,11 +120,13 @@ impl SphereEntity {
             std::mem::swap(&mut t0, &mut t1);
         }
 
-        if t0 < 0.0 {
+        // Take the nearest hit inside the range of the ray, the far one if the near one is too close
+        if t0 < object_space_ray.t_min {
             t0 = t1;
-            if t0 < 0.0 {
-                return None;
-            }
+        }
+
+        if t0 < object_space_ray.t_min || t0 > object_space_ray.t_max {
+            return None;
         }
 
         return Some(t0);
@@ -135,14 +140,15 @@ impl Renderable for SphereEntity {
         !self.material.emission().is_zero()
     }
 
-    fn get_random_emissive_surface(&self, rng: &mut StdRng) -> SurfaceDescription {
+    fn get_random_emissive_surface(&self, time: f32, sampler: &mut dyn Sampler) -> SurfaceDescription {
+        let transform = self.transform.at(time);
         let point_on_unit_sphere = glm::vec3(
-            rng.gen::<f32>() * 2.0 - 1.0,
-            rng.gen::<f32>() * 2.0 - 1.0,
-            rng.gen::<f32>() * 2.0 - 1.0);
-        let surface_normal = *self.transform.world() * point_on_unit_sphere.extend(0.0);
+            sampler.get_1d() * 2.0 - 1.0,
+            sampler.get_1d() * 2.0 - 1.0,
+            sampler.get_1d() * 2.0 - 1.0);
+        let surface_normal = *transform.world() * point_on_unit_sphere.extend(0.0);
 
-        let coordinate = *self.transform.world() * (point_on_unit_sphere * self.radius).extend(1.0);
+        let coordinate = *transform.world() * (point_on_unit_sphere * self.radius).extend(1.0);
 
         SurfaceDescription {
             emission: *self.material.emission(),
@@ -169,20 +175,21 @@ impl Renderable for SphereEntity {
 
 impl Intersectable for SphereEntity {
     fn intersect(&self, world_ray: &Ray) -> Option<Box<dyn Intersection + '_>> {
-        let object_ray = world_ray.transform(&self.transform.inverse_world());
+        let transform = self.transform.at(world_ray.time);
+        let object_ray = world_ray.transform(transform.inverse_world());
 
         if let Some(object_space_distance) = self.intersect_object_space_ray(&object_ray) {
             let object_space_hit_point = object_ray.origin + (object_ray.direction * object_space_distance);
-            let tmp = *self.transform.world() * glm::vec4(object_space_hit_point.x, object_space_hit_point.y, object_space_hit_point.z, 1.0);
+            let tmp = *transform.world() * glm::vec4(object_space_hit_point.x, object_space_hit_point.y, object_space_hit_point.z, 1.0);
             let world_space_hit_point = glm::vec3(tmp.x, tmp.y, tmp.z);
             let world_space_distance = glm::length(world_space_hit_point - world_ray.origin);
+            let normal = *transform.world() * object_space_hit_point.extend(0.0);
 
             return Some(Box::new(SphereIntersection {
                 entity_id: self.entity_id,
                 material: &self.material,
-                transform: &self.transform,
                 world_space_hit_point: glm::vec3(world_space_hit_point.x, world_space_hit_point.y, world_space_hit_point.z),
-                object_space_normal: glm::vec4(object_space_hit_point.x, object_space_hit_point.y, object_space_hit_point.z, 0.0),
+                world_space_normal: normal.truncate(3),
                 distance: world_space_distance,
             }));
         }
@@ -198,7 +205,7 @@ impl Intersectable for SphereEntity {
         self.entity_id
     }
 
-    fn transform(&self) -> &Transform { &self.transform }
+    fn transform(&self) -> &Transform { self.transform.start() }
 }
 
 #[cfg(test)]
@@ -213,10 +220,10 @@ mod tests {
     fn intersect_object_space_ray_simple_intersection() {
         let sphere = SphereEntity::new(0, 1.0, MaterialBuilder::new().build(), TransformBuilder::new().build());
 
-        let ray = Ray {
-            origin: glm::vec3(0.0, 0.0, 2.0),
-            direction: glm::vec3(0.0, 0.0, -1.0),
-        };
+        let ray = Ray::new(
+            glm::vec3(0.0, 0.0, 2.0),
+            glm::vec3(0.0, 0.0, -1.0),
+        );
 
         let result = sphere.intersect_object_space_ray(&ray);
 
@@ -227,10 +234,10 @@ mod tests {
     fn intersect_object_space_ray_should_not_intersect_behind_ray() {
         let sphere = SphereEntity::new(0, 1.0, MaterialBuilder::new().build(), TransformBuilder::new().build());
 
-        let ray = Ray {
-            origin: glm::vec3(0.0, 0.0, 2.0),
-            direction: glm::vec3(0.0, 0.0, 1.0),
-        };
+        let ray = Ray::new(
+            glm::vec3(0.0, 0.0, 2.0),
+            glm::vec3(0.0, 0.0, 1.0),
+        );
 
         let result = sphere.intersect_object_space_ray(&ray);
 
@@ -241,12 +248,35 @@ mod tests {
     fn intersect_object_space_ray_should_handle_rays_originating_inside_sphere() {
         let sphere = SphereEntity::new(0, 1.0, MaterialBuilder::new().build(), TransformBuilder::new().build());
 
-        let ray = Ray {
-            origin: glm::vec3(0.0, 0.0, 0.9),
-            direction: glm::vec3(0.0, 0.0, 1.0),
-        };
+        let ray = Ray::new(
+            glm::vec3(0.0, 0.0, 0.9),
+            glm::vec3(0.0, 0.0, 1.0),
+        );
 
         let result = sphere.intersect_object_space_ray(&ray);
         assert!(result.unwrap().approx_eq(0.1, F32Margin { ulps: 2, epsilon: std::f32::EPSILON }))
     }
+
+    #[test]
+    fn intersect_object_space_ray_should_respect_ray_range() {
+        let sphere = SphereEntity::new(0, 1.0, MaterialBuilder::new().build(), TransformBuilder::new().build());
+
+        let mut ray = Ray::new(glm::vec3(0.0, 0.0, 2.0), glm::vec3(0.0, 0.0, -1.0));
+        ray.t_min = 1.5;
+        assert_eq!(sphere.intersect_object_space_ray(&ray).unwrap(), 3.0);
+
+        let ray = Ray::new(glm::vec3(0.0, 0.0, 2.0), glm::vec3(0.0, 0.0, -1.0)).with_max_distance(0.5);
+        assert!(sphere.intersect_object_space_ray(&ray).is_none());
+    }
+
+    #[test]
+    fn intersect_should_use_world_space_range_on_scaled_spheres() {
+        let sphere = SphereEntity::new(0, 1.0, MaterialBuilder::new().build(), TransformBuilder::new().with_scale(glm::vec3(0.5, 0.5, 0.5)).build());
+
+        let ray = Ray::new(glm::vec3(0.0, 0.0, 2.0), glm::vec3(0.0, 0.0, -1.0));
+        assert!(sphere.intersect(&ray.with_max_distance(1.4)).is_none());
+
+        let ray = Ray::new(glm::vec3(0.0, 0.0, 2.0), glm::vec3(0.0, 0.0, -1.0));
+        assert!(sphere.intersect(&ray.with_max_distance(1.6)).is_some());
+    }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use num_traits::One;
 
+#[derive(Clone)]
 pub struct Transform {
     translation: glm::Vec3,
     rotation: glm::Vec3,
@@ -46,6 +47,23 @@ impl Transform {
         &self.inverse_world
     }
 
+    /// Interpolates translation, rotation and scale separately, so a spinning entity turns instead of shrinking.
+    pub fn lerp(&self, other: &Transform, factor: f32) -> Transform {
+        let mix = |a: &glm::Vec3, b: &glm::Vec3| *a + (*b - *a) * factor;
+
+        let mut result = Transform::new();
+        result.translation = mix(&self.translation, &other.translation);
+        result.rotation = mix(&self.rotation, &other.rotation);
+        result.scale = mix(&self.scale, &other.scale);
+        result.build_transform();
+
+        result
+    }
+
+    pub fn is_same_as(&self, other: &Transform) -> bool {
+        self.translation == other.translation && self.rotation == other.rotation && self.scale == other.scale
+    }
+
     fn build_transform(&mut self) {
         let mut transform = glm::ext::translate(&glm::Mat4::one(), self.translation);
         transform = glm::ext::rotate(&transform, self.rotation.z, glm::vec3(0.0, 0.0, 1.0));
@@ -97,6 +115,21 @@ mod tests {
         assert_eq!(result.c3, glm::vec4(100.0, 200.0, 300.0, 1.0));
     }
 
+    #[test]
+    fn lerp_should_interpolate_components() {
+        let mut start = Transform::new();
+        start.set_translation(glm::vec3(0.0, 2.0, 0.0));
+        let mut end = Transform::new();
+        end.set_translation(glm::vec3(4.0, 2.0, 0.0));
+        end.set_scale(glm::vec3(3.0, 3.0, 3.0));
+
+        let result = start.lerp(&end, 0.5);
+
+        assert_eq!(*result.translation(), glm::vec3(2.0, 2.0, 0.0));
+        assert_eq!(*result.scale(), glm::vec3(2.0, 2.0, 2.0));
+        assert_eq!(result.world().c3, glm::vec4(2.0, 2.0, 0.0, 1.0));
+    }
+
     #[test]
     fn new_transform_should_equal_identity() {
         let mut transform = Transform::new();